@@ -0,0 +1,132 @@
+//! The `build` subcommand.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use iowa_compiler::aot::build;
+
+/// The file name of the runtime support library.
+const RUNTIME: &str = "libiowa_rt.a";
+
+/// The system libraries the runtime support library needs.
+#[cfg(target_os = "macos")]
+const SYSTEM_LIBS: &[&str] = &["-lSystem", "-lc", "-lm"];
+#[cfg(not(target_os = "macos"))]
+const SYSTEM_LIBS: &[&str] = &[
+    "-lgcc_s",
+    "-lutil",
+    "-lrt",
+    "-lpthread",
+    "-lm",
+    "-ldl",
+    "-lc",
+];
+
+/// What the subcommand writes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Emit {
+    /// An executable linked with the runtime support library.
+    Exe,
+    /// The object file only.
+    Obj,
+}
+
+/// Compile the file ahead of time to an executable.
+///
+/// The object file is linked with the C compiler in `CC`, or `cc`, and the runtime support
+/// library, which is looked up in `IOWA_RUNTIME` and then next to the `iowa` executable.
+///
+/// The executable still runs the program with the runtime, see the limits of
+/// [`iowa_compiler::aot`].
+pub(crate) fn run(args: &[String]) -> Result<ExitCode, String> {
+    let mut emit = Emit::Exe;
+    let mut output = None;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = match arg.split_once('=') {
+            Some(("--emit", value)) => Some(value),
+            _ if arg == "--emit" => Some(args.next().map_or("", String::as_str)),
+            _ => None,
+        };
+
+        match value {
+            Some("exe") => emit = Emit::Exe,
+            Some("obj") => emit = Emit::Obj,
+            Some(_) => return Err("--emit expects `exe` or `obj`".to_string()),
+            None if arg == "-o" || arg == "--output" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err(format!("{arg} expects a path")),
+            },
+            None if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            None => files.push(arg.as_str()),
+        }
+    }
+
+    let [path] = files[..] else {
+        return Err("build expects a single file".to_string());
+    };
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(path).with_extension("");
+        match emit {
+            Emit::Exe => stem,
+            Emit::Obj => stem.with_extension("o"),
+        }
+    });
+
+    let source =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+    let object = build(path, &source).map_err(|err| format!("{path}:\n{err}"))?;
+
+    if emit == Emit::Obj {
+        return write(&output, &object).map(|()| ExitCode::SUCCESS);
+    }
+
+    let runtime = runtime()?;
+    let object_path = std::env::temp_dir().join(format!("iowa-{}.o", std::process::id()));
+    write(&object_path, &object)?;
+    let res = link(&object_path, &runtime, &output);
+    let _ = std::fs::remove_file(&object_path);
+    res.map(|()| ExitCode::SUCCESS)
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|err| format!("can't write {}: {err}", path.display()))
+}
+
+/// Find the runtime support library.
+fn runtime() -> Result<PathBuf, String> {
+    if let Some(path) = std::env::var_os("IOWA_RUNTIME") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let exe = std::env::current_exe().map_err(|err| format!("can't find iowa: {err}"))?;
+    let path = exe.with_file_name(RUNTIME);
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(format!(
+            "can't find {RUNTIME} next to {}, set IOWA_RUNTIME to its path",
+            exe.display()
+        ))
+    }
+}
+
+fn link(object: &Path, runtime: &Path, output: &Path) -> Result<(), String> {
+    let cc = std::env::var_os("CC").unwrap_or_else(|| "cc".into());
+    let status = Command::new(&cc)
+        .arg(object)
+        .arg(runtime)
+        .arg("-o")
+        .arg(output)
+        .args(SYSTEM_LIBS)
+        .status()
+        .map_err(|err| format!("can't run {}: {err}", cc.to_string_lossy()))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("linking {} failed: {status}", output.display()))
+    }
+}
@@ -0,0 +1,89 @@
+//! The `fmt` subcommand.
+
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use iowa_parser::format::{format, CommentPlacement, FormatOptions};
+
+/// Format the files in place or the standard input to the standard output.
+///
+/// With `--check` nothing is written, the files which aren't formatted are listed and the exit
+/// code is non-zero if there are any.
+pub(crate) fn run(args: &[String]) -> Result<ExitCode, String> {
+    let mut options = FormatOptions::default();
+    let mut check = false;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--indent" => options.indent_width = number_value(arg, args.next())?,
+            "--max-width" => options.max_width = number_value(arg, args.next())?,
+            "--comments" => {
+                options.comments = match args.next().map(String::as_str) {
+                    Some("preserve") => CommentPlacement::Preserve,
+                    Some("own-line") => CommentPlacement::OwnLine,
+                    _ => return Err("--comments expects `preserve` or `own-line`".to_string()),
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{arg}`")),
+            _ => files.push(arg.as_str()),
+        }
+    }
+
+    if files.is_empty() || files == ["-"] {
+        return format_stdin(&options, check);
+    }
+
+    let mut unformatted = false;
+    for path in files {
+        let input =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        let output = format(&input, &options).map_err(|err| format!("{path}:\n{err}"))?;
+
+        if output == input {
+            continue;
+        }
+
+        if check {
+            println!("{path}");
+            unformatted = true;
+        } else {
+            std::fs::write(path, output).map_err(|err| format!("can't write {path}: {err}"))?;
+        }
+    }
+
+    Ok(if unformatted {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn format_stdin(options: &FormatOptions, check: bool) -> Result<ExitCode, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|err| format!("can't read the standard input: {err}"))?;
+    let output = format(&input, options).map_err(|err| err.to_string())?;
+
+    if check {
+        return Ok(if output == input {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    std::io::stdout()
+        .write_all(output.as_bytes())
+        .map_err(|err| format!("can't write the standard output: {err}"))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn number_value(option: &str, value: Option<&String>) -> Result<usize, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{option} expects a number"))
+}
@@ -1,3 +1,68 @@
-fn main() {
-    println!("Hello, world!");
+//! Command line interface for Io programming language.
+
+#![warn(
+    clippy::all,
+    deprecated_in_future,
+    missing_docs,
+    unused_import_braces,
+    unused_labels,
+    unused_lifetimes,
+    unused_qualifications,
+    unreachable_pub
+)]
+
+mod build;
+mod fmt;
+mod parse;
+mod repl;
+mod run;
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    iowa <file> [<arg>...]
+    iowa -e <expr> [<arg>...]
+    iowa build [--emit=<exe|obj>] [-o <output>] <file>
+    iowa fmt [--check] [--indent <width>] [--max-width <width>] [--comments <preserve|own-line>] [<file>...]
+    iowa parse [--emit=<json|bincode|io|bytecode>] [<file>...]
+    iowa [repl]
+
+`iowa build` links the program with libiowa_rt.a from IOWA_RUNTIME or next to iowa. The
+executable embeds the source and runs each instruction through the runtime, so it doesn't run
+faster than `iowa <file>`.";
+
+/// The size of the stack the commands run on, deep enough for the activations the machine allows
+/// before it raises a stack overflow.
+const STACK_SIZE: usize = 1 << 30;
+
+fn main() -> ExitCode {
+    let main = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run);
+    match main {
+        Ok(main) => main.join().unwrap_or(ExitCode::FAILURE),
+        // the stack can't be reserved, run with the default one
+        Err(_) => run(),
+    }
+}
+
+fn run() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let res = match args.first().map(String::as_str) {
+        Some("build") => build::run(&args[1..]),
+        Some("fmt") => fmt::run(&args[1..]),
+        Some("parse") => parse::run(&args[1..]),
+        Some("repl") => repl::run(&args[1..]),
+        None => repl::run(&[]),
+        Some("-e") => run::expression(&args),
+        Some(arg) if arg == "-" || !arg.starts_with('-') => run::script(&args),
+        _ => Err(USAGE.to_string()),
+    };
+
+    res.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        ExitCode::FAILURE
+    })
 }
@@ -0,0 +1,88 @@
+//! The `parse` subcommand.
+
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use iowa_compiler::compile;
+use iowa_parser::{parse, MessageChain};
+
+/// The output format of the tree.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Emit {
+    /// JSON array of the chains.
+    Json,
+    /// Compact binary encoding of the chains.
+    Bincode,
+    /// Io code without the operator and assignment sugar.
+    Io,
+    /// Disassembled bytecode.
+    Bytecode,
+}
+
+/// Parse the files or the standard input and print the trees to the standard output.
+pub(crate) fn run(args: &[String]) -> Result<ExitCode, String> {
+    let mut emit = Emit::Json;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = match arg.split_once('=') {
+            Some(("--emit", value)) => Some(value),
+            _ if arg == "--emit" => Some(args.next().map_or("", String::as_str)),
+            _ => None,
+        };
+
+        match value {
+            Some("json") => emit = Emit::Json,
+            Some("bincode") => emit = Emit::Bincode,
+            Some("io") => emit = Emit::Io,
+            Some("bytecode") => emit = Emit::Bytecode,
+            Some(_) => {
+                return Err("--emit expects `json`, `bincode`, `io` or `bytecode`".to_string())
+            }
+            None if arg.starts_with("--") => return Err(format!("unknown option `{arg}`")),
+            None => files.push(arg.as_str()),
+        }
+    }
+
+    let mut stdout = std::io::stdout().lock();
+
+    if files.is_empty() || files == ["-"] {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|err| format!("can't read the standard input: {err}"))?;
+        let chains = parse(&input).map_err(|err| err.to_string())?;
+        write_chains(&mut stdout, &chains, emit)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for path in files {
+        let input =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        let chains = parse(&input).map_err(|err| format!("{path}:\n{err}"))?;
+        write_chains(&mut stdout, &chains, emit)?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write_chains(
+    out: &mut impl Write,
+    chains: &[MessageChain<'_>],
+    emit: Emit,
+) -> Result<(), String> {
+    let res = match emit {
+        Emit::Json => serde_json::to_writer(&mut *out, chains)
+            .map_err(|err| err.to_string())
+            .and_then(|()| writeln!(out).map_err(|err| err.to_string())),
+        Emit::Bincode => bincode::serialize_into(&mut *out, chains).map_err(|err| err.to_string()),
+        Emit::Io => chains
+            .iter()
+            .try_for_each(|chain| writeln!(out, "{chain}"))
+            .map_err(|err| err.to_string()),
+        Emit::Bytecode => write!(out, "{}", compile(chains)).map_err(|err| err.to_string()),
+    };
+
+    res.map_err(|err| format!("can't write the standard output: {err}"))
+}
@@ -0,0 +1,214 @@
+//! The interactive `repl` subcommand.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use iowa_compiler::Vm;
+use iowa_parser::stream::StreamParser;
+use iowa_parser::{Parser, SymbolId};
+use iowa_runtime::{exit_status, Data, Error, ObjectRef, Stop};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+const PROMPT: &str = "Io> ";
+/// The prompt for the lines of an incomplete chain.
+const CONTINUATION: &str = "... ";
+/// The name of the input in the stack traces.
+const FILE: &str = "<repl>";
+
+/// Read chains from the terminal and print their values, until the end of the input or
+/// `System exit`.
+///
+/// The history is kept in the file in `IOWA_HISTORY`, or `~/.iowa_history`.
+pub(crate) fn run(args: &[String]) -> Result<ExitCode, String> {
+    if let Some(arg) = args.first() {
+        return Err(format!("unknown argument `{arg}`"));
+    }
+
+    let mut vm = Vm::new();
+    let lobby = vm.interpreter().lobby().clone();
+    let mut editor = Editor::<Slots, DefaultHistory>::new()
+        .map_err(|err| format!("can't open the terminal: {err}"))?;
+    editor.set_helper(Some(Slots { lobby }));
+    let history = history();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    let parser = Parser::default();
+    let mut stream = StreamParser::new(&parser);
+    let mut status = 0;
+
+    loop {
+        let prompt = match stream.pending() {
+            Some(_) => CONTINUATION,
+            None => PROMPT,
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                stream.push(&line);
+                stream.push("\n");
+            }
+            Err(ReadlineError::Interrupted) => {
+                stream.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(format!("can't read the input: {err}")),
+        }
+
+        if let Some(exit) = eval(&mut vm, &mut stream) {
+            status = exit;
+            break;
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    Ok(ExitCode::from(exit_status(status)))
+}
+
+/// Evaluate the complete chains, printing their values or the errors. Returns the status of
+/// `System exit`.
+fn eval(vm: &mut Vm, stream: &mut StreamParser<'_>) -> Option<i32> {
+    while let Some(chain) = stream.next_chain() {
+        let res = chain
+            .map_err(Error::from)
+            .and_then(|chain| vm.eval_chains(&[chain]));
+        let res = match res {
+            Ok(value) => vm.interpreter().as_string(&value),
+            Err(Error::Exit(status)) => Err(Stop::Exit(status)),
+            Err(err) => {
+                eprintln!("{}", err.report(FILE));
+                continue;
+            }
+        };
+        let _ = std::io::stdout().flush();
+
+        match res {
+            Ok(value) => println!("==> {value}"),
+            Err(Stop::Exit(status)) => return Some(status),
+            Err(Stop::Error(err)) => eprintln!("{}", err.report(FILE)),
+            // `break`, `continue` or `return` in `asString`, there's no value to print
+            Err(_) => {}
+        }
+    }
+    None
+}
+
+/// The history file.
+fn history() -> Option<PathBuf> {
+    match std::env::var_os("IOWA_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".iowa_history")),
+    }
+}
+
+/// The completion of the slot names.
+struct Slots {
+    lobby: ObjectRef,
+}
+
+impl Completer for Slots {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&self.lobby, &line[..pos]))
+    }
+}
+
+impl Hinter for Slots {
+    type Hint = String;
+}
+
+impl Highlighter for Slots {}
+
+impl Validator for Slots {}
+
+impl Helper for Slots {}
+
+fn is_name(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The start of the name before the cursor and the slot names it can be completed to.
+///
+/// The receiver is the chain of names before it, looked up from the `Lobby` without sending any
+/// message, so only the values of the slots are completed, not the results of the methods.
+fn complete(lobby: &ObjectRef, line: &str) -> (usize, Vec<String>) {
+    let start = line.trim_end_matches(is_name).len();
+    let prefix = &line[start..];
+    let path = line[..start]
+        .trim_end_matches(|c: char| is_name(c) || c == ' ' || c == '\t')
+        .len();
+
+    let mut receiver = lobby.clone();
+    for name in line[path..start].split_whitespace() {
+        let value = match receiver.lookup(SymbolId::intern(name)) {
+            Some((value, _)) => value,
+            None => return (start, Vec::new()),
+        };
+        if matches!(&*value.data(), Data::Block(_) | Data::Native(_)) {
+            return (start, Vec::new());
+        }
+        receiver = value;
+    }
+
+    let mut names = Vec::new();
+    let mut seen = HashSet::new();
+    let mut objects = vec![receiver];
+    while let Some(obj) = objects.pop() {
+        if !seen.insert(obj.id()) {
+            continue;
+        }
+        names.extend(
+            obj.slots()
+                .keys()
+                .map(|name| name.as_str())
+                .filter(|name| name.starts_with(prefix))
+                .map(str::to_string),
+        );
+        objects.extend(obj.protos().iter().rev().cloned());
+    }
+    names.sort_unstable();
+    names.dedup();
+    (start, names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        let mut vm = Vm::new();
+        vm.eval("Foo := Object clone\nFoo bar := 1\nFoo baz := method(2)\nfoo := Foo clone")
+            .unwrap();
+        let lobby = vm.interpreter().lobby().clone();
+
+        assert_eq!(complete(&lobby, "Fo"), (0, vec!["Foo".to_string()]));
+        assert_eq!(
+            complete(&lobby, "x := foo ba"),
+            (9, vec!["bar".to_string(), "baz".to_string()])
+        );
+        let (start, names) = complete(&lobby, "list(Lobby Protos Core Number fl");
+        assert_eq!((start, names), (30, vec!["floor".to_string()]));
+        assert!(complete(&lobby, "foo baz ").1.is_empty());
+        assert!(complete(&lobby, "missing ").1.is_empty());
+        assert!(complete(&lobby, "foo ").1.contains(&"clone".to_string()));
+    }
+}
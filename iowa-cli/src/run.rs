@@ -0,0 +1,70 @@
+//! Running scripts and expressions.
+
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use iowa_compiler::Vm;
+use iowa_runtime::{exit_status, Error};
+
+/// Run the file, or the standard input for `-`, with the rest of the arguments as `System args`.
+pub(crate) fn script(args: &[String]) -> Result<ExitCode, String> {
+    let path = args[0].as_str();
+    let source = if path == "-" {
+        let mut source = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|err| format!("can't read the standard input: {err}"))?;
+        source
+    } else {
+        std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?
+    };
+
+    eval(path, skip_shebang(&source), args)
+}
+
+/// Run the expression after `-e`, with the rest of the arguments as `System args`.
+pub(crate) fn expression(args: &[String]) -> Result<ExitCode, String> {
+    let Some(source) = args.get(1) else {
+        return Err("-e expects an expression".to_string());
+    };
+    let mut rest = vec![args[0].clone()];
+    rest.extend_from_slice(&args[2..]);
+    eval("-e", source, &rest)
+}
+
+/// Blank out a `#!` line, keeping its newline for the line numbers of the errors.
+fn skip_shebang(source: &str) -> &str {
+    if source.starts_with("#!") {
+        &source[source.find('\n').unwrap_or(source.len())..]
+    } else {
+        source
+    }
+}
+
+fn eval(name: &str, source: &str, args: &[String]) -> Result<ExitCode, String> {
+    let mut vm = Vm::new();
+    vm.interpreter().set_args(args);
+    let res = vm.eval(source);
+    let _ = std::io::stdout().flush();
+
+    match res {
+        Ok(_) => Ok(ExitCode::SUCCESS),
+        Err(Error::Exit(status)) => Ok(ExitCode::from(exit_status(status))),
+        Err(err) => Err(err.report(name).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_shebang() {
+        assert_eq!(
+            skip_shebang("#!/usr/bin/env iowa\n1 println"),
+            "\n1 println"
+        );
+        assert_eq!(skip_shebang("#!/usr/bin/env iowa"), "");
+        assert_eq!(skip_shebang("1 println\n#!"), "1 println\n#!");
+    }
+}
@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use iowa_runtime::Interpreter;
+
+/// An output which can be read after it's given to the interpreter.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The standard output, the standard error and the success of the script in the interpreter.
+fn interpret(name: &str, source: &str) -> (String, String, bool) {
+    let output = Output::default();
+    let mut interp = Interpreter::with_output(output.clone());
+    let res = interp.eval(source);
+    let stdout = String::from_utf8_lossy(&output.0.borrow()).to_string();
+    match res {
+        Ok(_) => (stdout, String::new(), true),
+        Err(err) => (stdout, format!("{}\n", err.report(name)), false),
+    }
+}
+
+/// The `iowa` executable, linking the programs with the runtime support library of the tests.
+fn iowa() -> Command {
+    let mut iowa = Command::new(env!("CARGO_BIN_EXE_iowa"));
+    iowa.env("IOWA_RUNTIME", runtime());
+    iowa
+}
+
+/// The static library of `iowa-rt`, which Cargo builds next to the tests for the dev-dependency.
+/// The newest one is built from the current sources.
+fn runtime() -> PathBuf {
+    let deps = std::env::current_exe().unwrap();
+    std::fs::read_dir(deps.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("libiowa_rt-") && name.ends_with(".a")
+        })
+        .max_by_key(|path| path.metadata().unwrap().modified().unwrap())
+        .expect("the runtime support library is built with the tests")
+}
+
+fn build(script: &Path) -> PathBuf {
+    let name = script.file_stem().unwrap();
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let status = iowa()
+        .arg("build")
+        .arg(script)
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{}", script.display());
+    exe
+}
+
+#[test]
+fn test_build_scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut scripts: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "io"))
+        .collect();
+    scripts.sort();
+    assert!(scripts.len() > 1);
+
+    for script in scripts {
+        let source = std::fs::read_to_string(&script).unwrap();
+        let (stdout, stderr, success) = interpret(&script.display().to_string(), &source);
+
+        let exe = build(&script);
+        let output = Command::new(&exe).output().unwrap();
+        let name = script.display();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), stdout, "{name}");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), stderr, "{name}");
+        assert_eq!(output.status.success(), success, "{name}");
+    }
+}
+
+#[test]
+fn test_build_object() {
+    let object = Path::new(env!("CARGO_TARGET_TMPDIR")).join("hello.o");
+    let script = Path::new(env!("CARGO_TARGET_TMPDIR")).join("hello.io");
+    std::fs::write(&script, "\"hello\" println").unwrap();
+
+    let status = iowa()
+        .args(["build", "--emit=obj", "-o"])
+        .arg(&object)
+        .arg(&script)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let bytes = std::fs::read(&object).unwrap();
+    assert!(bytes.len() > 4);
+
+    std::fs::write(&script, "foo(").unwrap();
+    let output = iowa().arg("build").arg(&script).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("hello.io:\nerror"));
+}
+
+#[test]
+fn test_build_args() {
+    let script = Path::new(env!("CARGO_TARGET_TMPDIR")).join("exit.io");
+    std::fs::write(
+        &script,
+        "System args foreach(arg, arg println)\nSystem exit(7)",
+    )
+    .unwrap();
+
+    let exe = build(&script);
+    let output = Command::new(&exe).args(["a", "b"]).output().unwrap();
+    let stdout = format!("{}\na\nb\n", exe.display());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), stdout);
+    assert_eq!(output.status.code(), Some(7));
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn repl(args: &[&str], input: &str, history: &Path) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_iowa"))
+        .args(args)
+        .env("IOWA_HISTORY", history)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_repl() {
+    let history = Path::new(env!("CARGO_TARGET_TMPDIR")).join("repl_history");
+    let _ = std::fs::remove_file(&history);
+    let input = "\
+x := 1 # a comment ends with the line
+double := method(n,
+  n * 2
+)
+double(x + 1)
+foo
+doc := \"\"\"
+two lines\"\"\" size
+x println; x + 1
+";
+
+    let output = repl(&["repl"], input, &history);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "==> 1");
+    assert!(lines[1].starts_with("==> Block_0x"), "{stdout}");
+    assert_eq!(lines[2..], ["==> 4", "==> 10", "1", "==> 1", "==> 2"]);
+    // the session goes on after the error
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Lobby does not respond to 'foo'\n    at foo (<repl>:6:1)\n"
+    );
+    assert!(output.status.success());
+
+    let history = std::fs::read_to_string(&history).unwrap();
+    assert!(history.contains("double(x + 1)\nfoo\n"), "{history}");
+}
+
+#[test]
+fn test_repl_exit() {
+    let history = Path::new(env!("CARGO_TARGET_TMPDIR")).join("repl_exit_history");
+    let output = repl(&[], "1 + 1\nSystem exit(5)\n\"no\" println\n", &history);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "==> 2\n");
+    assert_eq!(output.status.code(), Some(5));
+
+    let output = repl(&[], "list(1,\n", &history);
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_repl_stack_overflow() {
+    let history = Path::new(env!("CARGO_TARGET_TMPDIR")).join("repl_overflow_history");
+    let input = "f := method(n, f(n + 1))\nf(0)\n1 + 1\n";
+    let output = repl(&[], input, &history);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("==> 2\n"), "{stdout}");
+    // the session goes on after the error
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: stack overflow\n"));
+    assert!(output.status.success());
+}
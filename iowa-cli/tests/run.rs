@@ -0,0 +1,134 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn iowa(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_iowa"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_script() {
+    let script = Path::new(env!("CARGO_TARGET_TMPDIR")).join("args.io");
+    std::fs::write(
+        &script,
+        "#!/usr/bin/env iowa\nSystem args foreach(arg, arg println)\nfoo",
+    )
+    .unwrap();
+    let path = script.to_str().unwrap();
+
+    let output = iowa(&[path, "a", "b c"], "");
+    assert_eq!(stdout(&output), format!("{path}\na\nb c\n"));
+    assert_eq!(
+        stderr(&output),
+        format!("error: Lobby does not respond to 'foo'\n    at foo ({path}:3:1)\n")
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = iowa(&["missing.io"], "");
+    assert!(stderr(&output).starts_with("can't read missing.io"));
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_expression() {
+    let output = iowa(&["-e", "System args println", "a"], "");
+    assert_eq!(stdout(&output), "list(-e, a)\n");
+    assert!(output.status.success());
+
+    let output = iowa(&["-e", "foo("], "");
+    assert!(stderr(&output).starts_with("error: unclosed `(`\n --> -e:1:4\n"));
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = iowa(&["-e"], "");
+    assert_eq!(stderr(&output), "-e expects an expression\n");
+}
+
+#[test]
+fn test_stdin() {
+    let output = iowa(&["-", "a"], "System args println\n\"x\" println");
+    assert_eq!(stdout(&output), "list(-, a)\nx\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_exit() {
+    let output = iowa(&["-e", "\"a\" println\nSystem exit(3)\n\"b\" println"], "");
+    assert_eq!(stdout(&output), "a\n");
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = iowa(&["-e", "list(1, 2) foreach(n, System exit)\n1 / 0"], "");
+    assert!(output.status.success());
+
+    // the statuses a process can't exit with fail
+    for status in ["256", "-1"] {
+        let output = iowa(&["-e", &format!("System exit({status})")], "");
+        assert_eq!(output.status.code(), Some(1), "{status}");
+    }
+}
+
+#[test]
+fn test_exception() {
+    let source = "\
+Invalid := Exception clone
+check := method(n, if(n < 0, Invalid raise(\"negative\")); n)
+total := method(xs, xs foreach(x, check(x)))
+e := try(total(list(1, -2)))
+e catch(Invalid, err, err stack println)
+total(list(-1))
+";
+    let output = iowa(&["-"], source);
+    assert_eq!(
+        stdout(&output),
+        "list(raise at 2:38, check at 3:35, total at 4:10)\n"
+    );
+    assert_eq!(
+        stderr(&output),
+        "\
+error: Invalid: negative
+    at raise (-:2:38)
+    at check (-:3:35)
+    at total (-:6:1)
+"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_stack_overflow() {
+    let output = iowa(&["-e", "f := method(n, f(n + 1)); f(0)"], "");
+    let stderr = stderr(&output);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines[..2], ["error: stack overflow", "    at f (-e:1:16)"]);
+    assert!(lines[2].starts_with("    ... repeated"), "{stderr}");
+    assert_eq!(lines[3..], ["    at f (-e:1:27)"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = iowa(
+        &["-e", "f := method(n, f(n + 1)); try(f(0)) error println"],
+        "",
+    );
+    assert_eq!(stdout(&output), "stack overflow\n");
+    assert!(output.status.success());
+}
@@ -0,0 +1,23 @@
+counter := method(
+  count := 0
+  block(count = count + 1)
+)
+c := counter
+c call
+c call
+c call println
+
+add := block(a, b, a + b)
+add call(2, 3) println
+
+items := list(3, 1, 2)
+total := 0
+items foreach(i, item, total = total + i * item)
+total println
+
+firstOver := method(xs, limit,
+  xs foreach(x, if(x > limit, return x))
+  nil
+)
+firstOver(list(1, 5, 9), 4) println
+firstOver(list(1, 2), 4) println
@@ -0,0 +1,23 @@
+i := 0
+while(i < 10,
+  i = i + 1
+  if(i % 2 == 0, continue)
+  if(i > 7, break)
+  i print
+)
+"" println
+
+n := 0
+r := loop(n = n + 1; if(n == 5, break(n * 10)))
+r println
+
+classify := method(x,
+  if(x < 0, return "negative")
+  if(x == 0, "zero", "positive")
+)
+list(-3, 0, 4) foreach(x, classify(x) println)
+
+if(nil, "yes", "no") println
+if(false) println
+x := nil isNil and true not not
+x println
@@ -0,0 +1,3 @@
+"before" println
+Object clone undefinedMessage
+"after" println
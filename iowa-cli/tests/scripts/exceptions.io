@@ -0,0 +1,26 @@
+OutOfRange := Exception clone
+
+fetch := method(xs, i,
+  if(i >= xs size, OutOfRange raise("index " .. i))
+  xs at(i)
+)
+
+e := try(fetch(list(1, 2), 5))
+e error println
+e stack println
+e catch(Number, "not a number" println) catch(OutOfRange, err,
+  "caught " print
+  err error println
+)
+
+try(fetch(list(1), 0)) println
+try(missing) error println
+
+ensure(
+  try(Exception raise("inside")) error println,
+  "cleanup" println
+)
+
+sum := method(xs, total := 0; xs foreach(x, total = total + fetch(xs, x)); total)
+sum(list(1, 0)) println
+sum(list(4, 0)) println
@@ -0,0 +1,24 @@
+fib := method(n, if(n < 2, n, fib(n - 1) + fib(n - 2)))
+fib(20) println
+
+sum := method(n,
+  s := 0
+  while(n > 0, s = s + n; n = n - 1)
+  s
+)
+sum(1000) println
+
+x := 7 / 2
+x println
+x = 7 % 3
+x println
+x = 2 ** 10
+x println
+x = 2.5 floor + 7 negate abs
+x println
+10 sqrt round println
+3 min(4) max(1) println
+x = 1 < 2 and 3 >= 3
+x println
+x = 1 == 2 or 2 != 2
+x println
@@ -0,0 +1,29 @@
+Animal := Object clone
+Animal name := "animal"
+Animal sound := "..."
+Animal speak := method(writeln(name, " says ", sound))
+
+Dog := Animal clone
+Dog sound := "woof"
+Dog init := method(self tricks := list)
+Dog learn := method(trick, tricks append(trick); self)
+
+rex := Dog clone
+rex name := "Rex"
+rex speak
+rex learn("sit") learn("roll")
+rex tricks size println
+Dog clone tricks size println
+
+Animal speak
+Animal sound = "hm"
+Animal clone speak
+Dog speak
+
+rex hasSlot("name") println
+Dog hasSlot("name") println
+x := rex getSlot("sound") == "woof"
+x println
+x = rex protos first == Dog
+x println
+rex type println
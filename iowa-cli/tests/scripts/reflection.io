@@ -0,0 +1,30 @@
+m := message(foo bar(1, 2); baz)
+m name println
+m next arguments println
+m code println
+m next setNext(nil)
+m code println
+
+unless := method(c, if(c, nil, call evalArgAt(1)))
+unless(false, "ran" println)
+unless(true, "never" println)
+
+myWhile := method(
+  loop(if(call evalArgAt(0), call evalArgAt(1), break))
+)
+i := 0
+myWhile(i < 3, i = i + 1; i println)
+
+show := method(
+  call message name println
+  call argCount println
+  call argAt(0) code println
+  call sender == Lobby
+)
+show(1 + 2, missing) println
+
+Point := Object clone
+Point x := 10
+Point isTarget := method(call target == self)
+Point isTarget println
+message(x * 2) doInContext(Point) println
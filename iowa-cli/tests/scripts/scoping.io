@@ -0,0 +1,24 @@
+Account := Object clone
+Account balance := 0
+Account deposit := method(amount, balance = balance + amount; self)
+a := Account clone
+a deposit(10) deposit(5)
+a balance println
+Account balance println
+
+each := method(xs, b, xs foreach(x, b call(x)); "done")
+firstEven := method(xs, each(xs, block(x, if(x % 2 == 0, return x))); nil)
+firstEven(list(1, 4, 6)) println
+firstEven(list(1, 3)) println
+
+Account adder := method(block(n, self balance + n))
+a adder call(1) println
+
+add := block(a, b, a + b)
+add argumentNames println
+add code println
+Account getSlot("deposit") code println
+
+getBalance := block(balance)
+getBalance setScope(a)
+getBalance call println
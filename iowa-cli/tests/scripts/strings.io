@@ -0,0 +1,11 @@
+greet := method(who, "Hello, " .. who .. "!")
+greeting := greet("world")
+greeting println
+greeting size println
+x := "abc" < "abd"
+x println
+x = "42" asNumber + 1
+x println
+list(1, "two", list(3)) println
+write("a", 1, "b", "\n")
+writeln(nil, " ", true, " ", false)
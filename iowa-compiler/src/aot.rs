@@ -0,0 +1,418 @@
+//! The ahead-of-time compilation of the programs to object files.
+//!
+//! Every chunk of the program — the top level, the methods and the blocks — is compiled to a
+//! native function, a [`NativeChunk`], which calls the runtime support library to run each
+//! instruction and branches for the jumps itself. The object also has the source of the program,
+//! which the runtime compiles again for the constants and the messages, the [`fingerprint`] of the
+//! bytecode, which the runtime checks the one it compiled against, a table of the functions and
+//! `main`, which passes them to the runtime.
+//!
+//! The runtime support library, `iowa-rt`, defines the functions the object imports:
+//!
+//! ```c
+//! int iowa_rt_main(const char *source, size_t len, const char *name, size_t name_len,
+//!                  uint64_t fingerprint, const NativeChunk *chunks, size_t count, int argc,
+//!                  char **argv);
+//! int iowa_rt_exec(NativeFrame *frame, uint32_t pc); // non-zero when the code stops
+//! int iowa_rt_test(NativeFrame *frame);              // pops the condition of a jump
+//! int iowa_rt_lazy(NativeFrame *frame, uint32_t pc); // 1 when it sent the message lazily,
+//!                                                    // -1 when the code stops
+//! ```
+//!
+//! # Limits
+//!
+//! The native code is a threaded version of the bytecode, not a translation of it: only the
+//! jumps are compiled, every other instruction is a call into the virtual machine of the runtime,
+//! which does the same work as when it interprets the bytecode. Building a program saves the
+//! parsing and the dispatch of the instructions, but the messages are sent, the numbers boxed and
+//! the slots looked up exactly like in `iowa <file>`.
+//!
+//! The executable isn't standalone either: it embeds the source, which the runtime parses and
+//! compiles again at startup, and it's linked statically with `libiowa_rt.a`, so the library has
+//! to be built for the same version of the compiler.
+
+use std::fmt;
+use std::rc::Rc;
+
+use cranelift::codegen::ir;
+use cranelift::codegen::Context;
+use cranelift::prelude::{
+    settings, types, AbiParam, Configurable, FunctionBuilder, FunctionBuilderContext, InstBuilder,
+    IntCC,
+};
+use cranelift_module::{default_libcall_names, DataDescription, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use iowa_parser::{parse, ParseError};
+use iowa_runtime::{ObjectRef, Stop};
+
+use crate::bytecode::{Chunk, Instruction};
+use crate::compile;
+use crate::vm::{Code, Frame, Vm};
+
+/// The compiled code of a chunk, running it on the frame. Returns non-zero when the code stops.
+pub type NativeChunk = unsafe extern "C" fn(*mut NativeFrame) -> i32;
+
+/// The activation a [`NativeChunk`] runs in, which it passes back to the runtime.
+pub struct NativeFrame {
+    pub(crate) vm: *mut Vm,
+    pub(crate) code: Rc<Code>,
+    pub(crate) frame: Frame,
+    pub(crate) stack: Vec<ObjectRef>,
+    /// Why the code stopped, an error or a `return` from a block.
+    pub(crate) stop: Option<Stop>,
+}
+
+impl NativeFrame {
+    /// Run the instruction at the index, which mustn't be a jump or a return. Returns false when it
+    /// stops the code.
+    pub fn exec(&mut self, pc: u32) -> bool {
+        // SAFETY: the frames are made by the machine, which isn't used until the native code
+        // returns.
+        let vm = unsafe { &mut *self.vm };
+        match vm.exec(&self.code, &self.frame, &mut self.stack, pc as usize) {
+            Ok(()) => true,
+            Err(stop) => {
+                self.stop = Some(stop);
+                false
+            }
+        }
+    }
+
+    /// Run the check of the arguments or of the inlined method at the index. Returns whether it
+    /// sent the message lazily, or `None` when it stops the code.
+    pub fn lazy_args(&mut self, pc: u32) -> Option<bool> {
+        // SAFETY: see `exec`
+        let vm = unsafe { &mut *self.vm };
+        match vm.lazy_args(&self.code, &self.frame, &mut self.stack, pc as usize) {
+            Ok(sent) => Some(sent),
+            Err(stop) => {
+                self.stop = Some(stop);
+                None
+            }
+        }
+    }
+
+    /// Pop the condition of a jump and return whether it's true.
+    pub fn test(&mut self) -> bool {
+        // SAFETY: see `exec`
+        let vm = unsafe { &mut *self.vm };
+        let cond = self.stack.pop().expect("a condition on the stack");
+        vm.interpreter().is_true(&cond)
+    }
+}
+
+/// An error of the ahead-of-time compilation.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The source doesn't parse.
+    Parse(ParseError),
+    /// Cranelift doesn't support the host or failed to compile the code.
+    Codegen(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Codegen(msg) => write!(f, "can't compile the code: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<ParseError> for BuildError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+fn codegen(err: impl fmt::Display) -> BuildError {
+    BuildError::Codegen(err.to_string())
+}
+
+/// The chunk and all the chunks of its blocks, depth first, which is the order of the table of
+/// the native functions.
+pub(crate) fn chunks(chunk: &Chunk) -> Vec<&Chunk> {
+    let mut all = vec![chunk];
+    for block in &chunk.blocks {
+        all.extend(chunks(&block.chunk));
+    }
+    all
+}
+
+/// A hash of the bytecode of the program and of its blocks, which doesn't change between the
+/// builds of the compiler as long as it compiles the program the same way.
+pub fn fingerprint(chunk: &Chunk) -> u64 {
+    // FNV-1a of the disassembly
+    chunk
+        .to_string()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Compile the program to an object file for the host, which defines `main` and has to be linked
+/// with the runtime support library. The name of the file is shown in the stack traces of the
+/// errors.
+pub fn build(name: &str, source: &str) -> Result<Vec<u8>, BuildError> {
+    let chunk = compile(&parse(source)?);
+    let fingerprint = fingerprint(&chunk);
+
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").map_err(codegen)?;
+    flags.set("is_pic", "true").map_err(codegen)?;
+    let isa = cranelift_native::builder()
+        .map_err(codegen)?
+        .finish(settings::Flags::new(flags))
+        .map_err(codegen)?;
+    let builder = ObjectBuilder::new(isa, "iowa", default_libcall_names()).map_err(codegen)?;
+    let mut module = ObjectModule::new(builder);
+    let pointer = module.target_config().pointer_type();
+    let mut ctx = module.make_context();
+    let mut fctx = FunctionBuilderContext::new();
+
+    let signature = |module: &ObjectModule, params: &[ir::Type]| {
+        let mut signature = module.make_signature();
+        signature.params = params.iter().map(|&ty| AbiParam::new(ty)).collect();
+        signature.returns.push(AbiParam::new(types::I32));
+        signature
+    };
+    let chunk_signature = signature(&module, &[pointer]);
+    let exec = module
+        .declare_function(
+            "iowa_rt_exec",
+            Linkage::Import,
+            &signature(&module, &[pointer, types::I32]),
+        )
+        .map_err(codegen)?;
+    let test = module
+        .declare_function("iowa_rt_test", Linkage::Import, &chunk_signature)
+        .map_err(codegen)?;
+    let lazy = module
+        .declare_function(
+            "iowa_rt_lazy",
+            Linkage::Import,
+            &signature(&module, &[pointer, types::I32]),
+        )
+        .map_err(codegen)?;
+    let imports = Imports { exec, test, lazy };
+
+    let chunks = chunks(&chunk);
+    let mut ids = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let name = format!("iowa_chunk_{i}");
+        let id = module
+            .declare_function(&name, Linkage::Local, &chunk_signature)
+            .map_err(codegen)?;
+        ctx.func.signature = chunk_signature.clone();
+        define_chunk(&mut module, &mut ctx, &mut fctx, chunk, &imports);
+        module.define_function(id, &mut ctx).map_err(codegen)?;
+        module.clear_context(&mut ctx);
+        ids.push(id);
+    }
+
+    let table = module
+        .declare_data("iowa_chunks", Linkage::Local, false, false)
+        .map_err(codegen)?;
+    let mut data = DataDescription::new();
+    let size = pointer.bytes() as usize;
+    data.define(vec![0; ids.len() * size].into());
+    data.set_align(size as u64);
+    for (i, &id) in ids.iter().enumerate() {
+        let func = module.declare_func_in_data(id, &mut data);
+        data.write_function_addr((i * size) as u32, func);
+    }
+    module.define_data(table, &data).map_err(codegen)?;
+
+    let text = module
+        .declare_data("iowa_source", Linkage::Local, false, false)
+        .map_err(codegen)?;
+    let mut data = DataDescription::new();
+    data.define(source.as_bytes().into());
+    module.define_data(text, &data).map_err(codegen)?;
+
+    let file = module
+        .declare_data("iowa_file", Linkage::Local, false, false)
+        .map_err(codegen)?;
+    let mut data = DataDescription::new();
+    data.define(name.as_bytes().into());
+    module.define_data(file, &data).map_err(codegen)?;
+
+    let run = module
+        .declare_function(
+            "iowa_rt_main",
+            Linkage::Import,
+            &signature(
+                &module,
+                &[
+                    pointer,
+                    pointer,
+                    pointer,
+                    pointer,
+                    types::I64,
+                    pointer,
+                    pointer,
+                    types::I32,
+                    pointer,
+                ],
+            ),
+        )
+        .map_err(codegen)?;
+    let main_signature = signature(&module, &[types::I32, pointer]);
+    let main = module
+        .declare_function("main", Linkage::Export, &main_signature)
+        .map_err(codegen)?;
+    ctx.func.signature = main_signature;
+    let run = module.declare_func_in_func(run, &mut ctx.func);
+    let text = module.declare_data_in_func(text, &mut ctx.func);
+    let file = module.declare_data_in_func(file, &mut ctx.func);
+    let table = module.declare_data_in_func(table, &mut ctx.func);
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut fctx);
+    let block = b.create_block();
+    b.append_block_params_for_function_params(block);
+    b.switch_to_block(block);
+    b.seal_block(block);
+    let (argc, argv) = (b.block_params(block)[0], b.block_params(block)[1]);
+    let args = [
+        b.ins().symbol_value(pointer, text),
+        b.ins().iconst(pointer, source.len() as i64),
+        b.ins().symbol_value(pointer, file),
+        b.ins().iconst(pointer, name.len() as i64),
+        b.ins().iconst(types::I64, fingerprint as i64),
+        b.ins().symbol_value(pointer, table),
+        b.ins().iconst(pointer, ids.len() as i64),
+        argc,
+        argv,
+    ];
+    let call = b.ins().call(run, &args);
+    let status = b.inst_results(call)[0];
+    b.ins().return_(&[status]);
+    b.finalize();
+    module.define_function(main, &mut ctx).map_err(codegen)?;
+
+    module.finish().emit().map_err(codegen)
+}
+
+/// The functions of the runtime the chunks call.
+struct Imports {
+    exec: FuncId,
+    test: FuncId,
+    lazy: FuncId,
+}
+
+/// Emit the function of a chunk: a call of the runtime for each instruction, followed by a
+/// return when it stops the code, and branches for the jumps.
+fn define_chunk(
+    module: &mut ObjectModule,
+    ctx: &mut Context,
+    fctx: &mut FunctionBuilderContext,
+    chunk: &Chunk,
+    imports: &Imports,
+) {
+    let exec = module.declare_func_in_func(imports.exec, &mut ctx.func);
+    let test = module.declare_func_in_func(imports.test, &mut ctx.func);
+    let lazy = module.declare_func_in_func(imports.lazy, &mut ctx.func);
+    let mut b = FunctionBuilder::new(&mut ctx.func, fctx);
+
+    let entry = b.create_block();
+    b.append_block_params_for_function_params(entry);
+    let stopped = b.create_block();
+    let labels: Vec<ir::Block> = (0..=chunk.code.len()).map(|_| b.create_block()).collect();
+
+    b.switch_to_block(entry);
+    let frame = b.block_params(entry)[0];
+    b.ins().jump(labels[0], &[]);
+
+    for (pc, &inst) in chunk.code.iter().enumerate() {
+        b.switch_to_block(labels[pc]);
+        let next = labels[pc + 1];
+        match inst {
+            Instruction::Jump(target) => {
+                b.ins().jump(labels[target as usize], &[]);
+            }
+            Instruction::JumpIfFalse(target) => {
+                let call = b.ins().call(test, &[frame]);
+                let cond = b.inst_results(call)[0];
+                b.ins().brif(cond, next, &[], labels[target as usize], &[]);
+            }
+            Instruction::LazyArgs { end, .. } | Instruction::Inline { end, .. } => {
+                let pc = b.ins().iconst(types::I32, pc as i64);
+                let call = b.ins().call(lazy, &[frame, pc]);
+                let sent = b.inst_results(call)[0];
+                let stops = b.ins().icmp_imm(IntCC::SignedLessThan, sent, 0);
+                let check = b.create_block();
+                b.ins().brif(stops, stopped, &[], check, &[]);
+                b.switch_to_block(check);
+                b.ins().brif(sent, labels[end as usize], &[], next, &[]);
+            }
+            Instruction::Return => {
+                let ok = b.ins().iconst(types::I32, 0);
+                b.ins().return_(&[ok]);
+            }
+            _ => {
+                let pc = b.ins().iconst(types::I32, pc as i64);
+                let call = b.ins().call(exec, &[frame, pc]);
+                let status = b.inst_results(call)[0];
+                b.ins().brif(status, stopped, &[], next, &[]);
+            }
+        }
+    }
+
+    b.switch_to_block(labels[chunk.code.len()]);
+    let ok = b.ins().iconst(types::I32, 0);
+    b.ins().return_(&[ok]);
+
+    b.switch_to_block(stopped);
+    let stop = b.ins().iconst(types::I32, 1);
+    b.ins().return_(&[stop]);
+
+    b.seal_all_blocks();
+    b.finalize();
+}
+
+#[cfg(test)]
+mod tests {
+    use iowa_parser::parse;
+
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let source = "f := method(g := method(1); block(2))\nh := method(3)";
+        let chunk = compile(&parse(source).unwrap());
+        let sizes: Vec<usize> = chunks(&chunk).iter().map(|c| c.code.len()).collect();
+        // the top level, f, g, the block in f, h
+        assert_eq!(sizes.len(), 5);
+        assert_eq!(sizes[0], chunk.code.len());
+        assert_eq!(sizes[1], chunk.blocks[0].chunk.code.len());
+        assert_eq!(sizes[4], chunk.blocks[1].chunk.code.len());
+    }
+
+    #[test]
+    fn test_build() {
+        let object = build("hello.io", "\"hello\" println").unwrap();
+        assert!(!object.is_empty());
+
+        let err = build("error.io", "foo(").unwrap_err();
+        assert!(matches!(err, BuildError::Parse(_)), "{err}");
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = |source| fingerprint(&compile(&parse(source).unwrap()));
+        assert_eq!(fingerprint("x := 1"), fingerprint("x := 1"));
+        assert_ne!(fingerprint("x := 1"), fingerprint("x := 2"));
+        assert_ne!(fingerprint("f := method(1)"), fingerprint("f := method(2)"));
+
+        let mut vm = Vm::with_output(std::io::sink());
+        let err = vm
+            .eval_native("x := 1", fingerprint("x := 2"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: the native code wasn't compiled from the source"
+        );
+    }
+}
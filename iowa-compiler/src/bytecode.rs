@@ -0,0 +1,156 @@
+//! The bytecode format.
+//!
+//! The code runs on a stack machine. A chain pushes its first target, usually the locals, and each
+//! message replaces the target on the top of the stack with its result, so a chain leaves a single
+//! value on the stack. Arguments are pushed above the target before the send, left to right. A
+//! chunk ends with its value on the top of the stack.
+//!
+//! The control flow messages — `if`, `while`, `loop`, `break`, `continue`, `return`, `and` and
+//! `or` — are compiled into jumps when their form is known, and `method` and `block` bodies are
+//! compiled ahead into separate chunks. They're preceded by [`Instruction::Inline`], which sends
+//! the message instead when the target doesn't respond to it with the built-in method anymore.
+//! Messages which evaluate their own arguments, like `foreach`, are kept as trees and sent with
+//! [`Instruction::SendLazy`].
+//!
+//! A method only gets the arguments for its parameters evaluated, it can evaluate the others with
+//! `call`. So the arguments of the other sends are preceded by [`Instruction::LazyArgs`], which
+//! sends the message lazily instead when the method found doesn't take them all. The operators and
+//! the assignments are assumed to take their arguments.
+
+use std::collections::HashMap;
+
+use iowa_parser::{Message, MessageChain, Span, SymbolId};
+
+/// A single instruction.
+///
+/// The operands index the tables of the [`Chunk`] or the code itself for jumps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    /// Push the constant at the index of [`Chunk::constants`].
+    Constant(u32),
+    /// Push `nil`.
+    Nil,
+    /// Push `true`.
+    True,
+    /// Push `false`.
+    False,
+    /// Push the receiver of the activation.
+    PushSelf,
+    /// Push the locals of the activation.
+    PushLocals,
+    /// Pop the value.
+    Pop,
+    /// Pop the value and the number of values below it, then push the value back.
+    PopUnder(u32),
+    /// Pop the arguments and the target, send the message and push the result.
+    Send {
+        /// The index of [`Chunk::selectors`].
+        selector: u32,
+        /// The number of the arguments.
+        argc: u32,
+    },
+    /// Pop the target, send the message at the index of [`Chunk::messages`] with the arguments
+    /// unevaluated and push the result.
+    SendLazy(u32),
+    /// Check the slot the target on the top of the stack responds with to the [`Instruction::Send`]
+    /// before `end`. If it's a method with fewer parameters than the arguments, or isn't a method,
+    /// pop the target, send the message at the index of [`Chunk::messages`] with the arguments
+    /// unevaluated, push the result and continue at `end`. Otherwise continue with the arguments.
+    LazyArgs {
+        /// The index of [`Chunk::messages`].
+        message: u32,
+        /// The instruction after the send.
+        end: u32,
+    },
+    /// Check the slot the target on the top of the stack responds with to the message at the index
+    /// of [`Chunk::messages`] is still the built-in method the code before `end` was compiled from.
+    /// If it isn't, pop the target, send the message with the arguments unevaluated, push the
+    /// result and continue at `end`. Otherwise continue with the compiled code.
+    Inline {
+        /// The index of [`Chunk::messages`].
+        message: u32,
+        /// The instruction after the compiled code.
+        end: u32,
+    },
+    /// Pop the value and return it from the activation, which is how `return` is compiled.
+    ///
+    /// In a block, it returns from the method the block was made in while that one is running,
+    /// otherwise from the block.
+    Return,
+    /// Continue at the instruction.
+    Jump(u32),
+    /// Pop the value and continue at the instruction if it's `false` or `nil`.
+    JumpIfFalse(u32),
+    /// Push a method or a block with the code at the index of [`Chunk::blocks`].
+    MakeBlock(u32),
+}
+
+impl Instruction {
+    /// The number of values the instruction pushes minus the number it pops.
+    pub fn stack_effect(self) -> i32 {
+        match self {
+            Self::Constant(_)
+            | Self::Nil
+            | Self::True
+            | Self::False
+            | Self::PushSelf
+            | Self::PushLocals
+            | Self::MakeBlock(_) => 1,
+            Self::Pop | Self::Return | Self::JumpIfFalse(_) => -1,
+            Self::PopUnder(n) => -(n as i32),
+            Self::Send { argc, .. } => -(argc as i32),
+            Self::SendLazy(_) | Self::LazyArgs { .. } | Self::Inline { .. } | Self::Jump(_) => 0,
+        }
+    }
+}
+
+/// A literal of the code.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    /// A number.
+    Number(f64),
+    /// A string.
+    Sequence(String),
+}
+
+/// The kind of the code of [`Instruction::MakeBlock`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BlockKind {
+    /// A method, which runs in the context of the receiver.
+    Method,
+    /// A block, which runs in the context it was created in.
+    Block,
+}
+
+/// The code of a method or a block.
+#[derive(Debug, Clone)]
+pub struct BlockCode {
+    /// Method or block.
+    pub kind: BlockKind,
+    /// The names of the arguments.
+    pub params: Vec<SymbolId>,
+    /// The body as it was parsed, for the engines which don't run the bytecode.
+    pub body: Vec<MessageChain<'static>>,
+    /// The compiled body.
+    pub chunk: Chunk,
+}
+
+/// A compiled unit of code: the top level or the body of a method or a block.
+#[derive(Debug, Default, Clone)]
+pub struct Chunk {
+    /// The instructions.
+    pub code: Vec<Instruction>,
+    /// The location of the message each instruction was compiled from.
+    pub spans: Vec<Span>,
+    /// The literals.
+    pub constants: Vec<Constant>,
+    /// The names of the messages sent by [`Instruction::Send`].
+    pub selectors: Vec<SymbolId>,
+    /// The messages sent by [`Instruction::SendLazy`].
+    pub messages: Vec<Message<'static>>,
+    /// The message of each [`Instruction::Send`] and [`Instruction::SendLazy`] with the rest of its
+    /// chain, by the index of the instruction, for `call`.
+    pub chains: HashMap<usize, Vec<Message<'static>>>,
+    /// The methods and the blocks.
+    pub blocks: Vec<BlockCode>,
+}
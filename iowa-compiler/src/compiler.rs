@@ -0,0 +1,604 @@
+//! The compiler from the message chains to the bytecode.
+
+use iowa_parser::{Argument, Message, MessageChain, Span, Symbol, SymbolId};
+
+use crate::bytecode::{BlockCode, BlockKind, Chunk, Constant, Instruction};
+
+/// The messages which evaluate their arguments themselves.
+const LAZY: &[&str] = &[
+    "if", "while", "loop", "break", "continue", "return", "method", "block", "and", "or", "&&",
+    "||", "foreach", "do", "message", "try", "catch", "ensure",
+];
+
+/// The messages which are assumed to take all their arguments evaluated, besides the operators.
+const EAGER: &[&str] = &["setSlot", "updateSlot", "newSlot"];
+
+/// A message compiled into instructions instead of a send, with its arguments.
+enum Form<'m, 'a> {
+    Self_,
+    If(&'m Argument<'a>, &'m Argument<'a>, Option<&'m Argument<'a>>),
+    /// `while` with its condition or `loop`.
+    Loop(Option<&'m Argument<'a>>, &'m Argument<'a>),
+    Break(Option<&'m Argument<'a>>),
+    Continue,
+    Return(Option<&'m Argument<'a>>),
+    Block(BlockKind, Vec<SymbolId>, &'m [MessageChain<'a>]),
+    And(&'m Argument<'a>),
+    Or(&'m Argument<'a>),
+}
+
+/// A loop compiled into jumps, which `break` and `continue` can jump out of.
+struct Loop {
+    /// The first instruction of the loop.
+    start: u32,
+    /// The stack depth before the loop.
+    depth: u32,
+    /// The jumps of `break` to patch with the end of the loop.
+    breaks: Vec<usize>,
+}
+
+/// Compiles a single chunk.
+#[derive(Default)]
+pub(crate) struct Compiler {
+    chunk: Chunk,
+    /// The stack depth at the current instruction.
+    depth: u32,
+    loops: Vec<Loop>,
+    /// The location of the message being compiled.
+    span: Span,
+}
+
+impl Compiler {
+    /// Compile the chains into a chunk which ends with the value of the last one.
+    pub(crate) fn compile(mut self, chains: &[MessageChain<'_>]) -> Chunk {
+        self.chains(chains);
+        self.chunk
+    }
+
+    fn emit(&mut self, inst: Instruction) -> usize {
+        self.depth = self.depth.wrapping_add_signed(inst.stack_effect());
+        self.chunk.code.push(inst);
+        self.chunk.spans.push(self.span);
+        self.chunk.code.len() - 1
+    }
+
+    /// The index of the next instruction.
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Set the target of the jump to the next instruction.
+    fn patch(&mut self, jump: usize) {
+        let here = self.here();
+        match &mut self.chunk.code[jump] {
+            Instruction::Jump(target)
+            | Instruction::JumpIfFalse(target)
+            | Instruction::LazyArgs { end: target, .. }
+            | Instruction::Inline { end: target, .. } => *target = here,
+            inst => unreachable!("{inst:?} is not a jump"),
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        let pos = self
+            .chunk
+            .constants
+            .iter()
+            .position(|c| match (c, &constant) {
+                // compare the bits, so `0` and `-0` are different constants
+                (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+                (a, b) => a == b,
+            });
+        pos.unwrap_or_else(|| {
+            self.chunk.constants.push(constant);
+            self.chunk.constants.len() - 1
+        }) as u32
+    }
+
+    fn selector(&mut self, name: SymbolId) -> u32 {
+        let pos = self.chunk.selectors.iter().position(|&s| s == name);
+        pos.unwrap_or_else(|| {
+            self.chunk.selectors.push(name);
+            self.chunk.selectors.len() - 1
+        }) as u32
+    }
+
+    /// Compile the chains leaving the value of the last one, or `nil` if there are none.
+    fn chains(&mut self, chains: &[MessageChain<'_>]) {
+        if chains.is_empty() {
+            self.emit(Instruction::Nil);
+        }
+        for (i, chain) in chains.iter().enumerate() {
+            if i > 0 {
+                self.emit(Instruction::Pop);
+            }
+            self.chain(chain);
+        }
+    }
+
+    fn chain(&mut self, chain: &MessageChain<'_>) {
+        if chain.is_empty() {
+            self.emit(Instruction::PushLocals);
+        }
+        for (i, msg) in chain.iter().enumerate() {
+            self.message(msg, &chain[i + 1..], i == 0);
+        }
+    }
+
+    /// Compile the message followed by the next ones, the first message of a chain is sent to the
+    /// locals.
+    fn message(&mut self, msg: &Message<'_>, next: &[Message<'_>], first: bool) {
+        let outer = std::mem::replace(&mut self.span, msg.span);
+        let name = match &msg.symbol {
+            Symbol::Number(num) => {
+                let num = match *num {
+                    iowa_parser::Number::Decimal(num) => num,
+                    iowa_parser::Number::Hex(num) => num as f64,
+                };
+                self.literal(Constant::Number(num), first);
+                None
+            }
+            Symbol::Quote(quote) => {
+                self.literal(Constant::Sequence(quote.as_str().to_string()), first);
+                None
+            }
+            Symbol::Identifier(_) | Symbol::Operator(_) | Symbol::Error => Some(msg.symbol.id()),
+        };
+
+        if let Some(name) = name {
+            let form = match name {
+                Some(name) if first => self.special_form(name.as_str(), &msg.args),
+                Some(name) => operator_form(name.as_str(), &msg.args),
+                None => None,
+            };
+            let chain = std::iter::once(msg).chain(next);
+            let chain = chain.map(|msg| msg.clone().into_owned()).collect();
+            let send = match form {
+                Some(form) => self.inline(form, msg, first),
+                None => self.send(name, msg, first),
+            };
+            self.chunk.chains.insert(send, chain);
+        }
+        self.span = outer;
+    }
+
+    fn literal(&mut self, constant: Constant, first: bool) {
+        if !first {
+            self.emit(Instruction::Pop);
+        }
+        let index = self.constant(constant);
+        self.emit(Instruction::Constant(index));
+    }
+
+    /// Compile the send of the message, returning the index of the instruction.
+    fn send(&mut self, name: Option<SymbolId>, msg: &Message<'_>, first: bool) -> usize {
+        if first {
+            self.emit(Instruction::PushLocals);
+        }
+
+        match name {
+            Some(name) if !LAZY.contains(&name.as_str()) => {
+                let eager = msg.args.is_empty()
+                    || matches!(msg.symbol, Symbol::Operator(_))
+                    || EAGER.contains(&name.as_str());
+                let lazy = (!eager).then(|| {
+                    self.chunk.messages.push(msg.clone().into_owned());
+                    let message = self.chunk.messages.len() as u32 - 1;
+                    self.emit(Instruction::LazyArgs { message, end: 0 })
+                });
+                for arg in &msg.args {
+                    self.chains(arg);
+                }
+                let selector = self.selector(name);
+                let send = self.emit(Instruction::Send {
+                    selector,
+                    argc: msg.args.len() as u32,
+                });
+                if let Some(lazy) = lazy {
+                    self.patch(lazy);
+                }
+                send
+            }
+            _ => {
+                self.chunk.messages.push(msg.clone().into_owned());
+                self.emit(Instruction::SendLazy(self.chunk.messages.len() as u32 - 1))
+            }
+        }
+    }
+
+    /// The form of the control flow message sent to the locals, if it's known.
+    fn special_form<'m, 'a>(&self, name: &str, args: &'m [Argument<'a>]) -> Option<Form<'m, 'a>> {
+        let form = match (name, args) {
+            ("self", []) => Form::Self_,
+            ("if", [cond, then]) => Form::If(cond, then, None),
+            ("if", [cond, then, otherwise]) => Form::If(cond, then, Some(otherwise)),
+            ("while", [cond, body]) => Form::Loop(Some(cond), body),
+            ("loop", [body]) => Form::Loop(None, body),
+            ("break", [] | [_]) if !self.loops.is_empty() => Form::Break(args.first()),
+            ("continue", []) if !self.loops.is_empty() => Form::Continue,
+            ("return", [] | [_]) => Form::Return(args.first()),
+            ("method", _) => block_form(BlockKind::Method, args)?,
+            ("block", _) => block_form(BlockKind::Block, args)?,
+            _ => return None,
+        };
+        Some(form)
+    }
+
+    /// Compile the form of the message, checked to be still the one of the built-in method of
+    /// the target, which is the locals for the control flow and the value on the stack for the
+    /// operators. Returns the index of the check.
+    fn inline(&mut self, form: Form<'_, '_>, msg: &Message<'_>, first: bool) -> usize {
+        if first {
+            self.emit(Instruction::PushLocals);
+        }
+        self.chunk.messages.push(msg.clone().into_owned());
+        let message = self.chunk.messages.len() as u32 - 1;
+        let check = self.emit(Instruction::Inline { message, end: 0 });
+        if first {
+            self.emit(Instruction::Pop);
+        }
+
+        match form {
+            Form::Self_ => {
+                self.emit(Instruction::PushSelf);
+            }
+            Form::If(cond, then, otherwise) => self.if_(cond, then, otherwise),
+            Form::Loop(cond, body) => self.loop_(cond, body),
+            Form::Break(value) => self.break_(value),
+            Form::Continue => self.continue_(),
+            Form::Return(value) => {
+                match value {
+                    Some(value) => self.chains(value),
+                    None => {
+                        self.emit(Instruction::Nil);
+                    }
+                }
+                self.emit(Instruction::Return);
+                // the code after it is unreachable, but it expects a value on the stack
+                self.depth += 1;
+            }
+            Form::Block(kind, params, body) => self.block(kind, params, body),
+            Form::And(other) => {
+                let target_false = self.emit(Instruction::JumpIfFalse(0));
+                self.chains(other);
+                let other_false = self.emit(Instruction::JumpIfFalse(0));
+                self.boolean(&[target_false, other_false]);
+            }
+            Form::Or(other) => {
+                let target_false = self.emit(Instruction::JumpIfFalse(0));
+                self.emit(Instruction::True);
+                let end = self.emit(Instruction::Jump(0));
+                self.depth -= 1;
+                self.patch(target_false);
+                self.chains(other);
+                let other_false = self.emit(Instruction::JumpIfFalse(0));
+                self.boolean(&[other_false]);
+                self.patch(end);
+            }
+        }
+        self.patch(check);
+        check
+    }
+
+    /// Push `true`, or `false` if one of the jumps is taken.
+    fn boolean(&mut self, jumps: &[usize]) {
+        self.emit(Instruction::True);
+        let end = self.emit(Instruction::Jump(0));
+        self.depth -= 1;
+        for &jump in jumps {
+            self.patch(jump);
+        }
+        self.emit(Instruction::False);
+        self.patch(end);
+    }
+
+    fn if_(&mut self, cond: &Argument<'_>, then: &Argument<'_>, otherwise: Option<&Argument<'_>>) {
+        self.chains(cond);
+        let to_else = self.emit(Instruction::JumpIfFalse(0));
+        self.chains(then);
+        let to_end = self.emit(Instruction::Jump(0));
+        self.depth -= 1;
+        self.patch(to_else);
+        match otherwise {
+            Some(otherwise) => self.chains(otherwise),
+            None => {
+                self.emit(Instruction::False);
+            }
+        }
+        self.patch(to_end);
+    }
+
+    fn loop_(&mut self, cond: Option<&Argument<'_>>, body: &Argument<'_>) {
+        self.loops.push(Loop {
+            start: self.here(),
+            depth: self.depth,
+            breaks: Vec::new(),
+        });
+
+        let exit = cond.map(|cond| {
+            self.chains(cond);
+            self.emit(Instruction::JumpIfFalse(0))
+        });
+        self.chains(body);
+        self.emit(Instruction::Pop);
+        let start = self.loops.last().unwrap().start;
+        self.emit(Instruction::Jump(start));
+
+        if let Some(exit) = exit {
+            self.patch(exit);
+            self.emit(Instruction::Nil);
+        } else {
+            self.depth += 1;
+        }
+        for jump in self.loops.pop().unwrap().breaks {
+            self.patch(jump);
+        }
+    }
+
+    fn break_(&mut self, value: Option<&Argument<'_>>) {
+        let depth = self.depth;
+        match value {
+            Some(value) => self.chains(value),
+            None => {
+                self.emit(Instruction::Nil);
+            }
+        }
+        let count = depth - self.loops.last().unwrap().depth;
+        if count > 0 {
+            self.emit(Instruction::PopUnder(count));
+        }
+        let jump = self.emit(Instruction::Jump(0));
+        self.loops.last_mut().unwrap().breaks.push(jump);
+        // the code after it is unreachable, but it expects a value on the stack
+        self.depth = depth + 1;
+    }
+
+    fn continue_(&mut self) {
+        let depth = self.depth;
+        let current = self.loops.last().unwrap();
+        let start = current.start;
+        for _ in current.depth..depth {
+            self.emit(Instruction::Pop);
+        }
+        self.emit(Instruction::Jump(start));
+        self.depth = depth + 1;
+    }
+
+    fn block(&mut self, kind: BlockKind, params: Vec<SymbolId>, body: &[MessageChain<'_>]) {
+        let chunk = Compiler::default().compile(body);
+        self.chunk.blocks.push(BlockCode {
+            kind,
+            params,
+            body: body.iter().cloned().map(MessageChain::into_owned).collect(),
+            chunk,
+        });
+        self.emit(Instruction::MakeBlock(self.chunk.blocks.len() as u32 - 1));
+    }
+}
+
+/// The form of the operator sent to the value on the stack, if it's known.
+fn operator_form<'m, 'a>(name: &str, args: &'m [Argument<'a>]) -> Option<Form<'m, 'a>> {
+    match (name, args) {
+        ("and" | "&&", [other]) => Some(Form::And(other)),
+        ("or" | "||", [other]) => Some(Form::Or(other)),
+        _ => None,
+    }
+}
+
+/// The form of `method` or `block` if the arguments before the body are names.
+fn block_form<'m, 'a>(kind: BlockKind, args: &'m [Argument<'a>]) -> Option<Form<'m, 'a>> {
+    let (body, params) = args
+        .split_last()
+        .map_or((&[][..], &[][..]), |(body, params)| (&body[..], params));
+    let params = params.iter().map(param_name).collect::<Option<Vec<_>>>()?;
+    Some(Form::Block(kind, params, body))
+}
+
+/// The name of the argument which is a single message without arguments.
+fn param_name(arg: &Argument<'_>) -> Option<SymbolId> {
+    match &arg[..] {
+        [chain] => match &chain[..] {
+            [Message {
+                symbol: Symbol::Identifier(ident),
+                args,
+                ..
+            }] if args.is_empty() => Some(ident.id()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iowa_parser::parse;
+
+    use super::*;
+    use crate::bytecode::Constant as Literal;
+    use Instruction::*;
+
+    fn compile(source: &str) -> Chunk {
+        Compiler::default().compile(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn test_compile_sends() {
+        let chunk = compile("foo(1, \"a\") bar\n2 + 1");
+        assert_eq!(
+            chunk.code,
+            [
+                PushLocals,
+                LazyArgs { message: 0, end: 5 },
+                Constant(0),
+                Constant(1),
+                Send {
+                    selector: 0,
+                    argc: 2
+                },
+                Send {
+                    selector: 1,
+                    argc: 0
+                },
+                Pop,
+                Constant(2),
+                Constant(0),
+                Send {
+                    selector: 2,
+                    argc: 1
+                },
+            ]
+        );
+        assert_eq!(
+            chunk.constants,
+            [
+                Literal::Number(1.0),
+                Literal::Sequence("a".to_string()),
+                Literal::Number(2.0),
+            ]
+        );
+        let selectors: Vec<_> = chunk.selectors.iter().map(|s| s.as_str()).collect();
+        assert_eq!(selectors, ["foo", "bar", "+"]);
+        assert_eq!(chunk.messages[0].to_string(), "foo(1, \"a\")");
+        assert_eq!((chunk.spans[4].start, chunk.spans[4].end), (0, 11));
+    }
+
+    #[test]
+    fn test_compile_control_flow() {
+        let chunk = compile("if(a, 1, 2)");
+        assert_eq!(
+            chunk.code,
+            [
+                PushLocals,
+                Inline { message: 0, end: 9 },
+                Pop,
+                PushLocals,
+                Send {
+                    selector: 0,
+                    argc: 0
+                },
+                JumpIfFalse(8),
+                Constant(0),
+                Jump(9),
+                Constant(1),
+            ]
+        );
+
+        let chunk = compile("a and b");
+        assert_eq!(
+            chunk.code,
+            [
+                PushLocals,
+                Send {
+                    selector: 0,
+                    argc: 0
+                },
+                Inline {
+                    message: 0,
+                    end: 10
+                },
+                JumpIfFalse(9),
+                PushLocals,
+                Send {
+                    selector: 1,
+                    argc: 0
+                },
+                JumpIfFalse(9),
+                True,
+                Jump(10),
+                False,
+            ]
+        );
+        assert_eq!(chunk.messages[0].to_string(), "and(b)");
+
+        // `break` drops the target of `foo` under its value
+        let chunk = compile("while(a, foo(break(1)); continue)");
+        assert_eq!(
+            chunk.code,
+            [
+                PushLocals,
+                Inline {
+                    message: 0,
+                    end: 23
+                },
+                Pop,
+                PushLocals,
+                Send {
+                    selector: 0,
+                    argc: 0
+                },
+                JumpIfFalse(22),
+                PushLocals,
+                LazyArgs {
+                    message: 1,
+                    end: 15
+                },
+                PushLocals,
+                Inline {
+                    message: 2,
+                    end: 14
+                },
+                Pop,
+                Constant(0),
+                PopUnder(1),
+                Jump(23),
+                Send {
+                    selector: 1,
+                    argc: 1
+                },
+                Pop,
+                PushLocals,
+                Inline {
+                    message: 3,
+                    end: 20
+                },
+                Pop,
+                Jump(3),
+                Pop,
+                Jump(3),
+                Nil,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_blocks() {
+        let chunk = compile("method(m, n, return m)\nblock(foo(x), x)\nlist foreach(x, x)");
+        assert_eq!(
+            chunk.code,
+            [
+                PushLocals,
+                Inline { message: 0, end: 4 },
+                Pop,
+                MakeBlock(0),
+                Pop,
+                PushLocals,
+                SendLazy(1),
+                Pop,
+                PushLocals,
+                Send {
+                    selector: 0,
+                    argc: 0
+                },
+                SendLazy(2),
+            ]
+        );
+        let block = &chunk.blocks[0];
+        assert_eq!(block.kind, BlockKind::Method);
+        assert_eq!(block.params, [SymbolId::intern("m"), SymbolId::intern("n")]);
+        assert_eq!(
+            block.chunk.code,
+            [
+                PushLocals,
+                Inline { message: 0, end: 6 },
+                Pop,
+                PushLocals,
+                Send {
+                    selector: 0,
+                    argc: 0
+                },
+                Return,
+            ]
+        );
+        assert_eq!(chunk.messages[1].to_string(), "block(foo(x), x)");
+    }
+}
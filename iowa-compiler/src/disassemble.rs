@@ -0,0 +1,136 @@
+//! The disassembler, which prints the bytecode for debugging.
+
+use std::fmt;
+
+use crate::bytecode::{BlockKind, Chunk, Constant, Instruction};
+
+/// Prints the instructions with their locations and resolved operands, then the blocks.
+///
+/// ```text
+/// 0000    1:1    push_locals
+/// 0001    1:5    constant      0    ; 3
+/// 0002    1:1    send          0 1  ; ack
+/// ```
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, "")
+    }
+}
+
+impl Chunk {
+    fn write(&self, f: &mut fmt::Formatter<'_>, path: &str) -> fmt::Result {
+        for (i, (inst, span)) in self.code.iter().zip(&self.spans).enumerate() {
+            let location = if span.is_dummy() {
+                String::new()
+            } else {
+                format!("{}:{}", span.line, span.column)
+            };
+            write!(f, "{i:04} {location:>6}    ")?;
+
+            match *inst {
+                Instruction::Constant(index) => {
+                    let comment = match &self.constants[index as usize] {
+                        Constant::Number(num) => num.to_string(),
+                        Constant::Sequence(seq) => format!("{seq:?}"),
+                    };
+                    writeln!(f, "{:<13} {index:<4} ; {comment}", "constant")?;
+                }
+                Instruction::Nil => writeln!(f, "nil")?,
+                Instruction::True => writeln!(f, "true")?,
+                Instruction::False => writeln!(f, "false")?,
+                Instruction::PushSelf => writeln!(f, "push_self")?,
+                Instruction::PushLocals => writeln!(f, "push_locals")?,
+                Instruction::Pop => writeln!(f, "pop")?,
+                Instruction::PopUnder(count) => writeln!(f, "{:<13} {count}", "pop_under")?,
+                Instruction::Send { selector, argc } => {
+                    let name = self.selectors[selector as usize];
+                    let operands = format!("{selector} {argc}");
+                    writeln!(f, "{:<13} {operands:<4} ; {name}", "send")?;
+                }
+                Instruction::SendLazy(index) => {
+                    let msg = &self.messages[index as usize];
+                    writeln!(f, "{:<13} {index:<4} ; {msg}", "send_lazy")?;
+                }
+                Instruction::LazyArgs { message, end } => {
+                    let operands = format!("{message} {end:04}");
+                    let name = &self.messages[message as usize].symbol;
+                    writeln!(f, "{:<13} {operands:<4} ; {name}", "lazy_args")?;
+                }
+                Instruction::Inline { message, end } => {
+                    let operands = format!("{message} {end:04}");
+                    let name = &self.messages[message as usize].symbol;
+                    writeln!(f, "{:<13} {operands:<4} ; {name}", "inline")?;
+                }
+                Instruction::Return => writeln!(f, "return")?,
+                Instruction::Jump(target) => writeln!(f, "{:<13} {target:04}", "jump")?,
+                Instruction::JumpIfFalse(target) => {
+                    writeln!(f, "{:<13} {target:04}", "jump_if_false")?
+                }
+                Instruction::MakeBlock(index) => writeln!(f, "{:<13} {path}{index}", "make_block")?,
+            }
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let kind = match block.kind {
+                BlockKind::Method => "method",
+                BlockKind::Block => "block",
+            };
+            let params: Vec<_> = block.params.iter().map(|param| param.as_str()).collect();
+            let path = format!("{path}{i}");
+            writeln!(f, "\n{kind} {path}({}):", params.join(", "))?;
+            block.chunk.write(f, &format!("{path}."))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iowa_parser::parse;
+
+    use crate::compile;
+
+    #[test]
+    fn test_disassemble() {
+        let chunk = compile(
+            &parse("ack := method(m, n, if(m < 1, return n + 1))\nack(3, 4) print").unwrap(),
+        );
+        let expected = r#"0000    1:1    push_locals
+0001    1:1    constant      0    ; "ack"
+0002    1:8    push_locals
+0003    1:8    inline        0 0006 ; method
+0004    1:8    pop
+0005    1:8    make_block    0
+0006    1:1    send          0 2  ; setSlot
+0007           pop
+0008    2:1    push_locals
+0009    2:1    lazy_args     1 0013 ; ack
+0010    2:5    constant      1    ; 3
+0011    2:8    constant      2    ; 4
+0012    2:1    send          1 2  ; ack
+0013   2:11    send          2 0  ; print
+
+method 0(m, n):
+0000   1:21    push_locals
+0001   1:21    inline        0 0018 ; if
+0002   1:21    pop
+0003   1:24    push_locals
+0004   1:24    send          0 0  ; m
+0005   1:28    constant      0    ; 1
+0006   1:26    send          1 1  ; <
+0007   1:21    jump_if_false 0017
+0008   1:31    push_locals
+0009   1:31    inline        1 0016 ; return
+0010   1:31    pop
+0011   1:38    push_locals
+0012   1:38    send          2 0  ; n
+0013   1:42    constant      0    ; 1
+0014   1:40    send          3 1  ; +
+0015   1:31    return
+0016   1:21    jump          0018
+0017   1:21    false
+"#;
+        assert_eq!(chunk.to_string(), expected);
+    }
+}
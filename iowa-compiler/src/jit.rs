@@ -0,0 +1,1082 @@
+//! The just-in-time compiler of the hot methods.
+//!
+//! A method the machine has called [`THRESHOLD`] times is lowered from the bytecode to Cranelift
+//! IR, together with the methods it calls. Only the methods computing with numbers are compiled:
+//! the values on the stack are tracked by their kind — the locals, a `Number`, a boolean, `nil` or
+//! a slot name — and the sends are specialized for the kinds. `Number` arithmetic and comparisons
+//! become float instructions, the slots of the locals become variables and the methods found in the
+//! receiver become direct calls. Any other message keeps the method in the bytecode.
+//!
+//! The specializations hold while the slots they were made for don't change, so they're recorded
+//! as guards and checked with the arguments each time the machine enters the compiled code. A call
+//! with other arguments than numbers runs the bytecode. When a guard fails the compiled code is
+//! dropped and the method is compiled again once it's hot again, at most [`MAX_COMPILES`] times.
+//! The compiled code doesn't change any slot itself, so the guards hold until it returns.
+
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use cranelift::codegen::ir::{self, TrapCode};
+use cranelift::codegen::Context;
+use cranelift::prelude::{
+    settings, types, AbiParam, Configurable, EntityRef, FloatCC, FunctionBuilder,
+    FunctionBuilderContext, InstBuilder, MemFlags, Signature, Type, Value, Variable,
+};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Module};
+use iowa_parser::SymbolId;
+use iowa_runtime::{Block, Data, Interpreter, ObjectRef};
+
+use crate::bytecode::{Chunk, Constant, Instruction};
+use crate::compile;
+
+/// The number of the calls after which a method is compiled.
+pub(crate) const THRESHOLD: u32 = 100;
+
+/// The most times a method is compiled, after which it stays in the bytecode.
+pub(crate) const MAX_COMPILES: u32 = 4;
+
+/// The most slots the locals of a compiled method can have.
+const MAX_VARS: usize = 64;
+
+/// The arithmetic of `Number` which is compiled.
+const BINARY: &[&str] = &["+", "-", "*", "/"];
+const COMPARE: &[(&str, FloatCC)] = &[
+    ("<", FloatCC::LessThan),
+    ("<=", FloatCC::LessThanOrEqual),
+    (">", FloatCC::GreaterThan),
+    (">=", FloatCC::GreaterThanOrEqual),
+    ("==", FloatCC::Equal),
+    ("!=", FloatCC::NotEqual),
+];
+const UNARY: &[&str] = &["floor", "ceil", "abs", "sqrt", "negate"];
+
+/// The methods of the locals which are compiled.
+const LOCALS: &[&str] = &["setSlot", "updateSlot", "-"];
+
+/// The control flow compiled into jumps, sent to the locals and to the values.
+const FORMS: &[&str] = &["if", "while", "loop", "break", "continue", "return"];
+const OPERATORS: &[&str] = &["and", "&&", "or", "||"];
+
+/// The entry of the compiled code, taking the arguments as an array, the flag it sets on a stack
+/// overflow and the number of the activations it can make.
+type Entry = unsafe extern "C" fn(*const f64, *mut u8, i64) -> f64;
+
+/// The compiled code made more activations than it was allowed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct StackOverflow;
+
+/// The numbers of the methods compiled and of the calls which left the compiled code.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct JitStats {
+    /// The methods compiled, counting the recompilations.
+    pub compiled: u64,
+    /// The calls of the compiled methods which ran the bytecode, as their arguments weren't
+    /// numbers or a guard failed.
+    pub deopts: u64,
+}
+
+/// The object a guarded slot is looked up in.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Receiver {
+    /// The receiver of the compiled method.
+    Target,
+    Number,
+    True,
+    False,
+}
+
+/// A slot a send was specialized for.
+struct Guard {
+    receiver: Receiver,
+    name: SymbolId,
+    slot: ObjectRef,
+}
+
+/// The kind of a value on the stack.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Kind {
+    Locals,
+    Number,
+    Bool,
+    Nil,
+    /// A string constant, which can only name a slot.
+    Name(SymbolId),
+}
+
+impl Kind {
+    /// The type of the value in the compiled code, if it has one.
+    fn ty(self) -> Option<Type> {
+        match self {
+            Self::Number => Some(types::F64),
+            Self::Bool => Some(types::I8),
+            _ => None,
+        }
+    }
+}
+
+/// The kinds of the values before an instruction.
+#[derive(Debug, PartialEq, Clone)]
+struct State {
+    stack: Vec<Kind>,
+    /// The slots of the locals which are set on every path, by the index of the variable.
+    assigned: u64,
+}
+
+/// An instruction specialized for the kinds of its operands.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Number(f64),
+    Bool(bool),
+    /// Only changes the kinds.
+    Skip,
+    /// Pop the value and the number of values below it, then push the value back.
+    Move(u32, Option<Type>),
+    Load(usize),
+    Store(usize),
+    Binary(&'static str),
+    Compare(FloatCC),
+    Unary(&'static str),
+    Negate,
+    Not,
+    Call {
+        callee: usize,
+        argc: usize,
+    },
+    Return,
+    Jump(usize),
+    Branch(usize),
+    /// The end of the code, returning the top of the stack.
+    End,
+}
+
+/// A method lowered for the compilation.
+struct Lowered {
+    method: Rc<Block>,
+    chunk: Chunk,
+    /// The slots of the locals, the parameters first.
+    vars: Vec<SymbolId>,
+    /// The instructions with the depth of the stack before them, `None` where they're unreachable.
+    ops: Vec<Option<(Op, usize)>>,
+}
+
+/// A method compiled with the methods it calls.
+#[derive(Default)]
+struct Group {
+    methods: Vec<Lowered>,
+    guards: Vec<Guard>,
+}
+
+impl Group {
+    /// The index of the method, adding it if it's new.
+    fn add(&mut self, method: &Rc<Block>) -> Option<usize> {
+        if let Some(index) = self
+            .methods
+            .iter()
+            .position(|m| Rc::ptr_eq(&m.method, method))
+        {
+            return Some(index);
+        }
+        if method.params.len() > MAX_VARS {
+            return None;
+        }
+        self.methods.push(Lowered {
+            method: method.clone(),
+            chunk: compile(&method.body),
+            vars: method.params.clone(),
+            ops: Vec::new(),
+        });
+        Some(self.methods.len() - 1)
+    }
+
+    fn guard(&mut self, guard: Guard) {
+        let known = self.guards.iter().any(|g| {
+            g.receiver == guard.receiver && g.name == guard.name && g.slot.ptr_eq(&guard.slot)
+        });
+        if !known {
+            self.guards.push(guard);
+        }
+    }
+}
+
+/// The machine code of a method.
+struct Compiled {
+    params: usize,
+    guards: Vec<Guard>,
+    entry: Entry,
+}
+
+/// What the compiler knows about a hot method.
+struct Profile {
+    /// Kept so the address of the method isn't reused while it's profiled, without keeping the
+    /// method alive.
+    method: Weak<Block>,
+    compiled: Option<Rc<Compiled>>,
+    /// The number of the times the method was compiled.
+    compiles: u32,
+    /// Whether the method can't be compiled.
+    failed: bool,
+}
+
+/// The compiler of the hot methods to the machine code.
+pub(crate) struct Jit {
+    module: JITModule,
+    ctx: Context,
+    fctx: FunctionBuilderContext,
+    /// A number, true and false, which the slots of their protos are looked up from.
+    number: ObjectRef,
+    true_: ObjectRef,
+    false_: ObjectRef,
+    /// The built-in slots the sends are specialized for.
+    builtins: HashMap<(Receiver, SymbolId), ObjectRef>,
+    profiles: HashMap<usize, Profile>,
+    stats: JitStats,
+}
+
+impl Jit {
+    /// Create the compiler for the host, if Cranelift supports it.
+    ///
+    /// The built-in methods of the interpreter are recorded, so it should be created before they
+    /// can change.
+    pub(crate) fn new(interp: &Interpreter) -> Option<Self> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder()
+            .ok()?
+            .finish(settings::Flags::new(flags))
+            .ok()?;
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+        let mut jit = Self {
+            ctx: module.make_context(),
+            module,
+            fctx: FunctionBuilderContext::new(),
+            number: interp.number(0.0),
+            true_: interp.boolean(true),
+            false_: interp.boolean(false),
+            builtins: HashMap::new(),
+            profiles: HashMap::new(),
+            stats: JitStats::default(),
+        };
+
+        let number = BINARY
+            .iter()
+            .chain(COMPARE.iter().map(|(name, _)| name))
+            .chain(UNARY);
+        let builtins = number
+            .map(|name| (Receiver::Number, *name))
+            .chain(
+                LOCALS
+                    .iter()
+                    .chain(FORMS)
+                    .map(|name| (Receiver::Target, *name)),
+            )
+            .chain([(Receiver::True, "not"), (Receiver::False, "not")])
+            .chain(OPERATORS.iter().flat_map(|name| {
+                [Receiver::Number, Receiver::True, Receiver::False]
+                    .map(|receiver| (receiver, *name))
+            }));
+        for (receiver, name) in builtins {
+            let name = SymbolId::intern(name);
+            let obj = jit.receiver(receiver, interp.lobby());
+            if let Some((slot, _)) = obj.lookup(name) {
+                jit.builtins.insert((receiver, name), slot);
+            }
+        }
+
+        Some(jit)
+    }
+
+    pub(crate) fn stats(&self) -> JitStats {
+        self.stats
+    }
+
+    /// Run the method as the machine code if it's hot and the guards hold, making at most the
+    /// number of the activations, counting the one of the method.
+    pub(crate) fn call(
+        &mut self,
+        method: &Rc<Block>,
+        target: &ObjectRef,
+        args: &[ObjectRef],
+        budget: usize,
+    ) -> Option<Result<f64, StackOverflow>> {
+        let calls = method.calls.get().saturating_add(1);
+        method.calls.set(calls);
+        if calls < THRESHOLD {
+            return None;
+        }
+
+        let key = Rc::as_ptr(method) as usize;
+        if !self.profiles.contains_key(&key) {
+            self.profiles
+                .retain(|_, profile| profile.method.strong_count() > 0);
+        }
+        let profile = self.profiles.entry(key).or_insert_with(|| Profile {
+            method: Rc::downgrade(method),
+            compiled: None,
+            compiles: 0,
+            failed: false,
+        });
+        if profile.failed {
+            return None;
+        }
+
+        let compiled = match &profile.compiled {
+            Some(compiled) => compiled.clone(),
+            None => {
+                let compiled = self.compile(method, target).map(Rc::new);
+                let profile = self.profiles.get_mut(&key)?;
+                profile.compiles += 1;
+                profile.failed = compiled.is_none();
+                profile.compiled = compiled.clone();
+                compiled?
+            }
+        };
+
+        if !self.takes(&compiled, args) {
+            self.stats.deopts += 1;
+            return None;
+        }
+        if !self.holds(&compiled, target) {
+            self.stats.deopts += 1;
+            let profile = self.profiles.get_mut(&key)?;
+            profile.compiled = None;
+            profile.failed = profile.compiles >= MAX_COMPILES;
+            method.calls.set(0);
+            return None;
+        }
+
+        let args: Vec<f64> = args[..compiled.params]
+            .iter()
+            .filter_map(ObjectRef::as_number)
+            .collect();
+        let mut overflow = 0;
+        let budget = i64::try_from(budget).unwrap_or(i64::MAX);
+        // SAFETY: the entry was compiled for the number of the arguments, and it only reads them
+        // and writes the flag.
+        let value = unsafe { (compiled.entry)(args.as_ptr(), &mut overflow, budget) };
+        Some(if overflow == 0 {
+            Ok(value)
+        } else {
+            Err(StackOverflow)
+        })
+    }
+
+    fn receiver<'a>(&'a self, receiver: Receiver, target: &'a ObjectRef) -> &'a ObjectRef {
+        match receiver {
+            Receiver::Target => target,
+            Receiver::Number => &self.number,
+            Receiver::True => &self.true_,
+            Receiver::False => &self.false_,
+        }
+    }
+
+    /// Whether the compiled code can run with the arguments, which must be plain numbers.
+    fn takes(&self, compiled: &Compiled, args: &[ObjectRef]) -> bool {
+        let number_proto = self.number.protos()[0].clone();
+        let is_number = |arg: &ObjectRef| {
+            arg.as_number().is_some()
+                && arg.slots().is_empty()
+                && matches!(&arg.protos()[..], [proto] if proto.ptr_eq(&number_proto))
+        };
+        args.len() >= compiled.params && args[..compiled.params].iter().all(is_number)
+    }
+
+    /// Whether the slots the compiled code was specialized for are still found from the receiver.
+    fn holds(&self, compiled: &Compiled, target: &ObjectRef) -> bool {
+        compiled.guards.iter().all(|guard| {
+            self.receiver(guard.receiver, target)
+                .lookup(guard.name)
+                .is_some_and(|(slot, _)| slot.ptr_eq(&guard.slot))
+        })
+    }
+
+    /// The guard of a built-in slot, if the receiver still finds it.
+    fn builtin(&self, receiver: Receiver, name: SymbolId, target: &ObjectRef) -> Option<Guard> {
+        let builtin = self.builtins.get(&(receiver, name))?;
+        let (slot, _) = self.receiver(receiver, target).lookup(name)?;
+        slot.ptr_eq(builtin).then_some(Guard {
+            receiver,
+            name,
+            slot,
+        })
+    }
+
+    fn compile(&mut self, method: &Rc<Block>, target: &ObjectRef) -> Option<Compiled> {
+        let mut group = Group::default();
+        group.add(method)?;
+        let mut index = 0;
+        while index < group.methods.len() {
+            self.analyze(&mut group, index, target)?;
+            index += 1;
+        }
+
+        let mut ids = Vec::new();
+        for lowered in &group.methods {
+            let signature = self.signature(lowered.method.params.len());
+            ids.push(self.module.declare_anonymous_function(&signature).ok()?);
+        }
+        for index in 0..group.methods.len() {
+            self.define(&group, index, &ids)?;
+        }
+        let params = method.params.len();
+        let entry = self.define_entry(ids[0], params)?;
+        self.module.finalize_definitions().ok()?;
+        self.stats.compiled += 1;
+
+        let code = self.module.get_finalized_function(entry);
+        // SAFETY: the function was defined with the signature of the entry.
+        let entry = unsafe { std::mem::transmute::<*const u8, Entry>(code) };
+        Some(Compiled {
+            params,
+            guards: group.guards,
+            entry,
+        })
+    }
+
+    /// Find the kinds of the values on every path through the method and specialize its
+    /// instructions for them.
+    fn analyze(&self, group: &mut Group, index: usize, target: &ObjectRef) -> Option<()> {
+        let len = group.methods[index].chunk.code.len();
+        let params = group.methods[index].method.params.len();
+        let mut states: Vec<Option<State>> = vec![None; len + 1];
+        states[0] = Some(State {
+            stack: Vec::new(),
+            assigned: 1u64.checked_shl(params as u32).unwrap_or(0).wrapping_sub(1),
+        });
+
+        let mut work = vec![0];
+        while let Some(pc) = work.pop() {
+            let state = states[pc].clone()?;
+            let (_, next) = self.step(group, index, target, pc, state)?;
+            for (pc, state) in next {
+                let merged = match &states[pc] {
+                    None => state,
+                    Some(old) if old.stack != state.stack => return None,
+                    Some(old) => State {
+                        stack: state.stack,
+                        assigned: old.assigned & state.assigned,
+                    },
+                };
+                if states[pc].as_ref() != Some(&merged) {
+                    states[pc] = Some(merged);
+                    work.push(pc);
+                }
+            }
+        }
+
+        let mut ops = Vec::with_capacity(len + 1);
+        for (pc, state) in states.into_iter().enumerate() {
+            ops.push(match state {
+                Some(state) => {
+                    let depth = state.stack.len();
+                    let (op, _) = self.step(group, index, target, pc, state)?;
+                    Some((op, depth))
+                }
+                None => None,
+            });
+        }
+        group.methods[index].ops = ops;
+        Some(())
+    }
+
+    /// The specialized instruction at the `pc` with the states which follow it.
+    fn step(
+        &self,
+        group: &mut Group,
+        index: usize,
+        target: &ObjectRef,
+        pc: usize,
+        mut state: State,
+    ) -> Option<(Op, Vec<(usize, State)>)> {
+        let chunk = &group.methods[index].chunk;
+        let Some(&inst) = chunk.code.get(pc) else {
+            return (state.stack.last() == Some(&Kind::Number)).then_some((Op::End, Vec::new()));
+        };
+
+        let stack = &mut state.stack;
+        let op = match inst {
+            Instruction::Constant(constant) => match &chunk.constants[constant as usize] {
+                Constant::Number(num) => {
+                    stack.push(Kind::Number);
+                    Op::Number(*num)
+                }
+                Constant::Sequence(seq) => {
+                    stack.push(Kind::Name(SymbolId::intern(seq)));
+                    Op::Skip
+                }
+            },
+            Instruction::Nil => {
+                stack.push(Kind::Nil);
+                Op::Skip
+            }
+            Instruction::True | Instruction::False => {
+                stack.push(Kind::Bool);
+                Op::Bool(inst == Instruction::True)
+            }
+            Instruction::PushLocals => {
+                stack.push(Kind::Locals);
+                Op::Skip
+            }
+            Instruction::PushSelf | Instruction::SendLazy(_) | Instruction::MakeBlock(_) => {
+                return None
+            }
+            Instruction::Pop => {
+                stack.pop()?;
+                Op::Skip
+            }
+            // the sends are compiled for the built-in methods and the methods taking all the
+            // arguments, which the guards keep finding
+            Instruction::LazyArgs { .. } => Op::Skip,
+            // and the inlined control flow for the built-in methods
+            Instruction::Inline { message, .. } => {
+                let name = chunk.messages[message as usize].symbol.id()?;
+                let receivers: &[Receiver] = match *stack.last()? {
+                    Kind::Locals if !group.methods[index].vars.contains(&name) => {
+                        &[Receiver::Target]
+                    }
+                    Kind::Number => &[Receiver::Number],
+                    Kind::Bool => &[Receiver::True, Receiver::False],
+                    _ => return None,
+                };
+                for &receiver in receivers {
+                    group.guard(self.builtin(receiver, name, target)?);
+                }
+                Op::Skip
+            }
+            Instruction::PopUnder(count) => {
+                let value = stack.pop()?;
+                stack.truncate(stack.len().checked_sub(count as usize)?);
+                stack.push(value);
+                Op::Move(count, value.ty())
+            }
+            Instruction::Send { selector, argc } => {
+                let name = chunk.selectors[selector as usize];
+                let args = stack.split_off(stack.len().checked_sub(argc as usize)?);
+                let receiver = stack.pop()?;
+                let (op, kind) =
+                    self.send(group, index, target, receiver, name, &args, &mut state)?;
+                state.stack.push(kind);
+                op
+            }
+            Instruction::Return => {
+                return (stack.pop()? == Kind::Number).then_some((Op::Return, Vec::new()))
+            }
+            Instruction::Jump(to) => {
+                return Some((Op::Jump(to as usize), vec![(to as usize, state)]))
+            }
+            Instruction::JumpIfFalse(to) => {
+                let to = to as usize;
+                return match stack.pop()? {
+                    Kind::Bool => {
+                        Some((Op::Branch(to), vec![(pc + 1, state.clone()), (to, state)]))
+                    }
+                    // numbers are true and nil is false
+                    Kind::Number => Some((Op::Skip, vec![(pc + 1, state)])),
+                    Kind::Nil => Some((Op::Jump(to), vec![(to, state)])),
+                    _ => None,
+                };
+            }
+        };
+        Some((op, vec![(pc + 1, state)]))
+    }
+
+    /// Specialize a send for the kinds, returning the kind of its result.
+    #[allow(clippy::too_many_arguments)]
+    fn send(
+        &self,
+        group: &mut Group,
+        index: usize,
+        target: &ObjectRef,
+        receiver: Kind,
+        name: SymbolId,
+        args: &[Kind],
+        state: &mut State,
+    ) -> Option<(Op, Kind)> {
+        let selector = name.as_str();
+        match (receiver, args) {
+            (Kind::Number, [Kind::Number]) => {
+                group.guard(self.builtin(Receiver::Number, name, target)?);
+                if let Some(op) = BINARY.iter().find(|op| **op == selector) {
+                    Some((Op::Binary(op), Kind::Number))
+                } else {
+                    let (_, cc) = COMPARE.iter().find(|(op, _)| *op == selector)?;
+                    Some((Op::Compare(*cc), Kind::Bool))
+                }
+            }
+            (Kind::Number, []) => {
+                group.guard(self.builtin(Receiver::Number, name, target)?);
+                let op = UNARY.iter().find(|op| **op == selector)?;
+                Some((Op::Unary(op), Kind::Number))
+            }
+            (Kind::Bool, []) if selector == "not" => {
+                group.guard(self.builtin(Receiver::True, name, target)?);
+                group.guard(self.builtin(Receiver::False, name, target)?);
+                Some((Op::Not, Kind::Bool))
+            }
+            (Kind::Locals, _) => self.send_locals(group, index, target, name, args, state),
+            _ => None,
+        }
+    }
+
+    fn send_locals(
+        &self,
+        group: &mut Group,
+        index: usize,
+        target: &ObjectRef,
+        name: SymbolId,
+        args: &[Kind],
+        state: &mut State,
+    ) -> Option<(Op, Kind)> {
+        let vars = &mut group.methods[index].vars;
+        let assigned = |var: usize| state.assigned & (1 << var) != 0;
+        if let Some(var) = vars.iter().position(|&v| v == name) {
+            return (args.is_empty() && assigned(var)).then_some((Op::Load(var), Kind::Number));
+        }
+
+        match (name.as_str(), args) {
+            ("setSlot" | "updateSlot", &[Kind::Name(slot), Kind::Number]) => {
+                let var = match vars.iter().position(|&v| v == slot) {
+                    Some(var) if name.as_str() == "setSlot" || assigned(var) => var,
+                    None if name.as_str() == "setSlot" && vars.len() < MAX_VARS => {
+                        vars.push(slot);
+                        vars.len() - 1
+                    }
+                    _ => return None,
+                };
+                group.guard(self.builtin(Receiver::Target, name, target)?);
+                state.assigned |= 1 << var;
+                Some((Op::Store(var), Kind::Number))
+            }
+            ("-", [Kind::Number]) => {
+                group.guard(self.builtin(Receiver::Target, name, target)?);
+                Some((Op::Negate, Kind::Number))
+            }
+            ("self", _) => None,
+            _ => {
+                let (slot, _) = target.lookup(name)?;
+                let method = match &*slot.data() {
+                    Data::Block(block) if block.scope.is_none() => block.clone(),
+                    _ => return None,
+                };
+                let numbers = args.iter().all(|&arg| arg == Kind::Number);
+                if !numbers || args.len() != method.params.len() {
+                    return None;
+                }
+                let callee = group.add(&method)?;
+                group.guard(Guard {
+                    receiver: Receiver::Target,
+                    name,
+                    slot,
+                });
+                let argc = args.len();
+                Some((Op::Call { callee, argc }, Kind::Number))
+            }
+        }
+    }
+
+    /// The signature of a compiled method with the number of the parameters, followed by the
+    /// flag of the stack overflow and the number of the activations it can make.
+    fn signature(&self, params: usize) -> Signature {
+        let pointer = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params = vec![AbiParam::new(types::F64); params];
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(types::I64));
+        signature.returns.push(AbiParam::new(types::F64));
+        signature
+    }
+
+    /// Emit the instructions of a method of the group.
+    fn define(&mut self, group: &Group, index: usize, ids: &[FuncId]) -> Option<()> {
+        let lowered = &group.methods[index];
+        self.ctx.func.signature = self.signature(lowered.method.params.len());
+        let funcs: Vec<_> = ids
+            .iter()
+            .map(|&id| self.module.declare_func_in_func(id, &mut self.ctx.func))
+            .collect();
+
+        let mut b = FunctionBuilder::new(&mut self.ctx.func, &mut self.fctx);
+        let mut labels: Vec<Option<ir::Block>> = vec![None; lowered.ops.len() + 1];
+        for (pc, inst) in lowered.chunk.code.iter().enumerate() {
+            match *inst {
+                Instruction::Jump(to) | Instruction::JumpIfFalse(to) => {
+                    labels[to as usize].get_or_insert_with(|| b.create_block());
+                    labels[pc + 1].get_or_insert_with(|| b.create_block());
+                }
+                Instruction::Return => {
+                    labels[pc + 1].get_or_insert_with(|| b.create_block());
+                }
+                _ => {}
+            }
+        }
+
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        let params = b.block_params(entry).to_vec();
+        let (params, context) = params.split_at(lowered.method.params.len());
+        let (overflow, budget) = (context[0], context[1]);
+        for i in 0..lowered.vars.len() {
+            let var = Variable::new(i);
+            b.declare_var(var, types::F64);
+            let value = match params.get(i) {
+                Some(&param) => param,
+                None => b.ins().f64const(0.0),
+            };
+            b.def_var(var, value);
+        }
+        let max_depth = lowered.ops.iter().flatten().map(|&(_, depth)| depth + 1);
+        for depth in 0..max_depth.max().unwrap_or(0) {
+            b.declare_var(slot(depth, types::F64), types::F64);
+            b.declare_var(slot(depth, types::I8), types::I8);
+        }
+
+        // the activation is one too many
+        let body = b.create_block();
+        let exhausted = b.create_block();
+        b.ins().brif(budget, body, &[], exhausted, &[]);
+        b.switch_to_block(exhausted);
+        let set = b.ins().iconst(types::I8, 1);
+        b.ins().store(MemFlags::trusted(), set, overflow, 0);
+        bail(&mut b);
+        b.switch_to_block(body);
+
+        let mut filled = false;
+        for (pc, op) in lowered.ops.iter().enumerate() {
+            if let Some(label) = labels[pc] {
+                if !filled {
+                    b.ins().jump(label, &[]);
+                }
+                b.switch_to_block(label);
+                filled = false;
+            }
+            let Some((op, d)) = *op else { continue };
+
+            match op {
+                Op::Number(num) => {
+                    let value = b.ins().f64const(num);
+                    b.def_var(slot(d, types::F64), value);
+                }
+                Op::Bool(value) => {
+                    let value = b.ins().iconst(types::I8, i64::from(value));
+                    b.def_var(slot(d, types::I8), value);
+                }
+                Op::Skip | Op::Move(_, None) => {}
+                Op::Move(count, Some(ty)) => {
+                    let value = b.use_var(slot(d - 1, ty));
+                    b.def_var(slot(d - 1 - count as usize, ty), value);
+                }
+                Op::Load(var) => {
+                    let value = b.use_var(Variable::new(var));
+                    b.def_var(slot(d - 1, types::F64), value);
+                }
+                Op::Store(var) => {
+                    let value = number(&mut b, d - 1);
+                    b.def_var(Variable::new(var), value);
+                    b.def_var(slot(d - 3, types::F64), value);
+                }
+                Op::Binary(op) => {
+                    let (x, y) = (number(&mut b, d - 2), number(&mut b, d - 1));
+                    let value = match op {
+                        "+" => b.ins().fadd(x, y),
+                        "-" => b.ins().fsub(x, y),
+                        "*" => b.ins().fmul(x, y),
+                        _ => b.ins().fdiv(x, y),
+                    };
+                    b.def_var(slot(d - 2, types::F64), value);
+                }
+                Op::Compare(cc) => {
+                    let (x, y) = (number(&mut b, d - 2), number(&mut b, d - 1));
+                    let value = b.ins().fcmp(cc, x, y);
+                    b.def_var(slot(d - 2, types::I8), value);
+                }
+                Op::Unary(op) => {
+                    let x = number(&mut b, d - 1);
+                    let value = match op {
+                        "floor" => b.ins().floor(x),
+                        "ceil" => b.ins().ceil(x),
+                        "abs" => b.ins().fabs(x),
+                        "sqrt" => b.ins().sqrt(x),
+                        _ => b.ins().fneg(x),
+                    };
+                    b.def_var(slot(d - 1, types::F64), value);
+                }
+                Op::Negate => {
+                    let x = number(&mut b, d - 1);
+                    let value = b.ins().fneg(x);
+                    b.def_var(slot(d - 2, types::F64), value);
+                }
+                Op::Not => {
+                    let x = b.use_var(slot(d - 1, types::I8));
+                    let value = b.ins().bxor_imm(x, 1);
+                    b.def_var(slot(d - 1, types::I8), value);
+                }
+                Op::Call { callee, argc } => {
+                    let base = d - argc - 1;
+                    let params = group.methods[callee].method.params.len();
+                    let mut args: Vec<Value> =
+                        (0..params).map(|i| number(&mut b, base + 1 + i)).collect();
+                    args.push(overflow);
+                    args.push(b.ins().iadd_imm(budget, -1));
+                    let call = b.ins().call(funcs[callee], &args);
+                    let value = b.inst_results(call)[0];
+
+                    // leave all the activations after a stack overflow
+                    let returned = b.create_block();
+                    let overflowed = b.create_block();
+                    let flag = b.ins().load(types::I8, MemFlags::trusted(), overflow, 0);
+                    b.ins().brif(flag, overflowed, &[], returned, &[]);
+                    b.switch_to_block(overflowed);
+                    bail(&mut b);
+                    b.switch_to_block(returned);
+                    b.def_var(slot(base, types::F64), value);
+                }
+                Op::Return | Op::End => {
+                    let value = number(&mut b, d - 1);
+                    b.ins().return_(&[value]);
+                    filled = true;
+                }
+                Op::Jump(to) => {
+                    b.ins().jump(labels[to]?, &[]);
+                    filled = true;
+                }
+                Op::Branch(to) => {
+                    let cond = b.use_var(slot(d - 1, types::I8));
+                    b.ins().brif(cond, labels[pc + 1]?, &[], labels[to]?, &[]);
+                    filled = true;
+                }
+            }
+        }
+        if !filled {
+            b.ins().trap(TrapCode::UnreachableCodeReached);
+        }
+        b.seal_all_blocks();
+        b.finalize();
+
+        let res = self.module.define_function(ids[index], &mut self.ctx);
+        self.module.clear_context(&mut self.ctx);
+        res.ok()
+    }
+
+    /// Emit the entry of a method, which loads the arguments from an array.
+    fn define_entry(&mut self, method: FuncId, params: usize) -> Option<FuncId> {
+        let pointer = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(types::I64));
+        signature.returns.push(AbiParam::new(types::F64));
+        let id = self.module.declare_anonymous_function(&signature).ok()?;
+
+        self.ctx.func.signature = signature;
+        let method = self.module.declare_func_in_func(method, &mut self.ctx.func);
+        let mut b = FunctionBuilder::new(&mut self.ctx.func, &mut self.fctx);
+        let block = b.create_block();
+        b.append_block_params_for_function_params(block);
+        b.switch_to_block(block);
+        b.seal_block(block);
+        let &[array, overflow, budget] = b.block_params(block) else {
+            unreachable!("the parameters of the signature")
+        };
+        let mut args: Vec<Value> = (0..params)
+            .map(|i| {
+                b.ins()
+                    .load(types::F64, MemFlags::trusted(), array, (i * 8) as i32)
+            })
+            .collect();
+        args.extend([overflow, budget]);
+        let call = b.ins().call(method, &args);
+        let value = b.inst_results(call)[0];
+        b.ins().return_(&[value]);
+        b.finalize();
+
+        let res = self.module.define_function(id, &mut self.ctx);
+        self.module.clear_context(&mut self.ctx);
+        res.ok().map(|_| id)
+    }
+}
+
+/// Return from the function without a value, after a stack overflow.
+fn bail(b: &mut FunctionBuilder<'_>) {
+    let value = b.ins().f64const(0.0);
+    b.ins().return_(&[value]);
+}
+
+/// The number at the depth of the stack.
+fn number(b: &mut FunctionBuilder<'_>, depth: usize) -> Value {
+    b.use_var(slot(depth, types::F64))
+}
+
+/// The variable of the value at the depth of the stack.
+fn slot(depth: usize, ty: Type) -> Variable {
+    Variable::new(MAX_VARS + 2 * depth + usize::from(ty == types::I8))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use iowa_parser::{parse, MessageChain, SymbolId};
+    use iowa_runtime::{Block, Interpreter};
+
+    use super::{Jit, MAX_COMPILES, THRESHOLD};
+    use crate::Vm;
+
+    fn eval(vm: &mut Vm, source: &str) -> String {
+        let value = vm.eval(source).unwrap();
+        vm.interpreter().as_string(&value).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_compiled() {
+        let mut vm = Vm::with_output(std::io::sink());
+        let source = r#"
+            fib := method(n, if(n < 2, n, fib(n - 1) + fib(n - 2)))
+            sum := method(n, s := 0; while(n > 0, s = s + n; n = n - 1); s)
+            dist := method(x, y, d := x * x + y * y; d sqrt)
+            sign := method(x, if(x < 0 and (x != -1), - 1, if(x > 0, 1, 0)))
+        "#;
+        vm.eval(source).unwrap();
+
+        assert_eq!(eval(&mut vm, "fib(20)"), "6765");
+        assert_eq!(vm.jit_stats().compiled, 1);
+        for _ in 0..THRESHOLD {
+            vm.eval("sum(10)\ndist(3, 4)\nsign(-5)").unwrap();
+        }
+        assert_eq!(eval(&mut vm, "sum(100)"), "5050");
+        assert_eq!(eval(&mut vm, "dist(6, 8)"), "10");
+        assert_eq!(
+            eval(&mut vm, "list(sign(-5), sign(-1), sign(0), sign(7))"),
+            "list(-1, 0, 0, 1)"
+        );
+        assert_eq!(vm.jit_stats().compiled, 4);
+        assert_eq!(vm.jit_stats().deopts, 0);
+    }
+
+    #[test]
+    fn test_profiles() {
+        let interp = Interpreter::with_output(std::io::sink());
+        let mut jit = Jit::new(&interp).unwrap();
+        let method = |body| {
+            let body = parse(body).unwrap();
+            let body = body.into_iter().map(MessageChain::into_owned).collect();
+            Rc::new(Block::new(vec![SymbolId::intern("n")], body, None))
+        };
+        let args = [interp.number(3.0)];
+
+        let cold = method("n + 1");
+        assert_eq!(jit.call(&cold, interp.lobby(), &args, 1), None);
+        assert!(jit.profiles.is_empty());
+
+        let hot = method("n * 2");
+        for _ in 1..THRESHOLD {
+            jit.call(&hot, interp.lobby(), &args, 1);
+        }
+        assert_eq!(jit.call(&hot, interp.lobby(), &args, 1), Some(Ok(6.0)));
+        assert_eq!(jit.profiles.len(), 1);
+
+        // the profile of a dropped method is removed with the next one
+        drop(hot);
+        let next = method("n - 1");
+        for _ in 0..THRESHOLD {
+            jit.call(&next, interp.lobby(), &args, 1);
+        }
+        assert_eq!(jit.profiles.len(), 1);
+    }
+
+    #[test]
+    fn test_not_compiled() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("f := method(n, n print; n + 1)\ng := method(n, f(n) * 2)")
+            .unwrap();
+        for _ in 0..2 * THRESHOLD {
+            assert_eq!(eval(&mut vm, "g(1)"), "4");
+        }
+        assert_eq!(vm.jit_stats().compiled, 0);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.interpreter().set_max_depth(200);
+        vm.eval("down := method(n, if(n < 1, 0, down(n - 1) + 1))")
+            .unwrap();
+        for _ in 0..THRESHOLD {
+            vm.eval("down(3)").unwrap();
+        }
+        assert_eq!(eval(&mut vm, "down(150)"), "150");
+        assert_eq!(vm.jit_stats().compiled, 1);
+
+        // the compiled code counts its activations after the ones of the machine
+        assert_eq!(
+            eval(&mut vm, "f := method(n, down(n))\ntry(f(250)) error"),
+            "stack overflow"
+        );
+        assert_eq!(eval(&mut vm, "f(190)"), "190");
+        assert_eq!(vm.interpreter().depth(), 0);
+    }
+
+    #[test]
+    fn test_deopt() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("twice := method(n, n * 2)").unwrap();
+        for _ in 0..THRESHOLD {
+            vm.eval("twice(3)").unwrap();
+        }
+        assert_eq!(eval(&mut vm, "twice(4)"), "8");
+        assert_eq!(vm.jit_stats().compiled, 1);
+
+        // not a number, only this call runs the bytecode
+        vm.eval("Foo := Object clone\nFoo setSlot(\"*\", method(n, \"foo\"))")
+            .unwrap();
+        assert_eq!(eval(&mut vm, "twice(Foo)"), "foo");
+        assert_eq!(vm.jit_stats().deopts, 1);
+        assert_eq!(eval(&mut vm, "twice(4)"), "8");
+        assert_eq!(vm.jit_stats().deopts, 1);
+
+        // a changed method of Number drops the compiled code
+        vm.eval("Number setSlot(\"*\", method(n, \"times\"))")
+            .unwrap();
+        assert_eq!(eval(&mut vm, "twice(4)"), "times");
+        assert_eq!(vm.jit_stats().deopts, 2);
+        for _ in 0..THRESHOLD {
+            vm.eval("twice(3)").unwrap();
+        }
+        assert_eq!(eval(&mut vm, "twice(4)"), "times");
+        assert_eq!(vm.jit_stats().compiled, 1);
+    }
+
+    #[test]
+    fn test_mixed_arguments() {
+        let mut vm = Vm::with_output(std::io::sink());
+        let source = r#"
+            Foo := Object clone
+            Foo setSlot("+", method(n, self))
+            twice := method(n, n + n)
+            i := 0
+            while(i < 5000, twice(if(i % 2 == 0, 1, Foo)); i = i + 1)
+        "#;
+        vm.eval(source).unwrap();
+        assert_eq!(vm.jit_stats().compiled, 1);
+        // the calls with Foo since it was compiled at the hundredth
+        assert_eq!(vm.jit_stats().deopts, 2451);
+    }
+
+    #[test]
+    fn test_recompiles() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("down := method(n, if(n < 1, 0, down(n - 1) + 1))")
+            .unwrap();
+        for _ in 0..2 * MAX_COMPILES {
+            for _ in 0..THRESHOLD {
+                vm.eval("down(1)").unwrap();
+            }
+            // the same method in a new slot fails the guard of the call
+            vm.eval("setSlot(\"down\", getSlot(\"down\") clone)")
+                .unwrap();
+        }
+        assert_eq!(eval(&mut vm, "down(5)"), "5");
+        assert_eq!(vm.jit_stats().compiled, u64::from(MAX_COMPILES));
+    }
+}
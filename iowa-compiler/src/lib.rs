@@ -11,9 +11,24 @@
     unreachable_pub
 )]
 
-use iowa_parser::{Argument, Message, MessageChain};
+pub mod aot;
+pub mod bytecode;
+mod compiler;
+mod disassemble;
+mod jit;
+mod vm;
 
-/// Compile the io message chain into the bytecode.
-pub fn compile(chain: MessageChain) {
-    todo!()
+use iowa_parser::MessageChain;
+
+use bytecode::Chunk;
+
+pub use jit::JitStats;
+pub use vm::{CacheStats, Vm};
+
+/// Compile the io message chains into the bytecode.
+///
+/// The chains are expected to be sorted, as returned by [`parse`](iowa_parser::parse). The chunk
+/// ends with the value of the last chain.
+pub fn compile(chains: &[MessageChain<'_>]) -> Chunk {
+    compiler::Compiler::default().compile(chains)
 }
@@ -0,0 +1,886 @@
+//! The virtual machine running the bytecode.
+//!
+//! The machine shares the object model and the built-in methods with the
+//! [`Interpreter`](iowa_runtime::Interpreter), which also runs the messages sent with
+//! [`Instruction::SendLazy`] or [`Instruction::LazyArgs`] and the blocks. Each activation of a
+//! method gets its own frame with a value stack.
+//!
+//! Every send has an inline cache of the slots it found. An entry is keyed on the shape of the
+//! receiver — its only proto, as the receiver itself is checked for the slot first — and holds
+//! while the [`version`](ObjectRef::version)s of the objects the lookup looked into don't change.
+
+use std::any::Any;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::io::Write;
+use std::rc::Rc;
+
+use iowa_parser::{parse, Message, MessageChain, Span, SymbolId};
+use iowa_runtime::{
+    Activation, Block, Data, Error, EvalResult, Interpreter, ObjectRef, RuntimeError, Stop,
+};
+
+use crate::aot::{self, NativeChunk, NativeFrame};
+use crate::bytecode::{BlockKind, Chunk, Constant, Instruction};
+use crate::compile;
+use crate::jit::{Jit, JitStats, StackOverflow};
+
+/// The number of the receiver shapes an inline cache keeps.
+const CACHE_SIZE: usize = 4;
+
+/// The numbers of the sends answered by the inline caches and by the full lookups.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CacheStats {
+    /// The sends answered by a cache.
+    pub hits: u64,
+    /// The sends which looked up the slot in the protos.
+    pub misses: u64,
+}
+
+/// A slot found by a send for the proto of the receivers.
+struct CacheEntry {
+    proto: ObjectRef,
+    slot: ObjectRef,
+    /// The object which has the slot.
+    holder: ObjectRef,
+    /// The objects the lookup looked into with their versions.
+    path: Vec<(ObjectRef, u64)>,
+}
+
+impl CacheEntry {
+    fn holds(&self) -> bool {
+        self.path
+            .iter()
+            .all(|(obj, version)| obj.version() == *version)
+    }
+}
+
+/// The slots found by a send, for the protos of the receivers.
+#[derive(Default)]
+struct InlineCache {
+    entries: Vec<CacheEntry>,
+}
+
+/// A call site of [`Instruction::Send`].
+struct Site {
+    name: SymbolId,
+    /// The message of the send for the runtime methods and `call`.
+    msg: Message<'static>,
+    /// The messages following it in the chain.
+    next: Vec<Message<'static>>,
+    cache: RefCell<InlineCache>,
+}
+
+/// A chunk prepared for running.
+pub(crate) struct Code {
+    chunk: Chunk,
+    /// The objects of the constants.
+    constants: Vec<ObjectRef>,
+    /// The call sites by the index of the instruction.
+    sites: Vec<Option<Site>>,
+    /// The code of the methods and the blocks.
+    blocks: Vec<Rc<Code>>,
+    /// The code compiled ahead of time.
+    native: OnceCell<NativeChunk>,
+}
+
+impl Code {
+    fn new(chunk: Chunk, interp: &Interpreter) -> Self {
+        let constants = chunk
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Constant::Number(num) => interp.number(*num),
+                Constant::Sequence(seq) => interp.sequence(seq),
+            })
+            .collect();
+
+        let sites = chunk
+            .code
+            .iter()
+            .enumerate()
+            .map(|(i, inst)| match *inst {
+                Instruction::Send { selector, .. } => {
+                    let (msg, next) = chunk.chains[&i].split_first()?;
+                    Some(Site {
+                        name: chunk.selectors[selector as usize],
+                        msg: msg.clone(),
+                        next: next.to_vec(),
+                        cache: RefCell::default(),
+                    })
+                }
+                Instruction::Inline { .. } => {
+                    let (msg, next) = chunk.chains[&i].split_first()?;
+                    Some(Site {
+                        name: msg.symbol.id()?,
+                        msg: msg.clone(),
+                        next: next.to_vec(),
+                        cache: RefCell::default(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+
+        let blocks = chunk
+            .blocks
+            .iter()
+            .map(|block| Rc::new(Code::new(block.chunk.clone(), interp)))
+            .collect();
+
+        Self {
+            chunk,
+            constants,
+            sites,
+            blocks,
+            native: OnceCell::new(),
+        }
+    }
+
+    /// Set the native code of the chunk and of its blocks, in the order of
+    /// [`chunks`](crate::aot::chunks). Returns false if there are too few.
+    fn set_natives<'a>(&self, natives: &mut impl Iterator<Item = &'a NativeChunk>) -> bool {
+        let Some(&native) = natives.next() else {
+            return false;
+        };
+        let _ = self.native.set(native);
+        self.blocks.iter().all(|block| block.set_natives(natives))
+    }
+}
+
+/// An activation of a method or of the top-level code.
+#[derive(Clone)]
+pub(crate) struct Frame {
+    target: ObjectRef,
+    locals: ObjectRef,
+}
+
+/// Io virtual machine.
+///
+/// Compiles the code to the bytecode and runs it in the context of the `Lobby`. The hot methods
+/// are compiled to the machine code when the host is supported.
+pub struct Vm {
+    interp: Interpreter,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+    jit: Option<Jit>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    /// Create a machine printing to the standard output.
+    pub fn new() -> Self {
+        Self::with_interpreter(Interpreter::new())
+    }
+
+    /// Create a machine printing to the output.
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        Self::with_interpreter(Interpreter::with_output(output))
+    }
+
+    /// Create a machine sharing the objects with the interpreter.
+    pub fn with_interpreter(interp: Interpreter) -> Self {
+        let jit = Jit::new(&interp);
+        Self {
+            interp,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            jit,
+        }
+    }
+
+    /// Enable or disable the compilation of the hot methods to the machine code.
+    pub fn set_jit(&mut self, enabled: bool) {
+        self.jit = enabled.then(|| Jit::new(&self.interp)).flatten();
+    }
+
+    /// The interpreter with the objects.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interp
+    }
+
+    /// The numbers of the sends answered by the inline caches and by the full lookups.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+        }
+    }
+
+    /// The numbers of the methods compiled to the machine code and of the calls which left it.
+    pub fn jit_stats(&self) -> JitStats {
+        self.jit.as_ref().map(Jit::stats).unwrap_or_default()
+    }
+
+    /// Parse, compile and run the code, returning the value of the last chain.
+    pub fn eval(&mut self, source: &str) -> Result<ObjectRef, Error> {
+        self.eval_chains(&parse(source)?)
+    }
+
+    /// Compile and run the chains in the context of the `Lobby`.
+    pub fn eval_chains(&mut self, chains: &[MessageChain<'_>]) -> Result<ObjectRef, Error> {
+        let code = Rc::new(Code::new(compile(chains), &self.interp));
+        self.eval_code(&code)
+    }
+
+    /// Run the code compiled ahead of time by [`build`](crate::aot::build) from the source.
+    ///
+    /// The source is compiled again for the constants and the messages the native code refers
+    /// to, so the [`fingerprint`](aot::fingerprint) of its bytecode must be the one of the native
+    /// code.
+    pub fn eval_native(
+        &mut self,
+        source: &str,
+        fingerprint: u64,
+        natives: &[NativeChunk],
+    ) -> Result<ObjectRef, Error> {
+        let chunk = compile(&parse(source)?);
+        let matches = aot::fingerprint(&chunk) == fingerprint;
+        let code = Rc::new(Code::new(chunk, &self.interp));
+        let mut natives = natives.iter();
+        if !matches || !code.set_natives(&mut natives) || natives.next().is_some() {
+            let err = RuntimeError {
+                message: "the native code wasn't compiled from the source".to_string(),
+                span: Span::default(),
+                trace: Vec::new(),
+                exception: None,
+            };
+            return Err(err.into());
+        }
+        self.eval_code(&code)
+    }
+
+    fn eval_code(&mut self, code: &Rc<Code>) -> Result<ObjectRef, Error> {
+        let lobby = self.interp.lobby().clone();
+        let frame = Frame {
+            target: lobby.clone(),
+            locals: lobby,
+        };
+
+        match self.run(code, &frame).map_err(Stop::outside_loop) {
+            Ok(value) | Err(Stop::Return(value, _)) => Ok(value),
+            Err(Stop::Break(..) | Stop::Continue(_)) => unreachable!("they're errors now"),
+            Err(Stop::Error(err)) => Err(err.into()),
+            Err(Stop::Exit(status)) => Err(Error::Exit(status)),
+        }
+    }
+
+    fn run(&mut self, code: &Rc<Code>, frame: &Frame) -> EvalResult {
+        if let Some(&native) = code.native.get() {
+            return self.run_native(native, code, frame);
+        }
+
+        let mut stack: Vec<ObjectRef> = Vec::new();
+        let mut pc = 0;
+
+        while let Some(&inst) = code.chunk.code.get(pc) {
+            match inst {
+                Instruction::Return => return Ok(stack.pop().unwrap()),
+                Instruction::Jump(target) => pc = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    let cond = stack.pop().unwrap();
+                    pc = if self.interp.is_true(&cond) {
+                        pc + 1
+                    } else {
+                        target as usize
+                    };
+                }
+                Instruction::LazyArgs { end, .. } | Instruction::Inline { end, .. } => {
+                    pc = if self.lazy_args(code, frame, &mut stack, pc)? {
+                        end as usize
+                    } else {
+                        pc + 1
+                    };
+                }
+                _ => {
+                    self.exec(code, frame, &mut stack, pc)?;
+                    pc += 1;
+                }
+            }
+        }
+
+        Ok(stack.pop().unwrap_or_else(|| self.interp.nil()))
+    }
+
+    /// Run the compiled code of the chunk, which calls back to run the instructions.
+    fn run_native(&mut self, native: NativeChunk, code: &Rc<Code>, frame: &Frame) -> EvalResult {
+        let mut native_frame = NativeFrame {
+            vm: self,
+            code: code.clone(),
+            frame: frame.clone(),
+            stack: Vec::new(),
+            stop: None,
+        };
+        // SAFETY: the function was compiled from the chunk of the code, and the machine isn't used
+        // until it returns.
+        unsafe { native(&mut native_frame) };
+
+        match native_frame.stop {
+            Some(stop) => Err(stop),
+            None => Ok(native_frame
+                .stack
+                .pop()
+                .unwrap_or_else(|| self.interp.nil())),
+        }
+    }
+
+    /// Run an instruction which doesn't change the control flow.
+    pub(crate) fn exec(
+        &mut self,
+        code: &Rc<Code>,
+        frame: &Frame,
+        stack: &mut Vec<ObjectRef>,
+        pc: usize,
+    ) -> Result<(), Stop> {
+        match code.chunk.code[pc] {
+            Instruction::Constant(index) => stack.push(code.constants[index as usize].clone()),
+            Instruction::Nil => stack.push(self.interp.nil()),
+            Instruction::True => stack.push(self.interp.boolean(true)),
+            Instruction::False => stack.push(self.interp.boolean(false)),
+            Instruction::PushSelf => stack.push(frame.target.clone()),
+            Instruction::PushLocals => stack.push(frame.locals.clone()),
+            Instruction::Pop => {
+                stack.pop();
+            }
+            Instruction::PopUnder(count) => {
+                let value = stack.pop().unwrap();
+                stack.truncate(stack.len() - count as usize);
+                stack.push(value);
+            }
+            Instruction::Send { argc, .. } => {
+                let args = stack.split_off(stack.len() - argc as usize);
+                let target = stack.pop().unwrap();
+                let site = code.sites[pc].as_ref().unwrap();
+                let value = self.send(site, &target, &args, &frame.locals)?;
+                stack.push(value);
+            }
+            Instruction::SendLazy(index) => {
+                let target = stack.pop().unwrap();
+                let msg = &code.chunk.messages[index as usize];
+                let next = &code.chunk.chains[&pc][1..];
+                let value = self
+                    .interp
+                    .send_in_chain(&target, msg, next, &frame.locals)?;
+                stack.push(value);
+            }
+            Instruction::MakeBlock(index) => {
+                let block_code = &code.chunk.blocks[index as usize];
+                let scope = match block_code.kind {
+                    BlockKind::Method => None,
+                    BlockKind::Block => Some(frame.locals.clone()),
+                };
+                let block = Block::new(block_code.params.clone(), block_code.body.clone(), scope);
+                let compiled: Rc<dyn Any> = code.blocks[index as usize].clone();
+                let _ = block.compiled.set(compiled);
+                stack.push(self.interp.block(block));
+            }
+            Instruction::Return
+            | Instruction::Jump(_)
+            | Instruction::JumpIfFalse(_)
+            | Instruction::LazyArgs { .. }
+            | Instruction::Inline { .. } => {
+                unreachable!("control flow is run by the caller")
+            }
+        }
+        Ok(())
+    }
+
+    /// Run [`Instruction::LazyArgs`] or [`Instruction::Inline`], returning whether it sent the
+    /// message.
+    pub(crate) fn lazy_args(
+        &mut self,
+        code: &Rc<Code>,
+        frame: &Frame,
+        stack: &mut Vec<ObjectRef>,
+        pc: usize,
+    ) -> Result<bool, Stop> {
+        let target = stack.last().unwrap();
+        let (message, site, eager) = match code.chunk.code[pc] {
+            Instruction::LazyArgs { message, end } => {
+                let send = end as usize - 1;
+                let Instruction::Send { argc, .. } = code.chunk.code[send] else {
+                    unreachable!("the arguments aren't followed by a send")
+                };
+                let site = code.sites[send].as_ref().unwrap();
+                let eager =
+                    self.lookup(site, target)
+                        .is_some_and(|(slot, _)| match &*slot.data() {
+                            Data::Native(_) => true,
+                            Data::Block(block) => {
+                                block.scope.is_none() && block.params.len() >= argc as usize
+                            }
+                            _ => false,
+                        });
+                (message, site, eager)
+            }
+            Instruction::Inline { message, .. } => {
+                let site = code.sites[pc].as_ref().unwrap();
+                let builtin = self.lookup(site, target).is_some_and(|(slot, _)| {
+                    self.interp.is_builtin(&slot, site.name)
+                        // the `self` of the locals is the receiver of the activation
+                        || site.name.as_str() == "self" && slot.ptr_eq(&frame.target)
+                });
+                (message, site, builtin)
+            }
+            _ => unreachable!("not a check of the arguments"),
+        };
+        if eager {
+            return Ok(false);
+        }
+
+        let target = stack.pop().unwrap();
+        let msg = &code.chunk.messages[message as usize];
+        let value = self
+            .interp
+            .send_in_chain(&target, msg, &site.next, &frame.locals)?;
+        stack.push(value);
+        Ok(true)
+    }
+
+    fn send(
+        &mut self,
+        site: &Site,
+        target: &ObjectRef,
+        args: &[ObjectRef],
+        locals: &ObjectRef,
+    ) -> EvalResult {
+        let Some((slot, holder)) = self.lookup(site, target) else {
+            return Err(self.interp.does_not_respond(target, &site.msg));
+        };
+        let target = &self.interp.receiver(target, &slot, &holder);
+
+        let method = match &*slot.data() {
+            Data::Native(_) => None,
+            Data::Block(block) if block.scope.is_none() => Some(block.clone()),
+            _ => return Ok(slot.clone()),
+        };
+
+        match method {
+            Some(method) => self.call(&method, target, args, locals, site),
+            None => {
+                let act = Activation {
+                    target: target.clone(),
+                    locals: locals.clone(),
+                    msg: &site.msg,
+                    next: &site.next,
+                    args: Some(args),
+                };
+                self.interp.activate(&slot, &act)
+            }
+        }
+    }
+
+    /// Find the slot with the object which has it, using the inline cache of the site.
+    fn lookup(&self, site: &Site, target: &ObjectRef) -> Option<(ObjectRef, ObjectRef)> {
+        if let Some(slot) = target.local_slot(site.name) {
+            return Some((slot, target.clone()));
+        }
+
+        let protos = target.protos();
+        let [proto] = &protos[..] else {
+            drop(protos);
+            self.misses.set(self.misses.get() + 1);
+            return target.lookup(site.name);
+        };
+
+        let mut cache = site.cache.borrow_mut();
+        if let Some(index) = cache.entries.iter().position(|e| e.proto.ptr_eq(proto)) {
+            let entry = &cache.entries[index];
+            if entry.holds() {
+                self.hits.set(self.hits.get() + 1);
+                return Some((entry.slot.clone(), entry.holder.clone()));
+            }
+            cache.entries.swap_remove(index);
+        }
+
+        self.misses.set(self.misses.get() + 1);
+        let mut path = Vec::new();
+        let (slot, holder) =
+            proto.lookup_visiting(site.name, |obj| path.push((obj.clone(), obj.version())))?;
+        if cache.entries.len() < CACHE_SIZE {
+            cache.entries.push(CacheEntry {
+                proto: proto.clone(),
+                slot: slot.clone(),
+                holder: holder.clone(),
+                path,
+            });
+        }
+        Some((slot, holder))
+    }
+
+    /// Run the method with the arguments of the message of the site from the sender's locals.
+    fn call(
+        &mut self,
+        method: &Rc<Block>,
+        target: &ObjectRef,
+        args: &[ObjectRef],
+        sender: &ObjectRef,
+        site: &Site,
+    ) -> EvalResult {
+        self.interp.enter(&site.msg)?;
+        let res = self.activation(method, target, args, sender, site);
+        self.interp.leave();
+        res
+    }
+
+    /// Run the counted activation of the method, as the machine code if the JIT compiled it.
+    fn activation(
+        &mut self,
+        method: &Rc<Block>,
+        target: &ObjectRef,
+        args: &[ObjectRef],
+        sender: &ObjectRef,
+        site: &Site,
+    ) -> EvalResult {
+        // the activations the compiled code can make, counting this one
+        let budget = self.interp.max_depth() - self.interp.depth() + 1;
+        let compiled = self
+            .jit
+            .as_mut()
+            .and_then(|jit| jit.call(method, target, args, budget));
+        match compiled {
+            Some(Ok(value)) => return Ok(self.interp.number(value)),
+            Some(Err(StackOverflow)) => return Err(self.interp.stack_overflow(&site.msg)),
+            None => {}
+        }
+
+        let code = self.code(method);
+        let locals = self
+            .interp
+            .locals(method, target, sender, &site.msg, &site.next);
+        for (i, &param) in method.params.iter().enumerate() {
+            let value = args.get(i).cloned().unwrap_or_else(|| self.interp.nil());
+            locals.set_slot(param, value);
+        }
+
+        let frame = Frame {
+            target: target.clone(),
+            locals,
+        };
+        let res = self.run(&code, &frame);
+        frame.locals.end_activation();
+        match res {
+            Err(Stop::Return(value, Some(home))) if home.ptr_eq(&frame.locals) => Ok(value),
+            res => res.map_err(|stop| stop.outside_loop().unwind(&site.msg)),
+        }
+    }
+
+    /// The code of the method, compiling it if it was made by the interpreter.
+    fn code(&self, method: &Block) -> Rc<Code> {
+        let compiled = method.compiled.get_or_init(|| {
+            let chunk = compile(&method.body);
+            Rc::new(Code::new(chunk, &self.interp))
+        });
+        match compiled.clone().downcast::<Code>() {
+            Ok(code) => code,
+            // compiled by another engine
+            Err(_) => Rc::new(Code::new(compile(&method.body), &self.interp)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// An output which can be read after it's given to the machine.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn eval(vm: &mut Vm, source: &str) -> String {
+        let value = vm.eval(source).unwrap();
+        vm.interpreter().as_string(&value).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_run() {
+        let mut vm = Vm::with_output(std::io::sink());
+        let cases = [
+            ("1 + 2 * 3", "7"),
+            ("x := 1\nx = x + 1\nx", "2"),
+            ("if(x > 1, \"big\", \"small\")", "big"),
+            ("if(false, 1)", "false"),
+            ("nil or (1 and 2)", "true"),
+            ("i := 0\nwhile(i < 5, i = i + 1)\ni", "5"),
+            (
+                "i := 0\nloop(i = i + 1; if(i == 3, break(list(i, i) size)))",
+                "2",
+            ),
+            (
+                "s := 0\nlist(1, 2, 3) foreach(n, if(n == 2, continue); s = s + n)\ns",
+                "4",
+            ),
+            (
+                "f := method(n, if(n < 1, return 0); n + f(n - 1))\nf(4)",
+                "10",
+            ),
+            ("y := 1\nb := block(z, y + z)\ny = 10\nb call(2)", "12"),
+            (
+                "Foo := Object clone do(bar := method(self))\nFoo bar == Foo",
+                "true",
+            ),
+            (
+                "g := method(list(1, 2) foreach(n, if(n == 2, return n * 10)); 0)\ng",
+                "20",
+            ),
+            (
+                "each := method(b, b call(1); 0)\nh := method(each(block(n, return n + 10)); 2)\nh",
+                "11",
+            ),
+            (
+                "mk := method(block(return 5))\nb := mk\ng := method(b call; 7)\nlist(g, b call)",
+                "list(7, 5)",
+            ),
+            (
+                "counter := method(c := 0; block(c = c + 1))\nk := counter\nk call\nk call",
+                "2",
+            ),
+            (
+                "Foo inc := method(count = count + 1)\nFoo count := 0\nfoo := Foo clone\nfoo inc\nlist(foo count, Foo count)",
+                "list(1, 0)",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(&mut vm, source), expected, "{source}");
+        }
+
+        let err = vm.eval("1\n  2 bar").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: Number does not respond to 'bar'\n --> 2:5"
+        );
+
+        let Err(Error::Runtime(err)) = vm.eval("f := method(break(1))\nloop(f)") else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "'break' outside of a loop");
+        let names: Vec<_> = err.trace.iter().map(|site| site.name.as_str()).collect();
+        assert_eq!(names, ["break", "f"]);
+    }
+
+    #[test]
+    fn test_locals() {
+        let mut vm = Vm::with_output(std::io::sink());
+        let Err(Error::Runtime(err)) =
+            vm.eval("f := method(secret := 42; g)\ng := method(secret)\nf")
+        else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "Lobby does not respond to 'secret'");
+        let cases = [
+            (
+                "x := 1\nf := method(x := 2; g; x)\ng := method(x = 3)\nlist(f, x)",
+                "list(2, 3)",
+            ),
+            (
+                "Foo := Object clone\nFoo set := method(self val := 5)\nFoo run := method(set)\nFoo run\nFoo val",
+                "5",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(&mut vm, source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.set_jit(false);
+        vm.interpreter().set_max_depth(100);
+        vm.eval("f := method(n, f(n + 1))\ng := method(n, if(n > 0, g(n - 1), 0))")
+            .unwrap();
+
+        let Err(Error::Runtime(err)) = vm.eval("f(0)") else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "stack overflow");
+        assert_eq!(err.trace.len(), 101);
+        assert_eq!(eval(&mut vm, "try(f(0)) error"), "stack overflow");
+        assert_eq!(eval(&mut vm, "g(99)"), "0");
+        assert_eq!(eval(&mut vm, "try(g(100)) error"), "stack overflow");
+        // blocks count too
+        assert_eq!(
+            eval(
+                &mut vm,
+                "b := block(n, b call(n + 1))\ntry(b call(0)) error"
+            ),
+            "stack overflow"
+        );
+        assert_eq!(vm.interpreter().depth(), 0);
+    }
+
+    #[test]
+    fn test_overridden_forms() {
+        let cases = [
+            (
+                "Query := Object clone\nQuery setSlot(\"and\", method(o, \"custom and\"))\nQuery and(1)",
+                "custom and",
+            ),
+            (
+                "Query := Object clone\nQuery setSlot(\"||\", method(o, o * 2))\nQuery || 2",
+                "4",
+            ),
+            (
+                "Foo := Object clone\nFoo if := method(c, t, \"custom if\")\nFoo run := method(if(true, 1, 2))\nFoo run",
+                "custom if",
+            ),
+            (
+                "Foo := Object clone\nFoo setSlot(\"return\", method(v, v))\nFoo run := method(return 1; 2)\nFoo run",
+                "2",
+            ),
+            (
+                "Foo := Object clone\nFoo while := method(c, b, \"custom while\")\nFoo run := method(while(true, 1))\nFoo run",
+                "custom while",
+            ),
+            ("self := 3\nself + 1", "4"),
+            // the compiled code finds the new slot
+            (
+                "t := method(true and(false))\na := t\ntrue setSlot(\"and\", method(o, \"custom\"))\nlist(a, t)",
+                "list(false, custom)",
+            ),
+        ];
+        for (source, expected) in cases {
+            let mut interp = Interpreter::with_output(std::io::sink());
+            let value = interp.eval(source).unwrap();
+            assert_eq!(&*interp.as_string(&value).unwrap(), expected, "{source}");
+            let mut vm = Vm::with_output(std::io::sink());
+            assert_eq!(eval(&mut vm, source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_lazy_args() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("unless := method(c, if(c, nil, call evalArgAt(1)))")
+            .unwrap();
+        vm.eval("myWhile := method(loop(if(call evalArgAt(0), call evalArgAt(1), break)))")
+            .unwrap();
+        let cases = [
+            (
+                "n := 0\nunless(true, n = 1)\nunless(false, n = n + 2)\nn",
+                "2",
+            ),
+            ("i := 0\nmyWhile(i < 3, i = i + 1)\ni", "3"),
+            ("f := method(call argAt(0) code)\nf(x y)", "x y"),
+            ("g := method(a, a + call argCount)\ng(1, missing)", "3"),
+            ("message(foo bar(1)) next arguments first code", "1"),
+            ("h := method(call sender)\nh == Lobby", "true"),
+            (
+                "k := method(call message next name)\nk asString",
+                "asString",
+            ),
+            ("k := method(call message arguments size)\nk(1, 2)", "2"),
+            ("k := method(call message == call message)\nk", "true"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(&mut vm, source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("check := method(n, if(n > 1, Exception raise(\"big\")); n)")
+            .unwrap();
+        let cases = [
+            ("try(check(1))", "nil"),
+            ("try(check(2)) error", "big"),
+            (
+                "e := try(check(3))\ne catch(Exception, x, x stack size)",
+                "nil",
+            ),
+            ("x stack", "list(raise at 1:40, check at 1:10)"),
+            (
+                "r := list()\ntry(ensure(check(5), r append(1)))\nr",
+                "list(1)",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(&mut vm, source), expected, "{source}");
+        }
+
+        let Err(Error::Runtime(err)) = vm.eval("f := method(check(4))\nf") else {
+            panic!("expected an exception");
+        };
+        let names: Vec<_> = err.trace.iter().map(|site| site.name.as_str()).collect();
+        assert_eq!(names, ["raise", "check", "f"]);
+    }
+
+    #[test]
+    fn test_inline_caches() {
+        let mut vm = Vm::with_output(std::io::sink());
+        vm.eval("sum := method(n, s := 0; while(n > 0, s = s + n; n = n - 1); s)")
+            .unwrap();
+
+        let before = vm.stats();
+        assert_eq!(eval(&mut vm, "sum(100)"), "5050");
+        let after = vm.stats();
+        // `>`, `+`, `-` and `updateSlot` are looked up once and cached afterwards
+        assert!(after.misses - before.misses <= 8, "{after:?}");
+        assert!(after.hits - before.hits >= 4 * 99, "{after:?}");
+
+        // the slots set in the Lobby and in the locals of a block don't drop the other entries
+        let cases = [
+            "i := 0\nwhile(i < 1000, i = i + 1)",
+            "f := method(i := 0; b := block(i); while(i < 1000, i = i + 1))\nf",
+        ];
+        for source in cases {
+            let before = vm.stats();
+            vm.eval(source).unwrap();
+            let after = vm.stats();
+            assert!(after.misses - before.misses <= 16, "{source}: {after:?}");
+            assert!(after.hits - before.hits >= 3 * 999, "{source}: {after:?}");
+        }
+    }
+
+    #[test]
+    fn test_cache_invalidation() {
+        let output = Output::default();
+        let mut vm = Vm::with_output(output.clone());
+        let source = r#"
+            C := Object clone
+            C name := "c"
+            B := Object clone
+            B name := "b"
+            A := C clone
+            a := A clone
+            describe := method(x, x name println)
+            describe(a)
+        "#;
+        vm.eval(source).unwrap();
+        // a changed slot in a proto further up
+        vm.eval("C name := \"C\"\ndescribe(a)").unwrap();
+        // a new slot in the proto
+        vm.eval("A name := \"A\"\ndescribe(a)").unwrap();
+        // changed protos
+        vm.eval("A setProto(B)\nA removed := true\ndescribe(A clone)")
+            .unwrap();
+        vm.eval("a setProto(C)\ndescribe(a)").unwrap();
+        // a slot of the receiver itself
+        vm.eval("a name := \"own\"\ndescribe(a)").unwrap();
+        // another receiver shape
+        vm.eval("Number name := \"number\"\ndescribe(1)").unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&output.0.borrow()),
+            "c\nC\nA\nA\nC\nown\nnumber\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use iowa_compiler::Vm;
+
+/// An output which can be read after it's given to the machine.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const ACK: &str = r#"
+    ack := method(m, n,
+      if (m < 1, return n + 1)
+      if (n < 1, return ack(m - 1, 1))
+      return ack(m - 1, ack(m, n - 1))
+    )
+"#;
+
+/// Run `ack` and return the time it took.
+fn time(vm: &mut Vm, call: &str, expected: f64) -> Duration {
+    let start = Instant::now();
+    let res = vm.eval(call).unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(res.as_number(), Some(expected));
+    elapsed
+}
+
+#[test]
+fn test_ackermann() {
+    let input = r#"
+    #!/usr/bin/env io
+
+    ack := method(m, n,
+      //writeln("ack(", m, ",", n, ")")
+      if (m < 1, return n + 1)
+      if (n < 1, return ack(m - 1, 1))
+      return ack(m - 1, ack(m, n - 1))
+    )
+
+    ack(3, 4) print
+    "\n" print
+    "#;
+
+    let output = Output::default();
+    let mut vm = Vm::with_output(output.clone());
+    vm.eval(input).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "125\n");
+
+    let res = vm.eval("ack(3, 4)").unwrap();
+    assert_eq!(res.as_number(), Some(125.0));
+}
+
+#[test]
+fn test_ackermann_jit() {
+    let mut vm = Vm::with_output(io::sink());
+    vm.eval(ACK).unwrap();
+    time(&mut vm, "ack(3, 4)", 125.0);
+    assert_eq!(vm.jit_stats().compiled, 1);
+    time(&mut vm, "ack(3, 10)", 8189.0);
+    assert_eq!(vm.jit_stats().compiled, 1);
+    assert_eq!(vm.jit_stats().deopts, 0);
+}
+
+#[test]
+#[ignore = "compares the times, run it with `cargo test --release -- --ignored`"]
+fn test_ackermann_speed() {
+    let mut vm = Vm::with_output(io::sink());
+    vm.set_jit(false);
+    vm.eval(ACK).unwrap();
+    let interpreted = time(&mut vm, "ack(3, 6)", 509.0);
+
+    let mut vm = Vm::with_output(io::sink());
+    vm.eval(ACK).unwrap();
+    time(&mut vm, "ack(3, 4)", 125.0);
+    let compiled = time(&mut vm, "ack(3, 6)", 509.0);
+    assert!(
+        compiled * 10 < interpreted,
+        "{compiled:?} with the JIT, {interpreted:?} without"
+    );
+}
@@ -0,0 +1,346 @@
+//! Analysis of the tree: slot definitions, names under the cursor and semantic tokens.
+
+use iowa_parser::visit::{self, Visit};
+use iowa_parser::{Message, MessageChain, Span, Symbol};
+
+/// The messages the assignments to slots are rewritten to.
+const SLOT_SETTERS: [&str; 2] = ["setSlot", "newSlot"];
+
+/// A `name := method(...)` slot definition.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Definition {
+    pub(crate) name: String,
+    /// The location of the slot name.
+    pub(crate) name_span: Span,
+    /// The location of the whole definition.
+    pub(crate) span: Span,
+    /// The argument names of the method.
+    pub(crate) params: Vec<String>,
+    /// The methods defined in the body.
+    pub(crate) children: Vec<Definition>,
+}
+
+impl Definition {
+    /// The signature shown on hover.
+    pub(crate) fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+}
+
+/// Collect the method slot definitions of the chains.
+pub(crate) fn definitions(chains: &[MessageChain<'_>]) -> Vec<Definition> {
+    let mut out = Vec::new();
+    for chain in chains {
+        for msg in chain.iter() {
+            collect(msg, &mut out);
+        }
+    }
+    out
+}
+
+fn collect(msg: &Message<'_>, out: &mut Vec<Definition>) {
+    match method_slot(msg) {
+        Some((name, name_span, method)) => {
+            let mut children = Vec::new();
+            for arg in &method.args {
+                for msg in arg.iter().flat_map(|chain| chain.iter()) {
+                    collect(msg, &mut children);
+                }
+            }
+
+            // the last argument is the body
+            let params = method
+                .args
+                .split_last()
+                .map_or(&[][..], |(_, params)| params);
+            out.push(Definition {
+                name: name.to_string(),
+                name_span,
+                span: name_span.merge(method.span),
+                params: params
+                    .iter()
+                    .filter_map(|arg| match arg.as_slice() {
+                        [chain] => match chain.as_slice() {
+                            [param] => identifier(param).map(str::to_string),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect(),
+                children,
+            });
+        }
+        None => {
+            for msg in msg
+                .args
+                .iter()
+                .flat_map(|arg| arg.iter())
+                .flat_map(|chain| chain.iter())
+            {
+                collect(msg, out);
+            }
+        }
+    }
+}
+
+/// Match `setSlot("name", ...)`, which the assignments are rewritten to.
+fn slot_name<'t>(msg: &'t Message<'_>) -> Option<(&'t str, Span)> {
+    if !SLOT_SETTERS.contains(&identifier(msg)?) {
+        return None;
+    }
+
+    match msg.args.first()?.as_slice() {
+        [chain] => match chain.as_slice() {
+            [Message {
+                symbol: Symbol::Quote(quote),
+                span,
+                ..
+            }] if !span.is_dummy() => Some((quote.as_str(), *span)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Match `setSlot("name", method(...))`.
+fn method_slot<'t>(msg: &'t Message<'_>) -> Option<(&'t str, Span, &'t Message<'t>)> {
+    let (name, span) = slot_name(msg)?;
+    let [_, value] = msg.args.as_slice() else {
+        return None;
+    };
+    let method = value.first()?.first()?;
+    (identifier(method)? == "method").then_some((name, span, method))
+}
+
+fn identifier<'t>(msg: &'t Message<'_>) -> Option<&'t str> {
+    match &msg.symbol {
+        Symbol::Identifier(ident) => Some(ident.as_str()),
+        _ => None,
+    }
+}
+
+/// Find the name of the message or the slot at the offset along with its location.
+pub(crate) fn name_at(
+    source: &str,
+    chains: &[MessageChain<'_>],
+    offset: usize,
+) -> Option<(String, Span)> {
+    let mut finder = NameFinder {
+        source,
+        offset,
+        found: None,
+    };
+    for chain in chains {
+        finder.visit_chain(chain);
+    }
+    finder.found
+}
+
+struct NameFinder<'s> {
+    source: &'s str,
+    offset: usize,
+    found: Option<(String, Span)>,
+}
+
+impl<'a> Visit<'a> for NameFinder<'_> {
+    fn visit_message(&mut self, msg: &Message<'a>) {
+        let name = match &msg.symbol {
+            Symbol::Identifier(ident) => Some(ident.as_str()),
+            // the slot name of an assignment
+            Symbol::Quote(quote) => Some(quote.as_str()),
+            _ => None,
+        };
+
+        if let Some(name) = name {
+            let span = token_span(msg.span, name.len());
+            if span.start <= self.offset
+                && self.offset <= span.end
+                && self.source.get(span.start..span.end) == Some(name)
+            {
+                self.found = Some((name.to_string(), span));
+            }
+        }
+        visit::walk_message(self, msg);
+    }
+}
+
+/// The span of the message's symbol, without the arguments.
+fn token_span(span: Span, len: usize) -> Span {
+    Span {
+        end: span.start + len,
+        ..span
+    }
+}
+
+/// The token types of [`semantic_tokens`], in the order of the legend.
+pub(crate) const TOKEN_TYPES: [&str; 5] = ["variable", "method", "operator", "number", "string"];
+/// The token modifiers of [`semantic_tokens`], in the order of the legend.
+pub(crate) const TOKEN_MODIFIERS: [&str; 1] = ["definition"];
+
+const VARIABLE: u32 = 0;
+const METHOD: u32 = 1;
+const OPERATOR: u32 = 2;
+const NUMBER: u32 = 3;
+const STRING: u32 = 4;
+const DEFINITION: u32 = 1 << 0;
+
+/// A classified part of the source.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Token {
+    pub(crate) span: Span,
+    pub(crate) kind: u32,
+    pub(crate) modifiers: u32,
+}
+
+/// Classify the messages by their symbols, sorted by the location.
+pub(crate) fn semantic_tokens(source: &str, chains: &[MessageChain<'_>]) -> Vec<Token> {
+    let mut collector = TokenCollector {
+        source,
+        tokens: Vec::new(),
+    };
+    for chain in chains {
+        collector.visit_chain(chain);
+    }
+
+    let mut tokens = collector.tokens;
+    tokens.sort_by_key(|token| token.span.start);
+    // the rewritten compound assignments repeat the target
+    tokens.dedup_by_key(|token| token.span.start);
+    tokens
+}
+
+struct TokenCollector<'s> {
+    source: &'s str,
+    tokens: Vec<Token>,
+}
+
+impl TokenCollector<'_> {
+    /// Add the token if it's really in the source and not added by the rewrites.
+    fn push(&mut self, span: Span, text: &str, kind: u32, modifiers: u32) {
+        if !span.is_dummy() && self.source.get(span.start..span.end) == Some(text) {
+            self.tokens.push(Token {
+                span,
+                kind,
+                modifiers,
+            });
+        }
+    }
+}
+
+impl<'a> Visit<'a> for TokenCollector<'_> {
+    fn visit_message(&mut self, msg: &Message<'a>) {
+        if let Some((name, span, _)) = method_slot(msg) {
+            self.push(span, name, METHOD, DEFINITION);
+        } else if let Some((name, span)) = slot_name(msg) {
+            self.push(span, name, VARIABLE, DEFINITION);
+        }
+
+        let source = self.source;
+        match &msg.symbol {
+            Symbol::Identifier(ident) => {
+                let kind = if msg.args.is_empty() {
+                    VARIABLE
+                } else {
+                    METHOD
+                };
+                self.push(
+                    token_span(msg.span, ident.as_str().len()),
+                    ident.as_str(),
+                    kind,
+                    0,
+                );
+            }
+            Symbol::Operator(op) => {
+                self.push(
+                    token_span(msg.span, op.symbol().len()),
+                    op.symbol(),
+                    OPERATOR,
+                    0,
+                );
+            }
+            Symbol::Number(_) => {
+                let text = source.get(msg.span.start..msg.span.end).unwrap_or_default();
+                self.push(msg.span, text, NUMBER, 0);
+            }
+            Symbol::Quote(_) => {
+                let text = source.get(msg.span.start..msg.span.end).unwrap_or_default();
+                if text.starts_with('"') {
+                    self.push(msg.span, text, STRING, 0);
+                }
+            }
+            Symbol::Error => {}
+        }
+        visit::walk_message(self, msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iowa_parser::parse;
+
+    const SOURCE: &str =
+        "ack := method(m, n,\n  helper ::= method(x, x)\n  ack(m - 1, \"n\")\n)\nlist(0x1) count += 1";
+
+    #[test]
+    fn test_definitions() {
+        let chains = parse(SOURCE).unwrap();
+        let defs = definitions(&chains);
+
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].signature(), "ack(m, n)");
+        assert_eq!((defs[0].name_span.start, defs[0].name_span.end), (0, 3));
+        assert_eq!((defs[0].span.start, defs[0].span.end), (0, 65));
+        assert_eq!(defs[0].children.len(), 1);
+        assert_eq!(defs[0].children[0].signature(), "helper(x)");
+    }
+
+    #[test]
+    fn test_name_at() {
+        let chains = parse(SOURCE).unwrap();
+        let name = |offset| name_at(SOURCE, &chains, offset).map(|(name, span)| (name, span.start));
+
+        assert_eq!(name(1), Some(("ack".to_string(), 0)));
+        assert_eq!(name(50), Some(("ack".to_string(), 48)));
+        assert_eq!(name(7), Some(("method".to_string(), 7)));
+        assert_eq!(name(5), None);
+        // a string isn't a name
+        assert_eq!(name(60), None);
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let chains = parse(SOURCE).unwrap();
+        let tokens: Vec<_> = semantic_tokens(SOURCE, &chains)
+            .into_iter()
+            .map(|token| {
+                let text = &SOURCE[token.span.start..token.span.end];
+                (text, TOKEN_TYPES[token.kind as usize], token.modifiers)
+            })
+            .collect();
+
+        assert_eq!(
+            tokens,
+            [
+                ("ack", "method", DEFINITION),
+                ("method", "method", 0),
+                ("m", "variable", 0),
+                ("n", "variable", 0),
+                ("helper", "method", DEFINITION),
+                ("method", "method", 0),
+                ("x", "variable", 0),
+                ("x", "variable", 0),
+                ("ack", "method", 0),
+                ("m", "variable", 0),
+                ("-", "operator", 0),
+                ("1", "number", 0),
+                ("\"n\"", "string", 0),
+                ("list", "method", 0),
+                ("0x1", "number", 0),
+                ("count", "variable", 0),
+                ("+", "operator", 0),
+                ("1", "number", 0),
+            ]
+        );
+    }
+}
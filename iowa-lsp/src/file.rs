@@ -0,0 +1,183 @@
+//! Source files and the conversion between the byte offsets and the protocol positions.
+
+use iowa_parser::incremental::{Document, TextEdit};
+use iowa_parser::{MessageChain, ParseError, Parser, Span};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{self, Definition};
+
+/// A position in a file: zero-based line and UTF-16 code unit in the line.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Position {
+    pub(crate) line: u32,
+    pub(crate) character: u32,
+}
+
+/// A range of a file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Range {
+    pub(crate) start: Position,
+    pub(crate) end: Position,
+}
+
+/// A parsed file along with the results of the analysis.
+#[derive(Debug)]
+pub(crate) struct File {
+    doc: Document,
+    /// The tree recovered from the errors and the errors, if there are any.
+    recovered: Option<(Vec<MessageChain<'static>>, Vec<ParseError>)>,
+    definitions: Vec<Definition>,
+    /// The byte offsets of the starts of the lines.
+    lines: Vec<usize>,
+}
+
+impl File {
+    pub(crate) fn new(parser: &Parser, source: String) -> Self {
+        let mut file = Self {
+            doc: Document::new(parser, source),
+            recovered: None,
+            definitions: Vec::new(),
+            lines: Vec::new(),
+        };
+        file.analyze(parser);
+        file
+    }
+
+    /// Replace the range of the source or the whole source if there's no range.
+    pub(crate) fn edit(&mut self, parser: &Parser, range: Option<Range>, text: String) {
+        match range {
+            Some(range) => {
+                let range = self.offset(range.start)..self.offset(range.end);
+                let range = range.start..range.end.max(range.start);
+                self.doc.edit(parser, &TextEdit::new(range, text));
+            }
+            None => self.doc = Document::new(parser, text),
+        }
+        self.analyze(parser);
+    }
+
+    fn analyze(&mut self, parser: &Parser) {
+        self.recovered = self.doc.tree().is_err().then(|| {
+            let (chains, errors) = parser.parse_with_recovery(self.doc.source());
+            let chains = chains.into_iter().map(MessageChain::into_owned).collect();
+            (chains, errors)
+        });
+        self.definitions = analysis::definitions(self.chains());
+        let starts = self.source().match_indices('\n').map(|(i, _)| i + 1);
+        self.lines = std::iter::once(0).chain(starts).collect();
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        self.doc.source()
+    }
+
+    /// The tree, recovered from the errors if there are any.
+    pub(crate) fn chains(&self) -> &[MessageChain<'static>] {
+        match (&self.recovered, self.doc.tree()) {
+            (Some((chains, _)), _) => chains,
+            (None, Ok(chains)) => chains,
+            (None, Err(_)) => &[],
+        }
+    }
+
+    pub(crate) fn errors(&self) -> &[ParseError] {
+        self.recovered
+            .as_ref()
+            .map_or(&[], |(_, errors)| errors.as_slice())
+    }
+
+    /// The method slots defined in the file.
+    pub(crate) fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// The byte offset of the position, clamped to the line.
+    pub(crate) fn offset(&self, pos: Position) -> usize {
+        let source = self.source();
+        let Some(&start) = self.lines.get(pos.line as usize) else {
+            return source.len();
+        };
+
+        let mut units = 0;
+        for (i, ch) in source[start..].char_indices() {
+            if ch == '\n' || units >= pos.character as usize {
+                return start + i;
+            }
+            units += ch.len_utf16();
+        }
+        source.len()
+    }
+
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        Position {
+            line: line as u32,
+            character: self.source()[self.lines[line]..offset]
+                .encode_utf16()
+                .count() as u32,
+        }
+    }
+
+    pub(crate) fn range(&self, span: Span) -> Range {
+        Range {
+            start: self.position(span.start),
+            end: self.position(span.end),
+        }
+    }
+
+    /// The range of the whole source.
+    pub(crate) fn full_range(&self) -> Range {
+        self.range(Span {
+            start: 0,
+            end: self.source().len(),
+            line: 1,
+            column: 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_positions() {
+        let parser = Parser::default();
+        let file = File::new(&parser, "foo\n\"😀\" bar\n".to_string());
+
+        assert_eq!(file.offset(pos(0, 2)), 2);
+        assert_eq!(file.offset(pos(0, 10)), 3);
+        assert_eq!(file.offset(pos(1, 3)), 9);
+        assert_eq!(file.offset(pos(1, 4)), 10);
+        assert_eq!(file.offset(pos(5, 0)), 15);
+        assert_eq!(file.position(0), pos(0, 0));
+        assert_eq!(file.position(3), pos(0, 3));
+        assert_eq!(file.position(4), pos(1, 0));
+        assert_eq!(file.position(10), pos(1, 4));
+        assert_eq!(file.position(15), pos(2, 0));
+    }
+
+    #[test]
+    fn test_edit() {
+        let parser = Parser::default();
+        let mut file = File::new(&parser, "foo(1)\nbar".to_string());
+        let range = Range {
+            start: pos(0, 4),
+            end: pos(0, 5),
+        };
+
+        file.edit(&parser, Some(range), "(".to_string());
+        assert_eq!(file.source(), "foo(()\nbar");
+        assert!(!file.errors().is_empty());
+        // the next line is kept as an argument
+        assert_eq!(file.chains()[0][0].to_string(), "foo(; bar)");
+
+        file.edit(&parser, None, "baz".to_string());
+        assert!(file.errors().is_empty());
+        assert_eq!(file.chains()[0].to_string(), "baz");
+    }
+}
@@ -0,0 +1,33 @@
+//! Language server for Io programming language.
+//!
+//! Speaks the Language Server Protocol over the standard input and output.
+
+#![warn(
+    clippy::all,
+    deprecated_in_future,
+    missing_docs,
+    unused_import_braces,
+    unused_labels,
+    unused_lifetimes,
+    unused_qualifications,
+    unreachable_pub
+)]
+
+mod analysis;
+mod file;
+mod server;
+mod transport;
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let stdin = std::io::stdin().lock();
+    let stdout = std::io::stdout().lock();
+
+    server::Server::default()
+        .run(stdin, stdout)
+        .unwrap_or_else(|err| {
+            eprintln!("iowa-lsp: {err}");
+            ExitCode::FAILURE
+        })
+}
@@ -0,0 +1,606 @@
+//! The state of the server and the handling of the requests and notifications.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use iowa_parser::format::{format, FormatOptions};
+use iowa_parser::{ParseError, Parser};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::analysis::{self, Definition, TOKEN_MODIFIERS, TOKEN_TYPES};
+use crate::file::{File, Position, Range};
+use crate::transport;
+
+/// JSON-RPC and protocol error codes.
+mod code {
+    pub(super) const INVALID_REQUEST: i64 = -32600;
+    pub(super) const METHOD_NOT_FOUND: i64 = -32601;
+    pub(super) const INVALID_PARAMS: i64 = -32602;
+    pub(super) const SERVER_NOT_INITIALIZED: i64 = -32002;
+}
+
+/// Symbol kinds of the protocol.
+const SYMBOL_KIND_METHOD: u32 = 6;
+/// Diagnostic severities of the protocol.
+const SEVERITY_ERROR: u32 = 1;
+
+/// An error response.
+#[derive(Debug)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// The language server.
+#[derive(Debug, Default)]
+pub(crate) struct Server {
+    parser: Parser,
+    initialized: bool,
+    shutdown: bool,
+    exit: bool,
+    /// The documents opened by the client, by URI.
+    open: HashMap<String, File>,
+    /// The other Io files of the workspace, by URI.
+    workspace: HashMap<String, File>,
+}
+
+impl Server {
+    /// Serve the client until it sends the `exit` notification or closes the input.
+    pub(crate) fn run(
+        mut self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> io::Result<ExitCode> {
+        while let Some(msg) = transport::read(&mut input)? {
+            for msg in self.handle(msg) {
+                transport::write(&mut output, &msg)?;
+            }
+
+            if self.exit {
+                return Ok(if self.shutdown {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
+                });
+            }
+        }
+
+        Ok(ExitCode::FAILURE)
+    }
+
+    /// Handle a message from the client and return the messages to send back.
+    pub(crate) fn handle(&mut self, msg: Value) -> Vec<Value> {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+
+        let Some(id) = msg.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+        // a response to a request of the server
+        if msg.get("method").is_none() {
+            return Vec::new();
+        }
+
+        let res = if !self.initialized && method != "initialize" {
+            Err(ResponseError::new(
+                code::SERVER_NOT_INITIALIZED,
+                "the server isn't initialized",
+            ))
+        } else if self.shutdown {
+            Err(ResponseError::new(
+                code::INVALID_REQUEST,
+                "the server is shut down",
+            ))
+        } else {
+            self.request(method, params)
+        };
+
+        let response = match res {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": err.code, "message": err.message},
+            }),
+        };
+        vec![response]
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        match method {
+            "initialize" => self.initialize(parse_params(params)?),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/documentSymbol" => self.document_symbols(parse_params(params)?),
+            "textDocument/definition" => self.definition(parse_params(params)?),
+            "textDocument/hover" => self.hover(parse_params(params)?),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(parse_params(params)?),
+            "textDocument/formatting" => self.formatting(parse_params(params)?),
+            _ => Err(ResponseError::new(
+                code::METHOD_NOT_FOUND,
+                format!("unknown method `{method}`"),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> Vec<Value> {
+        if method == "exit" {
+            self.exit = true;
+            return Vec::new();
+        }
+        if !self.initialized {
+            return Vec::new();
+        }
+
+        // there's no way to report the errors of the notifications
+        let res = match method {
+            "textDocument/didOpen" => parse_params(params).map(|params| self.did_open(params)),
+            "textDocument/didChange" => parse_params(params).map(|params| self.did_change(params)),
+            "textDocument/didClose" => parse_params(params).map(|params| self.did_close(params)),
+            _ => Ok(Vec::new()),
+        };
+        res.unwrap_or_default()
+    }
+
+    fn initialize(&mut self, params: InitializeParams) -> Result<Value, ResponseError> {
+        if self.initialized {
+            return Err(ResponseError::new(
+                code::INVALID_REQUEST,
+                "the server is already initialized",
+            ));
+        }
+        self.initialized = true;
+
+        let roots = params
+            .workspace_folders
+            .unwrap_or_default()
+            .into_iter()
+            .map(|folder| folder.uri)
+            .chain(params.root_uri);
+        for root in roots {
+            if let Some(path) = uri_to_path(&root) {
+                self.scan(&path);
+            }
+        }
+
+        Ok(json!({
+            "capabilities": {
+                // incremental
+                "textDocumentSync": {"openClose": true, "change": 2},
+                "documentSymbolProvider": true,
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "semanticTokensProvider": {
+                    "legend": {"tokenTypes": TOKEN_TYPES, "tokenModifiers": TOKEN_MODIFIERS},
+                    "full": true,
+                },
+                "documentFormattingProvider": true,
+            },
+            "serverInfo": {"name": "iowa-lsp", "version": env!("CARGO_PKG_VERSION")},
+        }))
+    }
+
+    /// Load the Io files in the directory and its subdirectories.
+    fn scan(&mut self, dir: &Path) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || name == "target" {
+                continue;
+            }
+
+            if path.is_dir() {
+                self.scan(&path);
+            } else if path.extension().is_some_and(|ext| ext == "io") {
+                if let Ok(source) = std::fs::read_to_string(&path) {
+                    let file = File::new(&self.parser, source);
+                    self.workspace.insert(path_to_uri(&path), file);
+                }
+            }
+        }
+    }
+
+    fn did_open(&mut self, params: DidOpenParams) -> Vec<Value> {
+        let doc = params.text_document;
+        self.workspace.remove(&doc.uri);
+        let file = File::new(&self.parser, doc.text);
+        let diagnostics = diagnostics(&doc.uri, &file);
+        self.open.insert(doc.uri, file);
+        vec![diagnostics]
+    }
+
+    fn did_change(&mut self, params: DidChangeParams) -> Vec<Value> {
+        let uri = params.text_document.uri;
+        let Some(file) = self.open.get_mut(&uri) else {
+            return Vec::new();
+        };
+
+        for change in params.content_changes {
+            file.edit(&self.parser, change.range, change.text);
+        }
+        vec![diagnostics(&uri, file)]
+    }
+
+    fn did_close(&mut self, params: TextDocumentParams) -> Vec<Value> {
+        let uri = params.text_document.uri;
+        self.open.remove(&uri);
+
+        // the file on the disk may be different from the closed one
+        let path = uri_to_path(&uri);
+        if let Some(source) = path.and_then(|path| std::fs::read_to_string(path).ok()) {
+            self.workspace
+                .insert(uri.clone(), File::new(&self.parser, source));
+        }
+
+        vec![notification(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": []}),
+        )]
+    }
+
+    /// The open document or the workspace file.
+    fn file(&self, uri: &str) -> Result<&File, ResponseError> {
+        self.open
+            .get(uri)
+            .or_else(|| self.workspace.get(uri))
+            .ok_or_else(|| {
+                ResponseError::new(code::INVALID_PARAMS, format!("unknown document `{uri}`"))
+            })
+    }
+
+    /// All the files, with the open documents in place of the workspace files.
+    fn files(&self) -> impl Iterator<Item = (&String, &File)> {
+        self.open.iter().chain(
+            self.workspace
+                .iter()
+                .filter(|(uri, _)| !self.open.contains_key(*uri)),
+        )
+    }
+
+    fn document_symbols(&self, params: TextDocumentParams) -> Result<Value, ResponseError> {
+        fn symbols(file: &File, defs: &[Definition]) -> Value {
+            defs.iter()
+                .map(|def| {
+                    json!({
+                        "name": def.name,
+                        "detail": def.signature(),
+                        "kind": SYMBOL_KIND_METHOD,
+                        "range": file.range(def.span),
+                        "selectionRange": file.range(def.name_span),
+                        "children": symbols(file, &def.children),
+                    })
+                })
+                .collect()
+        }
+
+        let file = self.file(&params.text_document.uri)?;
+        Ok(symbols(file, file.definitions()))
+    }
+
+    /// The definitions of the slot with the name in all the files.
+    fn find_definitions(&self, name: &str) -> Vec<(&String, &File, &Definition)> {
+        fn find<'s>(defs: &'s [Definition], name: &str, out: &mut Vec<&'s Definition>) {
+            for def in defs {
+                if def.name == name {
+                    out.push(def);
+                }
+                find(&def.children, name, out);
+            }
+        }
+
+        let mut out = Vec::new();
+        for (uri, file) in self.files() {
+            let mut defs = Vec::new();
+            find(file.definitions(), name, &mut defs);
+            out.extend(defs.into_iter().map(|def| (uri, file, def)));
+        }
+        out.sort_by_key(|(uri, _, def)| (*uri, def.span.start));
+        out
+    }
+
+    fn definition(&self, params: PositionParams) -> Result<Value, ResponseError> {
+        let file = self.file(&params.text_document.uri)?;
+        let offset = file.offset(params.position);
+        let Some((name, _)) = analysis::name_at(file.source(), file.chains(), offset) else {
+            return Ok(Value::Null);
+        };
+
+        let locations: Vec<_> = self
+            .find_definitions(&name)
+            .into_iter()
+            .map(|(uri, file, def)| json!({"uri": uri, "range": file.range(def.name_span)}))
+            .collect();
+        Ok(locations.into())
+    }
+
+    fn hover(&self, params: PositionParams) -> Result<Value, ResponseError> {
+        let uri = &params.text_document.uri;
+        let file = self.file(uri)?;
+        let offset = file.offset(params.position);
+        let Some((name, span)) = analysis::name_at(file.source(), file.chains(), offset) else {
+            return Ok(Value::Null);
+        };
+
+        let defs = self.find_definitions(&name);
+        // prefer the definitions in the same file
+        let def = defs
+            .iter()
+            .find(|(def_uri, ..)| *def_uri == uri)
+            .or_else(|| defs.first());
+        Ok(match def {
+            Some((_, _, def)) => json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": format!("```io\n{}\n```", def.signature()),
+                },
+                "range": file.range(span),
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn semantic_tokens(&self, params: TextDocumentParams) -> Result<Value, ResponseError> {
+        let file = self.file(&params.text_document.uri)?;
+        let mut data = Vec::new();
+        let mut last = Position {
+            line: 0,
+            character: 0,
+        };
+
+        for token in analysis::semantic_tokens(file.source(), file.chains()) {
+            // the tokens can't span lines
+            let text = &file.source()[token.span.start..token.span.end];
+            let mut start = token.span.start;
+            for line in text.split_inclusive('\n') {
+                let pos = file.position(start);
+                let len = line.trim_end_matches(['\r', '\n']).encode_utf16().count();
+                start += line.len();
+                if len == 0 {
+                    continue;
+                }
+
+                let delta_start = if pos.line == last.line {
+                    pos.character - last.character
+                } else {
+                    pos.character
+                };
+                data.extend([
+                    pos.line - last.line,
+                    delta_start,
+                    len as u32,
+                    token.kind,
+                    token.modifiers,
+                ]);
+                last = pos;
+            }
+        }
+
+        Ok(json!({"data": data}))
+    }
+
+    fn formatting(&self, params: FormattingParams) -> Result<Value, ResponseError> {
+        let file = self.file(&params.text_document.uri)?;
+        let options = FormatOptions {
+            indent_width: params.options.tab_size as usize,
+            ..FormatOptions::default()
+        };
+
+        // the source with errors isn't formatted
+        let Ok(formatted) = format(file.source(), &options) else {
+            return Ok(Value::Null);
+        };
+        if formatted == file.source() {
+            return Ok(json!([]));
+        }
+        Ok(json!([{"range": file.full_range(), "newText": formatted}]))
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, ResponseError> {
+    serde_json::from_value(params)
+        .map_err(|err| ResponseError::new(code::INVALID_PARAMS, err.to_string()))
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn diagnostics(uri: &str, file: &File) -> Value {
+    let diagnostics: Vec<_> = file
+        .errors()
+        .iter()
+        .map(|err| {
+            json!({
+                "range": file.range(err.span),
+                "severity": SEVERITY_ERROR,
+                "source": "iowa",
+                "message": error_message(err),
+            })
+        })
+        .collect();
+
+    notification(
+        "textDocument/publishDiagnostics",
+        json!({"uri": uri, "diagnostics": diagnostics}),
+    )
+}
+
+fn error_message(err: &ParseError) -> String {
+    let tokens: Vec<_> = err
+        .expected
+        .iter()
+        .map(|token| format!("`{token}`"))
+        .collect();
+    match tokens.as_slice() {
+        [] => err.kind.to_string(),
+        [token] => format!("{}, expected {token}", err.kind),
+        tokens => format!("{}, expected one of {}", err.kind, tokens.join(", ")),
+    }
+}
+
+/// The path of a `file://` URI.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// The `file://` URI of an absolute path.
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitializeParams {
+    root_uri: Option<String>,
+    workspace_folders: Option<Vec<WorkspaceFolder>>,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceFolder {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionParams {
+    text_document: TextDocumentIdentifier,
+    position: Position,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentItem {
+    uri: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+struct ContentChange {
+    range: Option<Range>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormattingOptions {
+    tab_size: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormattingParams {
+    text_document: TextDocumentIdentifier,
+    options: FormattingOptions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri() {
+        let path = Path::new("/tmp/io code/ü.io");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/io%20code/%C3%BC.io");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut server = Server::default();
+        let request = |id: u32, method: &str| json!({"jsonrpc": "2.0", "id": id, "method": method});
+
+        let res = server.handle(request(1, "shutdown"));
+        assert_eq!(res[0]["error"]["code"], code::SERVER_NOT_INITIALIZED);
+
+        let res =
+            server.handle(json!({"jsonrpc": "2.0", "id": 2, "method": "initialize", "params": {}}));
+        assert_eq!(res[0]["result"]["capabilities"]["hoverProvider"], true);
+        let res = server.handle(request(3, "foo"));
+        assert_eq!(res[0]["error"]["code"], code::METHOD_NOT_FOUND);
+        let res = server.handle(request(4, "textDocument/hover"));
+        assert_eq!(res[0]["error"]["code"], code::INVALID_PARAMS);
+
+        assert_eq!(
+            server.handle(request(5, "shutdown"))[0]["result"],
+            Value::Null
+        );
+        let res = server.handle(request(6, "shutdown"));
+        assert_eq!(res[0]["error"]["code"], code::INVALID_REQUEST);
+        assert!(server
+            .handle(json!({"jsonrpc": "2.0", "method": "exit"}))
+            .is_empty());
+        assert!(server.exit);
+    }
+}
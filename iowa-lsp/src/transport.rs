@@ -0,0 +1,64 @@
+//! Message framing: each JSON-RPC message is preceded by the `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Read the next message, `None` at the end of the input.
+pub(crate) fn read(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let len = len.ok_or_else(|| invalid("missing `Content-Length` header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map_err(|err| invalid(&err.to_string()))
+}
+
+/// Write the message.
+pub(crate) fn write(output: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_framing() {
+        let mut out = Vec::new();
+        write(&mut out, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+        write(&mut out, &json!({"text": "ü"})).unwrap();
+        assert!(out.starts_with(b"Content-Length: 33\r\n\r\n{"));
+
+        let mut input = out.as_slice();
+        assert_eq!(read(&mut input).unwrap().unwrap()["method"], "exit");
+        assert_eq!(read(&mut input).unwrap().unwrap()["text"], "ü");
+        assert_eq!(read(&mut input).unwrap(), None);
+        assert!(read(&mut &b"Foo: 1\r\n\r\n{}"[..]).is_err());
+    }
+}
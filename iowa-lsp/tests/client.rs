@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+/// A client driving the server over its standard input and output.
+struct Client {
+    server: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    next_id: u64,
+    notifications: VecDeque<Value>,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_iowa-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        Self {
+            input: server.stdin.take().unwrap(),
+            output: BufReader::new(server.stdout.take().unwrap()),
+            server,
+            next_id: 1,
+            notifications: VecDeque::new(),
+        }
+    }
+
+    fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.output.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some(("Content-Length", value)) => len = value.parse().unwrap(),
+                _ if line.trim_end().is_empty() => break,
+                _ => {}
+            }
+        }
+
+        let mut body = vec![0; len];
+        self.output.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Send the request and wait for the response, keeping the notifications for later.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}));
+
+        loop {
+            let msg = self.receive();
+            if msg["id"] == id {
+                assert!(msg.get("error").is_none(), "{msg}");
+                return msg["result"].clone();
+            }
+            self.notifications.push_back(msg);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+
+    fn notification(&mut self) -> Value {
+        match self.notifications.pop_front() {
+            Some(msg) => msg,
+            None => self.receive(),
+        }
+    }
+}
+
+fn workspace() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iowa-lsp-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib/ack.io"),
+        "ack := method(m, n,\n    if(m < 1, return n + 1)\n    ack(m - 1, 1)\n)\n",
+    )
+    .unwrap();
+    dir
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+    json!({
+        "start": {"line": start.0, "character": start.1},
+        "end": {"line": end.0, "character": end.1},
+    })
+}
+
+#[test]
+fn test_session() {
+    let dir = workspace();
+    let root = format!("file://{}", dir.display());
+    let lib = format!("{root}/lib/ack.io");
+    let main = format!("{root}/main.io");
+    let mut client = Client::start();
+
+    let res = client.request("initialize", json!({"rootUri": root, "capabilities": {}}));
+    assert_eq!(res["capabilities"]["textDocumentSync"]["change"], 2);
+    client.notify("initialized", json!({}));
+
+    // diagnostics
+    let text = "ack(2, 3) println\nfoo(\n";
+    client.notify(
+        "textDocument/didOpen",
+        json!({"textDocument": {"uri": main, "languageId": "io", "version": 1, "text": text}}),
+    );
+    let msg = client.notification();
+    assert_eq!(msg["method"], "textDocument/publishDiagnostics");
+    let diagnostics = msg["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"], range((1, 4), (2, 0)));
+    assert_eq!(
+        diagnostics[0]["message"],
+        r"unexpected character `\n`, expected `)`"
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": {"uri": main, "version": 2},
+            "contentChanges": [{"range": range((1, 0), (2, 0)), "text": ""}],
+        }),
+    );
+    let msg = client.notification();
+    assert_eq!(msg["params"]["diagnostics"], json!([]));
+
+    // go to the definition in the workspace
+    let pos = json!({"textDocument": {"uri": main}, "position": {"line": 0, "character": 1}});
+    let res = client.request("textDocument/definition", pos.clone());
+    assert_eq!(res, json!([{"uri": lib, "range": range((0, 0), (0, 3))}]));
+
+    let res = client.request("textDocument/hover", pos);
+    assert_eq!(res["contents"]["value"], "```io\nack(m, n)\n```");
+    assert_eq!(res["range"], range((0, 0), (0, 3)));
+
+    let res = client.request(
+        "textDocument/documentSymbol",
+        json!({"textDocument": {"uri": lib}}),
+    );
+    assert_eq!(res[0]["name"], "ack");
+    assert_eq!(res[0]["detail"], "ack(m, n)");
+    assert_eq!(res[0]["range"], range((0, 0), (3, 1)));
+
+    let res = client.request(
+        "textDocument/semanticTokens/full",
+        json!({"textDocument": {"uri": main}}),
+    );
+    // ack, 2, 3, println
+    assert_eq!(
+        res["data"],
+        json!([0, 0, 3, 1, 0, 0, 4, 1, 3, 0, 0, 3, 1, 3, 0, 0, 3, 7, 0, 0])
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": {"uri": main, "version": 3},
+            "contentChanges": [{"text": "foo( 1,2 )"}],
+        }),
+    );
+    client.notification();
+    let res = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": {"uri": main},
+            "options": {"tabSize": 2, "insertSpaces": true},
+        }),
+    );
+    assert_eq!(
+        res,
+        json!([{"range": range((0, 0), (0, 10)), "newText": "foo(1, 2)\n"}])
+    );
+
+    assert_eq!(client.request("shutdown", Value::Null), Value::Null);
+    client.notify("exit", Value::Null);
+    assert!(client.server.wait().unwrap().success());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
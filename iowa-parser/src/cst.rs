@@ -0,0 +1,406 @@
+//! Lossless concrete syntax tree.
+//!
+//! Unlike the [`MessageChain`](crate::MessageChain)s returned by [`parse`](crate::parse), the
+//! concrete syntax tree keeps every character of the source: whitespace, comments, terminators,
+//! brackets and the original spelling of literals (`0xFF` and `255`, mono- and tri-quoted
+//! strings). Printing the tree gives back the exact input, which is the base for refactoring
+//! tools and the formatter.
+//!
+//! The tree never fails to build. Anything the parser would reject ends up in
+//! [`TokenKind::Error`] tokens or in unexpected places of the tree.
+
+mod lexer;
+
+use std::fmt;
+
+use crate::{symbol::symbol, Parser, Span, State, Symbol};
+
+/// The kind of a token.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum TokenKind {
+    /// Spaces and tabs.
+    Whitespace,
+    /// A line ending, which terminates a chain.
+    Newline,
+    /// A line or block comment.
+    Comment,
+    /// `;`
+    Semicolon,
+    /// `,`
+    Comma,
+    /// `(`, `[` or `{`.
+    OpenBracket,
+    /// `)`, `]` or `}`.
+    CloseBracket,
+    /// Identifier.
+    Identifier,
+    /// Number.
+    Number,
+    /// Operator.
+    Operator,
+    /// A string in single double quotes.
+    MonoQuote,
+    /// A string in triple double quotes.
+    TriQuote,
+    /// A part of the source which can't be tokenized.
+    Error,
+}
+
+impl TokenKind {
+    /// Check if the token doesn't affect the meaning of the program.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+
+    /// Check if the token is a message symbol.
+    pub fn is_symbol(self) -> bool {
+        matches!(
+            self,
+            Self::Identifier | Self::Number | Self::Operator | Self::MonoQuote | Self::TriQuote
+        )
+    }
+}
+
+/// A token with its text exactly as it's written in the source.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token<'a> {
+    /// The kind of the token.
+    pub kind: TokenKind,
+    /// The text of the token.
+    pub text: &'a str,
+    /// The location of the token.
+    pub span: Span,
+}
+
+impl<'a> Token<'a> {
+    /// Get the symbol the token stands for, if it's a symbol token.
+    ///
+    /// Operators are looked up in the operator table of the parser, which should be the one that
+    /// built the tree.
+    pub fn symbol(&self, parser: &'a Parser) -> Option<Symbol<'a>> {
+        if !self.kind.is_symbol() {
+            return None;
+        }
+
+        let state = State {
+            source: self.text,
+            errors: None,
+            operators: parser.operators(),
+        };
+        symbol(state.input()).ok().map(|(_, symbol)| symbol)
+    }
+}
+
+/// The kind of a node.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum NodeKind {
+    /// The whole source.
+    Program,
+    /// A chain of messages including its terminator.
+    Chain,
+    /// A message: the symbol token and optionally the arguments.
+    Message,
+    /// Arguments including the brackets and the commas.
+    Arguments,
+    /// A single argument.
+    Argument,
+}
+
+/// An element of the tree.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Element<'a> {
+    /// A node.
+    Node(Node<'a>),
+    /// A token.
+    Token(Token<'a>),
+}
+
+impl Element<'_> {
+    /// The location of the element.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Node(node) => node.span(),
+            Self::Token(token) => token.span,
+        }
+    }
+}
+
+/// A node of the tree.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node<'a> {
+    /// The kind of the node.
+    pub kind: NodeKind,
+    /// The child nodes and tokens in the source order.
+    pub children: Vec<Element<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// The location of the node.
+    pub fn span(&self) -> Span {
+        self.children
+            .iter()
+            .fold(Span::default(), |span, child| span.merge(child.span()))
+    }
+
+    /// Iterate over the child nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Iterate over all the tokens of the node in the source order.
+    pub fn tokens(&self) -> Box<dyn Iterator<Item = &Token<'a>> + '_> {
+        Box::new(self.children.iter().flat_map(|child| match child {
+            Element::Node(node) => node.tokens(),
+            Element::Token(token) => Box::new(std::iter::once(token)),
+        }))
+    }
+}
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens().try_for_each(|token| f.write_str(token.text))
+    }
+}
+
+/// Build the concrete syntax tree of the input with the default operators.
+///
+/// Use [`Parser::parse_cst`] for other operator tables.
+pub fn parse(input: &str) -> Node<'_> {
+    Parser::default_ref().parse_cst(input)
+}
+
+pub(crate) fn build<'a>(state: State<'a>) -> Node<'a> {
+    let mut builder = Builder {
+        tokens: lexer::tokenize(state.input()),
+        pos: 0,
+    };
+    builder.program()
+}
+
+struct Builder<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Builder<'a> {
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.nth_kind(0)
+    }
+
+    fn nth_kind(&self, n: usize) -> Option<TokenKind> {
+        self.tokens.get(self.pos + n).map(|token| token.kind)
+    }
+
+    fn bump(&mut self, node: &mut Node<'a>) {
+        if let Some(token) = self.tokens.get(self.pos) {
+            node.children.push(Element::Token(token.clone()));
+            self.pos += 1;
+        }
+    }
+
+    fn program(&mut self) -> Node<'a> {
+        let mut node = Node::new(NodeKind::Program);
+
+        while let Some(kind) = self.peek_kind() {
+            if kind.is_symbol() {
+                let chain = self.chain();
+                node.children.push(Element::Node(chain));
+            } else {
+                self.bump(&mut node);
+            }
+        }
+
+        node
+    }
+
+    fn chain(&mut self) -> Node<'a> {
+        let mut node = Node::new(NodeKind::Chain);
+
+        loop {
+            let message = self.message();
+            node.children.push(Element::Node(message));
+
+            while self.peek_kind().is_some_and(TokenKind::is_trivia) {
+                self.bump(&mut node);
+            }
+
+            match self.peek_kind() {
+                Some(kind) if kind.is_symbol() => continue,
+                Some(TokenKind::Semicolon | TokenKind::Newline) => {
+                    self.bump(&mut node);
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        node
+    }
+
+    fn message(&mut self) -> Node<'a> {
+        let mut node = Node::new(NodeKind::Message);
+        self.bump(&mut node);
+
+        // a single separator or comment is allowed between the symbol and the arguments
+        let has_args = match self.peek_kind() {
+            Some(TokenKind::OpenBracket) => true,
+            Some(kind) if kind.is_trivia() => self.nth_kind(1) == Some(TokenKind::OpenBracket),
+            _ => false,
+        };
+
+        if has_args {
+            if self.peek_kind().is_some_and(TokenKind::is_trivia) {
+                self.bump(&mut node);
+            }
+            let arguments = self.arguments();
+            node.children.push(Element::Node(arguments));
+        }
+
+        node
+    }
+
+    fn arguments(&mut self) -> Node<'a> {
+        let mut node = Node::new(NodeKind::Arguments);
+        self.bump(&mut node);
+
+        loop {
+            let argument = self.argument();
+            if !argument.children.is_empty() {
+                node.children.push(Element::Node(argument));
+            }
+
+            match self.peek_kind() {
+                Some(TokenKind::Comma) => self.bump(&mut node),
+                Some(TokenKind::CloseBracket) => {
+                    self.bump(&mut node);
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        node
+    }
+
+    fn argument(&mut self) -> Node<'a> {
+        let mut node = Node::new(NodeKind::Argument);
+
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::Comma | TokenKind::CloseBracket => break,
+                kind if kind.is_symbol() => {
+                    let chain = self.chain();
+                    node.children.push(Element::Node(chain));
+                }
+                _ => self.bump(&mut node),
+            }
+        }
+
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CustomOperator, Number};
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "",
+            "foo",
+            "  foo bar  baz;\r\n\n",
+            "ack := method(m, n,\n  // comment\n  if (m < 1, return n + 1) # another\n)\n",
+            "list(1, 0xFF, 1.5e3, \"a\\tb\", \"\"\"tri\n\"quoted\"\"\"\")\n/* block\n comment */",
+            "foo(bar\n\"unterminated\nbaz) ) ] $ /* open",
+        ];
+
+        for input in inputs {
+            assert_eq!(parse(input).to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_structure() {
+        let tree = parse("foo bar(1, 2) # c\nbaz");
+
+        let kinds = |node: &Node<'_>| -> Vec<NodeKind> { node.nodes().map(|n| n.kind).collect() };
+        assert_eq!(kinds(&tree), [NodeKind::Chain, NodeKind::Chain]);
+
+        let chain = tree.nodes().next().unwrap();
+        assert_eq!(chain.to_string(), "foo bar(1, 2) # c\n");
+        assert_eq!(kinds(chain), [NodeKind::Message, NodeKind::Message]);
+
+        let bar = chain.nodes().nth(1).unwrap();
+        assert_eq!(bar.to_string(), "bar(1, 2)");
+        let arguments = bar.nodes().next().unwrap();
+        assert_eq!(kinds(arguments), [NodeKind::Argument, NodeKind::Argument]);
+        assert_eq!(arguments.nodes().nth(1).unwrap().to_string(), " 2");
+
+        let token_kinds: Vec<_> = chain.tokens().map(|t| t.kind).collect();
+        assert_eq!(
+            token_kinds,
+            [
+                TokenKind::Identifier,
+                TokenKind::Whitespace,
+                TokenKind::Identifier,
+                TokenKind::OpenBracket,
+                TokenKind::Number,
+                TokenKind::Comma,
+                TokenKind::Whitespace,
+                TokenKind::Number,
+                TokenKind::CloseBracket,
+                TokenKind::Whitespace,
+                TokenKind::Comment,
+                TokenKind::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literal_spelling() {
+        let tree = parse("0xFF 255 \"a\" \"\"\"a\"\"\"");
+        let tokens: Vec<_> = tree
+            .tokens()
+            .filter(|token| token.kind.is_symbol())
+            .collect();
+
+        assert_eq!(tokens[0].text, "0xFF");
+        let parser = Parser::default();
+        assert_eq!(
+            tokens[0].symbol(&parser),
+            Some(Symbol::Number(Number::Hex(255)))
+        );
+        assert_eq!(tokens[1].text, "255");
+        assert_eq!(tokens[2].kind, TokenKind::MonoQuote);
+        assert_eq!(tokens[3].kind, TokenKind::TriQuote);
+        assert_eq!(tokens[2].symbol(&parser), tokens[3].symbol(&parser));
+    }
+
+    #[test]
+    fn test_custom_operator_symbol() {
+        let mut parser = Parser::default();
+        parser.add_operator(CustomOperator::new("~>", 6));
+        let tree = parser.parse_cst("a ~> b");
+        let token = tree
+            .tokens()
+            .find(|token| token.kind == TokenKind::Operator)
+            .unwrap();
+
+        assert_eq!(token.text, "~>");
+        let symbol = token.symbol(&parser).unwrap();
+        assert!(matches!(symbol, Symbol::Operator(_)));
+        assert_eq!(symbol.to_string(), "~>");
+    }
+}
@@ -0,0 +1,90 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_until, take_while1},
+    character::complete::one_of,
+    combinator::{map, recognize, rest, value},
+    sequence::{pair, tuple},
+    InputTake,
+};
+
+use super::{Token, TokenKind};
+use crate::{symbol::symbol, Input, PResult, Span, Symbol};
+
+/// Split the whole input into tokens.
+///
+/// Anything which can't be recognized becomes a [`TokenKind::Error`] token, so the tokens always
+/// cover the input.
+pub(super) fn tokenize(mut input: Input<'_>) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+
+    while !input.is_empty() {
+        let (rest, kind) = match token(input.clone()) {
+            Ok(res) => res,
+            // a broken string or comment spans up to the end of the line
+            Err(nom::Err::Failure(_)) => {
+                let end = input.find(['\n', '\r']).unwrap_or(input.len()).max(1);
+                let (rest, _) = input.take_split(end);
+                (rest, TokenKind::Error)
+            }
+            Err(_) => {
+                let len = input.chars().next().map_or(0, char::len_utf8);
+                let (rest, _) = input.take_split(len);
+                (rest, TokenKind::Error)
+            }
+        };
+
+        let len = input.len() - rest.len();
+        tokens.push(Token {
+            kind,
+            text: &input.fragment()[..len],
+            span: Span::between(&input, &rest),
+        });
+        input = rest;
+    }
+
+    tokens
+}
+
+fn token(input: Input<'_>) -> PResult<'_, TokenKind> {
+    alt((
+        value(TokenKind::Newline, alt((tag("\r\n"), tag("\n"), tag("\r")))),
+        value(
+            TokenKind::Whitespace,
+            take_while1(|c| matches!(c, ' ' | '\t' | '\x0b' | '\x0c')),
+        ),
+        value(TokenKind::Comment, comment),
+        // unterminated block comment takes the rest of the input
+        value(TokenKind::Error, pair(tag("/*"), rest)),
+        map(one_of("()[]{},;"), |ch| match ch {
+            '(' | '[' | '{' => TokenKind::OpenBracket,
+            ')' | ']' | '}' => TokenKind::CloseBracket,
+            ',' => TokenKind::Comma,
+            _ => TokenKind::Semicolon,
+        }),
+        symbol_token,
+    ))(input)
+}
+
+fn symbol_token(input: Input<'_>) -> PResult<'_, TokenKind> {
+    let (rest, symbol) = symbol(input.clone())?;
+    let kind = match symbol {
+        Symbol::Identifier(_) => TokenKind::Identifier,
+        Symbol::Number(_) => TokenKind::Number,
+        Symbol::Operator(_) => TokenKind::Operator,
+        Symbol::Quote(_) if input.starts_with("\"\"\"") => TokenKind::TriQuote,
+        Symbol::Quote(_) => TokenKind::MonoQuote,
+        Symbol::Error => TokenKind::Error,
+    };
+    Ok((rest, kind))
+}
+
+/// Unlike the comments skipped by the parser, a line comment doesn't include the line ending.
+fn comment(input: Input<'_>) -> PResult<'_, Input<'_>> {
+    alt((
+        recognize(pair(
+            alt((tag("#"), tag("//"))),
+            take_till(|c| c == '\n' || c == '\r'),
+        )),
+        recognize(tuple((tag("/*"), take_until("*/"), tag("*/")))),
+    ))(input)
+}
@@ -0,0 +1,275 @@
+//! Parse errors.
+
+use std::fmt;
+
+use crate::{Input, Span};
+
+/// The kind of a parse error.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ErrorKind {
+    /// A string literal without the closing quotes.
+    UnterminatedString,
+    /// A block comment without the closing `*/`.
+    UnterminatedComment,
+    /// An invalid escape sequence in a string literal.
+    BadEscape,
+    /// An opening bracket without the matching closing one.
+    UnbalancedBracket(char),
+    /// A character which can't appear at this position.
+    UnexpectedCharacter(char),
+    /// The input ended where more was expected.
+    UnexpectedEnd,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnterminatedString => write!(f, "unterminated string"),
+            Self::UnterminatedComment => write!(f, "unterminated block comment"),
+            Self::BadEscape => write!(f, "invalid escape sequence"),
+            Self::UnbalancedBracket(ch) => write!(f, "unclosed `{ch}`"),
+            Self::UnexpectedCharacter(ch) => {
+                write!(f, "unexpected character `{}`", ch.escape_debug())
+            }
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+        }
+    }
+}
+
+/// An error produced by the parser.
+///
+/// The [`Display`](fmt::Display) implementation renders the error with the line of the source it
+/// points at.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    /// The kind of the error.
+    pub kind: ErrorKind,
+    /// The location of the error.
+    pub span: Span,
+    /// Tokens which would be valid at the error location.
+    pub expected: Vec<String>,
+    source_line: String,
+}
+
+impl From<Error<'_>> for ParseError {
+    fn from(error: Error<'_>) -> Self {
+        let span = error.span();
+        let source_line = error
+            .input
+            .extra
+            .source
+            .lines()
+            .nth(span.line as usize - 1)
+            .unwrap_or_default()
+            .to_string();
+
+        Self {
+            kind: error.kind,
+            span,
+            expected: error.expected,
+            source_line,
+        }
+    }
+}
+
+impl ParseError {
+    /// Display the error with the location in the file of the name.
+    pub fn report<'a>(&'a self, file: &'a str) -> ParseReport<'a> {
+        ParseReport { err: self, file }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, file: Option<&str>) -> fmt::Result {
+        let line_number = self.span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        // keep tabs, so the caret is aligned with the source line
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.span.column - 1)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let offset = self
+            .source_line
+            .char_indices()
+            .nth(self.span.column - 1)
+            .map_or(self.source_line.len(), |(i, _)| i);
+        let caret_len = self.source_line[offset..]
+            .char_indices()
+            .take_while(|(i, _)| *i < self.span.len())
+            .count()
+            .max(1);
+
+        writeln!(f, "error: {}", self.kind)?;
+        let file = file.map(|file| format!("{file}:")).unwrap_or_default();
+        writeln!(
+            f,
+            "{gutter}--> {file}{}:{}",
+            self.span.line, self.span.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.source_line)?;
+        write!(f, "{gutter} | {padding}{}", "^".repeat(caret_len))?;
+
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [token] => write!(f, " expected `{token}`"),
+            tokens => {
+                let tokens: Vec<_> = tokens.iter().map(|token| format!("`{token}`")).collect();
+                write!(f, " expected one of {}", tokens.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A [`ParseError`] with the name of the file.
+///
+/// ```text
+/// error: unclosed `(`
+///  --> main.io:1:4
+///   |
+/// 1 | foo(
+///   |    ^ expected `)`
+/// ```
+pub struct ParseReport<'a> {
+    err: &'a ParseError,
+    file: &'a str,
+}
+
+impl fmt::Display for ParseReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.err.write(f, Some(self.file))
+    }
+}
+
+/// The error type used by the parsers internally.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Error<'a> {
+    pub(crate) input: Input<'a>,
+    pub(crate) kind: ErrorKind,
+    pub(crate) expected: Vec<String>,
+}
+
+impl<'a> Error<'a> {
+    pub(crate) fn new(input: Input<'a>, kind: ErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Create an error for the character at the beginning of the input.
+    pub(crate) fn unexpected(input: Input<'a>) -> Self {
+        let kind = match input.chars().next() {
+            Some(ch) => ErrorKind::UnexpectedCharacter(ch),
+            None => ErrorKind::UnexpectedEnd,
+        };
+        Self::new(input, kind)
+    }
+
+    pub(crate) fn expected(mut self, token: impl Into<String>) -> Self {
+        self.expected.push(token.into());
+        self
+    }
+
+    fn span(&self) -> Span {
+        let start = Span::between(&self.input, &self.input);
+        let len = match self.kind {
+            ErrorKind::UnexpectedCharacter(ch) => ch.len_utf8(),
+            _ => 0,
+        };
+
+        Span {
+            end: start.end + len,
+            ..start
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<Input<'a>> for Error<'a> {
+    fn from_error_kind(input: Input<'a>, _kind: nom::error::ErrorKind) -> Self {
+        Self::unexpected(input)
+    }
+
+    fn append(_input: Input<'a>, _kind: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: Input<'a>, ch: char) -> Self {
+        Self::unexpected(input).expected(ch)
+    }
+
+    fn or(mut self, other: Self) -> Self {
+        // prefer the error which got further, but collect everything expected at the same place
+        match self
+            .input
+            .location_offset()
+            .cmp(&other.input.location_offset())
+        {
+            std::cmp::Ordering::Less => other,
+            std::cmp::Ordering::Greater => self,
+            std::cmp::Ordering::Equal => {
+                for token in other.expected {
+                    if !self.expected.contains(&token) {
+                        self.expected.push(token);
+                    }
+                }
+                self
+            }
+        }
+    }
+}
+
+impl<'a, E> nom::error::FromExternalError<Input<'a>, E> for Error<'a> {
+    fn from_external_error(input: Input<'a>, _kind: nom::error::ErrorKind, _error: E) -> Self {
+        Self::unexpected(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, ErrorKind};
+
+    #[test]
+    fn test_error_kinds() {
+        let cases = [
+            ("foo(\"bar\n", ErrorKind::UnterminatedString, (1, 5)),
+            ("foo \"\"\"bar", ErrorKind::UnterminatedString, (1, 5)),
+            ("\"\\xZZ\"", ErrorKind::BadEscape, (1, 2)),
+            ("foo /* bar", ErrorKind::UnterminatedComment, (1, 5)),
+            ("foo(bar,\n  baz", ErrorKind::UnbalancedBracket('('), (1, 4)),
+            ("foo\nbar)", ErrorKind::UnexpectedCharacter(')'), (2, 4)),
+        ];
+
+        for (input, kind, (line, column)) in cases {
+            let err = parse(input).unwrap_err();
+            assert_eq!(err.kind, kind, "{input:?}");
+            assert_eq!(
+                (err.span.line, err.span.column),
+                (line, column),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_error() {
+        let err = parse("foo\n  bar(baz\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: unclosed `(`\n --> 2:6\n  |\n2 |   bar(baz\n  |      ^ expected `)`"
+        );
+        assert_eq!(
+            err.report("main.io").to_string(),
+            "error: unclosed `(`\n --> main.io:2:6\n  |\n2 |   bar(baz\n  |      ^ expected `)`"
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! Source code formatter.
+//!
+//! The formatter works on the [concrete syntax tree](crate::cst), so the comments and the original
+//! spelling of literals and operators are kept, while the layout is normalized: one chain per
+//! line, single spaces between messages, at most one blank line in a row and argument lists
+//! wrapped when they don't fit into the width.
+
+use crate::cst::{self, Element, Node, NodeKind, Token, TokenKind};
+use crate::ParseError;
+
+/// Where to put the comments which follow the code on the same line.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum CommentPlacement {
+    /// Keep them at the end of the line.
+    #[default]
+    Preserve,
+    /// Move them to their own line above the code.
+    OwnLine,
+}
+
+/// Formatter options.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FormatOptions {
+    /// Number of spaces per indentation level.
+    pub indent_width: usize,
+    /// Argument lists which make a line longer than this are wrapped.
+    pub max_width: usize,
+    /// Placement of the comments following the code.
+    pub comments: CommentPlacement,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_width: 100,
+            comments: CommentPlacement::default(),
+        }
+    }
+}
+
+/// Format the source.
+///
+/// The source must be valid, the formatter doesn't touch code with errors.
+pub fn format(input: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    crate::parse(input)?;
+
+    let tree = cst::parse(input);
+    let mut printer = Printer {
+        options,
+        out: String::new(),
+        indent: 0,
+    };
+    let lines = lines(&tree.children);
+    printer.lines(&lines, "");
+    if !lines.is_empty() {
+        printer.out.push('\n');
+    }
+
+    Ok(printer.out)
+}
+
+/// A chain or a comment on its own line.
+enum Item<'n, 'a> {
+    Chain(&'n Node<'a>),
+    Comment(&'n Token<'a>),
+}
+
+struct Line<'n, 'a> {
+    item: Item<'n, 'a>,
+    blank_before: bool,
+    /// Comments following the chain on the same line.
+    trailing: Vec<&'n Token<'a>>,
+}
+
+/// Split the content of a program or an argument into lines.
+fn lines<'n, 'a>(children: &'n [Element<'a>]) -> Vec<Line<'n, 'a>> {
+    let mut lines: Vec<Line<'n, 'a>> = Vec::new();
+    let mut newlines = 0;
+
+    for child in children {
+        match child {
+            Element::Node(node) => {
+                lines.push(Line {
+                    item: Item::Chain(node),
+                    blank_before: newlines > 1,
+                    trailing: trailing_comments(node),
+                });
+                newlines = match node.children.last() {
+                    Some(Element::Token(token)) if token.kind == TokenKind::Newline => 1,
+                    _ => 0,
+                };
+            }
+            Element::Token(token) => match token.kind {
+                TokenKind::Newline => newlines += 1,
+                TokenKind::Comment => match lines.last_mut() {
+                    Some(Line {
+                        item: Item::Chain(_),
+                        trailing,
+                        ..
+                    }) if newlines == 0 => trailing.push(token),
+                    _ => {
+                        lines.push(Line {
+                            item: Item::Comment(token),
+                            blank_before: newlines > 1,
+                            trailing: Vec::new(),
+                        });
+                        newlines = 0;
+                    }
+                },
+                _ => (),
+            },
+        }
+    }
+
+    lines
+}
+
+/// Comments after the last message of the chain.
+fn trailing_comments<'n, 'a>(chain: &'n Node<'a>) -> Vec<&'n Token<'a>> {
+    let last_message = chain
+        .children
+        .iter()
+        .rposition(|child| matches!(child, Element::Node(_)))
+        .unwrap_or_default();
+
+    chain.children[last_message..]
+        .iter()
+        .filter_map(|child| match child {
+            Element::Token(token) if token.kind == TokenKind::Comment => Some(token),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Render the node on a single line if it's possible.
+fn inline(node: &Node<'_>) -> Option<String> {
+    let mut out = String::new();
+
+    match node.kind {
+        NodeKind::Chain => {
+            for (i, message) in node.nodes().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&inline(message)?);
+            }
+        }
+        NodeKind::Message => {
+            let symbol = node.tokens().next()?;
+            if symbol.text.contains('\n') {
+                return None;
+            }
+            out.push_str(symbol.text);
+
+            if let Some(arguments) = node.nodes().next() {
+                out.push_str(&inline(arguments)?);
+            }
+        }
+        NodeKind::Arguments => {
+            let (open, close) = brackets(node);
+            out.push_str(open);
+            for (i, argument) in node.nodes().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&inline(argument)?);
+            }
+            out.push_str(close);
+        }
+        NodeKind::Argument => {
+            if node.tokens().any(|token| token.kind == TokenKind::Comment) {
+                return None;
+            }
+
+            let mut chains = node.nodes();
+            let chain = chains.next()?;
+            if chains.next().is_some() {
+                return None;
+            }
+            out.push_str(&inline(chain)?);
+        }
+        NodeKind::Program => return None,
+    }
+
+    Some(out)
+}
+
+fn brackets<'a>(arguments: &Node<'a>) -> (&'a str, &'a str) {
+    let mut brackets = arguments.children.iter().filter_map(|child| match child {
+        Element::Token(token)
+            if matches!(token.kind, TokenKind::OpenBracket | TokenKind::CloseBracket) =>
+        {
+            Some(token.text)
+        }
+        _ => None,
+    });
+
+    (
+        brackets.next().unwrap_or("("),
+        brackets.next().unwrap_or(")"),
+    )
+}
+
+/// Check if the argument is just a name, like the argument names of a method.
+fn is_name(argument: &Node<'_>) -> bool {
+    let mut tokens = argument
+        .tokens()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Newline));
+
+    matches!(
+        (tokens.next(), tokens.next()),
+        (Some(token), None) if token.kind == TokenKind::Identifier
+    )
+}
+
+struct Printer<'o> {
+    options: &'o FormatOptions,
+    out: String,
+    indent: usize,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.out.extend(std::iter::repeat_n(' ', self.indent));
+    }
+
+    fn column(&self) -> usize {
+        self.out
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+    }
+
+    /// Print the lines, putting the `suffix` right after the code of the last line.
+    fn lines(&mut self, lines: &[Line<'_, '_>], suffix: &str) {
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                if line.blank_before {
+                    self.newline();
+                }
+                self.newline();
+            }
+
+            let last = i + 1 == lines.len();
+            match line.item {
+                Item::Comment(comment) => {
+                    self.write(comment.text);
+                    if last && !suffix.is_empty() {
+                        self.newline();
+                        self.write(suffix);
+                    }
+                }
+                Item::Chain(chain) => {
+                    if self.options.comments == CommentPlacement::OwnLine {
+                        for comment in &line.trailing {
+                            self.write(comment.text);
+                            self.newline();
+                        }
+                    }
+
+                    self.chain(chain);
+                    if last {
+                        self.write(suffix);
+                    }
+
+                    if self.options.comments == CommentPlacement::Preserve {
+                        for comment in &line.trailing {
+                            self.write(" ");
+                            self.write(comment.text);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Print the chain without the trailing comments.
+    fn chain(&mut self, chain: &Node<'_>) {
+        let mut comments = Vec::new();
+        let mut first = true;
+
+        for child in &chain.children {
+            match child {
+                Element::Node(message) => {
+                    for comment in comments.drain(..) {
+                        self.write(" ");
+                        self.write(comment);
+                    }
+                    if !first {
+                        self.write(" ");
+                    }
+                    self.message(message);
+                    first = false;
+                }
+                Element::Token(token) if token.kind == TokenKind::Comment => {
+                    comments.push(token.text)
+                }
+                Element::Token(_) => (),
+            }
+        }
+    }
+
+    fn message(&mut self, message: &Node<'_>) {
+        if let Some(symbol) = message.tokens().next() {
+            self.write(symbol.text);
+        }
+
+        if let Some(arguments) = message.nodes().next() {
+            self.arguments(arguments);
+        }
+    }
+
+    fn arguments(&mut self, arguments: &Node<'_>) {
+        if let Some(text) = inline(arguments) {
+            if self.column() + text.chars().count() <= self.options.max_width {
+                self.write(&text);
+                return;
+            }
+        }
+
+        let (open, close) = brackets(arguments);
+        let arguments: Vec<_> = arguments.nodes().collect();
+        // leading names stay on the first line: `method(a, b,`
+        let names = arguments[..arguments.len().saturating_sub(1)]
+            .iter()
+            .take_while(|argument| is_name(argument))
+            .count();
+
+        self.write(open);
+        for argument in &arguments[..names] {
+            self.write(&inline(argument).unwrap_or_default());
+            self.write(", ");
+        }
+
+        self.indent += self.options.indent_width;
+        let rest = &arguments[names..];
+        for (i, argument) in rest.iter().enumerate() {
+            self.newline();
+            let suffix = if i + 1 < rest.len() { "," } else { "" };
+            self.lines(&lines(&argument.children), suffix);
+        }
+        self.indent -= self.options.indent_width;
+
+        self.newline();
+        self.write(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_default(input: &str) -> String {
+        format(input, &FormatOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_layout() {
+        let input = "  foo   bar ;baz\n\n\n\nqux(1 ,2,   3)  ";
+        assert_eq!(format_default(input), "foo bar\nbaz\n\nqux(1, 2, 3)\n");
+    }
+
+    #[test]
+    fn test_wrap_arguments() {
+        let input = r#"#!/usr/bin/env io
+
+ack := method(m, n,
+  //writeln("ack(", m, ",", n, ")")
+  if (m < 1, return n + 1)
+  if (n < 1, return ack(m - 1, 1)) # recursion
+
+  return ack(m - 1, ack(m, n - 1))
+)
+
+ack(3, 4) print
+"\n" print
+"#;
+
+        let expected = r#"#!/usr/bin/env io
+
+ack := method(m, n,
+    //writeln("ack(", m, ",", n, ")")
+    if(m < 1, return n + 1)
+    if(n < 1, return ack(m - 1, 1)) # recursion
+
+    return ack(m - 1, ack(m, n - 1))
+)
+
+ack(3, 4) print
+"\n" print
+"#;
+
+        assert_eq!(format_default(input), expected);
+        assert_eq!(format_default(expected), expected);
+    }
+
+    #[test]
+    fn test_max_width() {
+        let options = FormatOptions {
+            indent_width: 2,
+            max_width: 20,
+            ..Default::default()
+        };
+        let input = "list(first, second thing, third(a, b))";
+        let expected = "list(first,\n  second thing,\n  third(a, b)\n)\n";
+        assert_eq!(format(input, &options).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_comment_placement() {
+        let options = FormatOptions {
+            comments: CommentPlacement::OwnLine,
+            ..Default::default()
+        };
+        let input = "foo /* a */ bar # b\nbaz; // c\n";
+        assert_eq!(
+            format(input, &options).unwrap(),
+            "# b\nfoo /* a */ bar\n// c\nbaz\n"
+        );
+        assert_eq!(format_default(input), "foo /* a */ bar # b\nbaz // c\n");
+    }
+
+    #[test]
+    fn test_preserve_semantics() {
+        let input = "a := list(1,2, 0xff, \"\"\"x\n y\"\"\") map(x, x * 2 + 1);\nb := if(a, c, d)";
+        let output = format_default(input);
+        assert_eq!(crate::parse(&output), crate::parse(input));
+    }
+
+    #[test]
+    fn test_reject_invalid() {
+        assert!(format("foo(", &FormatOptions::default()).is_err());
+    }
+}
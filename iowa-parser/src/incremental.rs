@@ -0,0 +1,491 @@
+//! Incremental reparsing.
+//!
+//! A [`Document`] keeps the source along with its tree. After an edit only the smallest affected
+//! part is parsed again: the arguments of the message around the edit or the top-level chains
+//! around it, and the rest of the tree is reused with the locations shifted.
+
+use std::ops::Range;
+
+use nom::{multi::many0, sequence::delimited, InputTake};
+
+use crate::visit::{self, Fold, VisitMut};
+use crate::{
+    message, message_chain, span, Argument, Input, Message, MessageChain, ParseError, Parser,
+    RewriteAssignments, Shuffle, Span, Symbol,
+};
+
+/// A change of the text: the `range` of the old text is replaced by the `text`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TextEdit {
+    /// Byte range of the replaced text.
+    pub range: Range<usize>,
+    /// The new text.
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Create an edit.
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+        }
+    }
+}
+
+/// The part of the document parsed again after an edit.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Reparse {
+    /// The arguments of a single message.
+    Arguments,
+    /// Some of the top-level chains.
+    Chains,
+    /// The whole document.
+    Full,
+}
+
+/// The source along with its tree.
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    tree: Result<Vec<MessageChain<'static>>, ParseError>,
+}
+
+impl Document {
+    /// Parse the source.
+    pub fn new(parser: &Parser, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let tree = parse_owned(parser, &source);
+        Self { source, tree }
+    }
+
+    /// The source.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The tree, or the error if the source is invalid.
+    pub fn tree(&self) -> Result<&[MessageChain<'static>], &ParseError> {
+        self.tree.as_deref()
+    }
+
+    /// Apply the edit and update the tree.
+    ///
+    /// The tree is the same as the one [`Parser::parse`] returns for the new source, including
+    /// the locations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of the source or isn't on the character boundaries.
+    pub fn edit(&mut self, parser: &Parser, edit: &TextEdit) -> Reparse {
+        let shift = Shift::new(&self.source, edit);
+        self.source.replace_range(edit.range.clone(), &edit.text);
+
+        let Ok(mut chains) = std::mem::replace(&mut self.tree, Ok(Vec::new())) else {
+            self.tree = parse_owned(parser, &self.source);
+            return Reparse::Full;
+        };
+
+        if let Some(path) = find_message(&chains, &edit.range) {
+            let old = message_at(&mut chains, &path);
+            if let Some(msg) = self.reparse_message(parser, old, &shift) {
+                shift.visit_chains(&mut chains);
+                *message_at(&mut chains, &path) = msg;
+                self.tree = Ok(chains);
+                return Reparse::Arguments;
+            }
+        }
+
+        match self.reparse_chains(parser, chains, edit, &shift) {
+            Some(chains) => {
+                self.tree = Ok(chains);
+                Reparse::Chains
+            }
+            None => {
+                self.tree = parse_owned(parser, &self.source);
+                Reparse::Full
+            }
+        }
+    }
+
+    /// The input of the new source starting at the offset.
+    fn input_at<'a>(&'a self, parser: &'a Parser, offset: usize) -> Input<'a> {
+        let (rest, _) = parser.state(&self.source, None).input().take_split(offset);
+        rest
+    }
+
+    /// Parse the message again if it ends at the same closing bracket.
+    fn reparse_message(
+        &self,
+        parser: &Parser,
+        old: &Message<'_>,
+        shift: &Shift,
+    ) -> Option<Message<'static>> {
+        let (rest, msg) = message(self.input_at(parser, old.span.start)).ok()?;
+        if rest.location_offset() != shift.offset(old.span.end)
+            || msg.symbol != old.symbol
+            || msg.args.is_empty()
+        {
+            return None;
+        }
+
+        let msg = Shuffle.fold_message(msg);
+        let msg = RewriteAssignments {
+            operators: parser.operators(),
+        }
+        .fold_message(msg);
+        Some(msg.into_owned())
+    }
+
+    /// Parse the chains from the one before the edit until the parser gets to the start of an
+    /// old chain after the edit.
+    fn reparse_chains(
+        &self,
+        parser: &Parser,
+        old: Vec<MessageChain<'static>>,
+        edit: &TextEdit,
+        shift: &Shift,
+    ) -> Option<Vec<MessageChain<'static>>> {
+        // the chains before the edit are terminated, so the parser starts in the same state there
+        let (first, start) = match old
+            .iter()
+            .rposition(|chain| chain.span.start < edit.range.start)
+        {
+            Some(first) => (first, old[first].span.start),
+            None => (0, 0),
+        };
+        let mut next = old
+            .iter()
+            .position(|chain| chain.span.start >= edit.range.end)
+            .unwrap_or(old.len());
+
+        let mut input = self.input_at(parser, start);
+        let mut chains = Vec::new();
+        while !input.is_empty() {
+            let (rest, chain) =
+                delimited(many0(span::wcpad), message_chain, many0(span::wcpad))(input).ok()?;
+            chains.push(chain);
+            input = rest;
+
+            let pos = input.location_offset();
+            while old
+                .get(next)
+                .is_some_and(|chain| shift.offset(chain.span.start) < pos)
+            {
+                next += 1;
+            }
+            if old
+                .get(next)
+                .is_some_and(|chain| shift.offset(chain.span.start) == pos)
+            {
+                break;
+            }
+        }
+        if input.is_empty() {
+            next = old.len();
+        }
+
+        let mut out = Vec::with_capacity(first + chains.len() + old.len() - next);
+        let mut old = old.into_iter();
+        out.extend(old.by_ref().take(first));
+        out.extend(
+            parser
+                .finish(chains)
+                .into_iter()
+                .map(MessageChain::into_owned),
+        );
+        let mut rest: Vec<_> = old.skip(next - first).collect();
+        shift.visit_chains(&mut rest);
+        out.extend(rest);
+        Some(out)
+    }
+}
+
+fn parse_owned(parser: &Parser, source: &str) -> Result<Vec<MessageChain<'static>>, ParseError> {
+    parser
+        .parse(source)
+        .map(|chains| chains.into_iter().map(MessageChain::into_owned).collect())
+}
+
+/// Find the innermost message whose arguments contain the edit.
+///
+/// The path consists of the chain and message indices followed by the argument, chain and message
+/// indices for each level of nesting.
+fn find_message(chains: &[MessageChain<'_>], edit: &Range<usize>) -> Option<Vec<usize>> {
+    for (i, chain) in chains.iter().enumerate() {
+        for (j, msg) in chain.iter().enumerate() {
+            // the messages added by the rewrites don't have a location, but their arguments do
+            if !msg.span.is_dummy() && !contains(msg.span, edit) {
+                continue;
+            }
+
+            let inner =
+                msg.args.iter().enumerate().find_map(|(k, arg)| {
+                    find_message(arg, edit).map(|path| [vec![k], path].concat())
+                });
+            if let Some(inner) = inner {
+                return Some([vec![i, j], inner].concat());
+            }
+
+            // the edit must be between the brackets
+            if let Symbol::Identifier(ref ident) = msg.symbol {
+                if !msg.args.is_empty()
+                    && contains(msg.span, edit)
+                    && msg.span.start + ident.as_str().len() <= edit.start
+                    && edit.end < msg.span.end
+                {
+                    return Some(vec![i, j]);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn contains(span: Span, edit: &Range<usize>) -> bool {
+    !span.is_dummy() && span.start <= edit.start && edit.end <= span.end
+}
+
+fn message_at<'t, 'a>(chains: &'t mut [MessageChain<'a>], path: &[usize]) -> &'t mut Message<'a> {
+    let msg = &mut chains[path[0]][path[1]];
+    match path[2..] {
+        [] => msg,
+        [arg, ..] => message_at(&mut msg.args[arg], &path[3..]),
+    }
+}
+
+/// Moves the locations after the edit.
+struct Shift {
+    old_end: usize,
+    new_end: usize,
+    old_line: u32,
+    new_line: u32,
+    old_column: usize,
+    new_column: usize,
+}
+
+impl Shift {
+    fn new(source: &str, edit: &TextEdit) -> Self {
+        let (old_line, old_column) = location(source, edit.range.end);
+
+        let new = format!("{}{}", &source[..edit.range.start], edit.text);
+        let (new_line, new_column) = location(&new, new.len());
+
+        Self {
+            old_end: edit.range.end,
+            new_end: new.len(),
+            old_line,
+            new_line,
+            old_column,
+            new_column,
+        }
+    }
+
+    fn offset(&self, offset: usize) -> usize {
+        if offset >= self.old_end {
+            offset - self.old_end + self.new_end
+        } else {
+            offset
+        }
+    }
+
+    fn span(&self, span: Span) -> Span {
+        if span.is_dummy() || span.end < self.old_end {
+            return span;
+        }
+        if span.start < self.old_end {
+            return Span {
+                end: self.offset(span.end),
+                ..span
+            };
+        }
+
+        let column = if span.line == self.old_line {
+            span.column - self.old_column + self.new_column
+        } else {
+            span.column
+        };
+        Span {
+            start: self.offset(span.start),
+            end: self.offset(span.end),
+            line: span.line - self.old_line + self.new_line,
+            column,
+        }
+    }
+
+    fn visit_chains(&self, chains: &mut [MessageChain<'_>]) {
+        let mut shift = self;
+        for chain in chains {
+            shift.visit_chain_mut(chain);
+        }
+    }
+}
+
+impl<'a> VisitMut<'a> for &Shift {
+    fn visit_chain_mut(&mut self, chain: &mut MessageChain<'a>) {
+        chain.span = self.span(chain.span);
+        visit::walk_chain_mut(self, chain);
+    }
+
+    fn visit_message_mut(&mut self, msg: &mut Message<'a>) {
+        msg.span = self.span(msg.span);
+        visit::walk_message_mut(self, msg);
+    }
+
+    fn visit_argument_mut(&mut self, arg: &mut Argument<'a>) {
+        arg.span = self.span(arg.span);
+        visit::walk_argument_mut(self, arg);
+    }
+}
+
+/// The line and the column of the offset, like the parser counts them.
+fn location(source: &str, offset: usize) -> (u32, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() as u32 + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "ack := method(m, n,\n  if(m < 1, return n + 1)\n  if(n < 1, return ack(m - 1, 1))\n  ack(m - 1, ack(m, n - 1))\n)\n\n// the result\nack(2, 3) println\nx += 1; y := list(1, 2) map(* 2)\n";
+
+    /// Apply the edit to the document and compare the tree with a full parse.
+    fn check(doc: &mut Document, edit: TextEdit, reparse: Reparse) {
+        let parser = Parser::default_ref();
+        assert_eq!(doc.edit(parser, &edit), reparse, "{edit:?}");
+
+        let expected = parser.parse(doc.source());
+        match (doc.tree(), expected) {
+            (Ok(tree), Ok(expected)) => {
+                assert_eq!(format!("{tree:?}"), format!("{expected:?}"), "{edit:?}");
+            }
+            (Err(err), Err(expected)) => assert_eq!(err.to_string(), expected.to_string()),
+            (tree, expected) => panic!("{edit:?}: {tree:?} != {expected:?}"),
+        }
+    }
+
+    fn at(source: &str, pat: &str) -> usize {
+        source.find(pat).unwrap()
+    }
+
+    #[test]
+    fn test_edit_arguments() {
+        let mut doc = Document::new(Parser::default_ref(), SOURCE);
+
+        let pos = at(doc.source(), "n + 1");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos + 1, "m * n"),
+            Reparse::Arguments,
+        );
+        let pos = at(doc.source(), "ack(m, n - 1)") + 4;
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos, "\n    "),
+            Reparse::Arguments,
+        );
+        let pos = at(doc.source(), "2, 3");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos + 4, "3,\n4"),
+            Reparse::Arguments,
+        );
+        let pos = at(doc.source(), "* 2");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos + 3, "x + 1; x ** 2"),
+            Reparse::Arguments,
+        );
+        // the edit continues the message
+        let pos = at(doc.source(), "1, 2");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos + 1, "0) foo(1"),
+            Reparse::Chains,
+        );
+    }
+
+    #[test]
+    fn test_edit_chains() {
+        let mut doc = Document::new(Parser::default_ref(), SOURCE);
+
+        let pos = at(doc.source(), "x += 1");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos, "z := 3\n"),
+            Reparse::Chains,
+        );
+        let pos = at(doc.source(), "println");
+        check(
+            &mut doc,
+            TextEdit::new(pos..pos + 7, "print; \"done\" println"),
+            Reparse::Chains,
+        );
+        // merging two chains
+        let pos = at(doc.source(), "; y");
+        check(&mut doc, TextEdit::new(pos..pos + 1, ""), Reparse::Chains);
+        check(
+            &mut doc,
+            TextEdit::new(0..0, "// ackermann\n"),
+            Reparse::Chains,
+        );
+        let end = doc.source().len();
+        check(
+            &mut doc,
+            TextEdit::new(end..end, "ack(1, 1)"),
+            Reparse::Chains,
+        );
+        // deleting everything after the method
+        let pos = at(doc.source(), "\n\n");
+        let end = doc.source().len();
+        check(&mut doc, TextEdit::new(pos..end, ""), Reparse::Chains);
+        let end = doc.source().len();
+        check(&mut doc, TextEdit::new(0..end, ""), Reparse::Chains);
+        check(&mut doc, TextEdit::new(0..0, "a b"), Reparse::Chains);
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let mut doc = Document::new(Parser::default_ref(), SOURCE);
+
+        let pos = at(doc.source(), "n,\n") + 2;
+        check(&mut doc, TextEdit::new(pos..pos, ")"), Reparse::Full);
+        assert!(doc.tree().is_err());
+        check(&mut doc, TextEdit::new(pos..pos + 1, ""), Reparse::Full);
+        assert!(doc.tree().is_ok());
+
+        let pos = at(doc.source(), "ack(2");
+        check(&mut doc, TextEdit::new(pos..pos, "(\n"), Reparse::Full);
+        check(&mut doc, TextEdit::new(pos..pos + 2, ""), Reparse::Full);
+    }
+
+    #[test]
+    fn test_edit_every_position() {
+        let parser = Parser::default_ref();
+        let source = "foo(a, b c) bar\nbaz(1 + qux(3))";
+
+        for start in 0..=source.len() {
+            for end in start..=source.len() {
+                for text in ["", "x", "(", ")", "\n", ", y"] {
+                    let mut doc = Document::new(parser, source);
+                    let edit = TextEdit::new(start..end, text);
+                    let reparse = doc.edit(parser, &edit);
+                    let expected = parser.parse(doc.source());
+                    match (doc.tree(), expected) {
+                        (Ok(tree), Ok(expected)) => assert_eq!(
+                            format!("{tree:?}"),
+                            format!("{expected:?}"),
+                            "{edit:?} {reparse:?}"
+                        ),
+                        (Err(_), Err(_)) => {}
+                        (tree, expected) => panic!("{edit:?}: {tree:?} != {expected:?}"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -11,52 +11,122 @@
     unreachable_pub
 )]
 
+pub mod cst;
+mod error;
+pub mod format;
+pub mod incremental;
+mod recovery;
+#[cfg(feature = "serde")]
+mod serialize;
 mod span;
+pub mod stream;
 mod symbol;
+pub mod visit;
 
+use std::cell::RefCell;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::OnceLock;
 
 use nom::{
     branch::alt,
     character::complete::char,
-    combinator::{all_consuming, opt},
+    combinator::{all_consuming, cut, opt, value},
     multi::{many0, many1, separated_list0},
     sequence::{delimited, preceded, terminated},
     IResult,
 };
+use nom_locate::LocatedSpan;
 use rayon::prelude::*;
 
+pub use error::{ErrorKind, ParseError, ParseReport};
+#[cfg(feature = "serde")]
+pub use serialize::TreeSeed;
+pub use span::Span;
 pub use symbol::*;
 
+use visit::Fold;
+
+/// Parser input, which keeps track of the position in the source.
+pub(crate) type Input<'a> = LocatedSpan<&'a str, State<'a>>;
+
+/// The state shared by the parsers during a single run.
+#[derive(Debug, Clone)]
+pub(crate) struct State<'a> {
+    /// The whole source.
+    pub(crate) source: &'a str,
+    /// The errors the parser recovered from, if it recovers from errors.
+    pub(crate) errors: Option<Rc<RefCell<Vec<ParseError>>>>,
+    /// The operators known to the parser.
+    pub(crate) operators: &'a OperatorTable,
+}
+
+impl<'a> State<'a> {
+    pub(crate) fn input(self) -> Input<'a> {
+        Input::new_extra(self.source, self)
+    }
+}
+
+pub(crate) type PResult<'a, O> = IResult<Input<'a>, O, error::Error<'a>>;
+
 /// A chain of messages is a list of messages before a terminator.
-#[derive(Debug, Default, PartialEq, Clone)]
-pub struct MessageChain<'a>(Vec<Message<'a>>);
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageChain<'a> {
+    messages: Vec<Message<'a>>,
+    span: Span,
+}
 
 impl<'a> MessageChain<'a> {
     /// Create a new message chain.
+    ///
+    /// The span of the chain covers the spans of the messages.
     pub fn new(messages: Vec<Message<'a>>) -> Self {
-        Self(messages)
+        let span = messages
+            .iter()
+            .fold(Span::default(), |span, msg| span.merge(msg.span));
+        Self { messages, span }
     }
 
+    /// The location of the chain in the source.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Convert to a chain which doesn't borrow the source.
+    ///
+    /// The owned tree can be cached, sent to another thread or built at runtime independently of
+    /// the source.
+    pub fn into_owned(self) -> MessageChain<'static> {
+        MessageChain {
+            messages: self.messages.into_iter().map(Message::into_owned).collect(),
+            span: self.span,
+        }
+    }
+
+    /// Push a message to the end of the chain, extending the span of the chain.
+    pub fn push(&mut self, msg: Message<'a>) {
+        self.span = self.span.merge(msg.span);
+        self.messages.push(msg);
+    }
+
+    /// Turn the operator sugar of the chain and its arguments into ordinary messages.
     fn sort(self) -> Self {
+        Shuffle.fold_chain(self)
+    }
+
+    /// Group the operator messages with their operands according to the precedence.
+    ///
+    /// The chain must be desugared and its arguments must be sorted.
+    fn sort_operators(self) -> Self {
         let mut stack: Vec<Message> = Vec::new();
         let mut output = Vec::new();
 
-        for mut msg in self.desugar_operators().0.into_iter() {
-            // sort arguments
-            msg.args = msg
-                .args
-                .into_par_iter()
-                .map(|arg| {
-                    arg.0
-                        .into_par_iter()
-                        .map(|chain: MessageChain| chain.sort())
-                        .collect::<Vec<MessageChain>>()
-                        .into()
-                })
-                .collect();
+        for msg in self.messages.into_iter() {
             match msg.symbol {
-                Symbol::Operator(ref msg_op) => {
+                // postfix operators are ordinary messages sent to the operand
+                Symbol::Operator(ref msg_op) if msg_op.arity() != Arity::Postfix => {
                     if let Some(top) = stack.last() {
                         if msg_op.precedence() > top.symbol.as_ref_op().precedence() {
                             Self::fold_op_args(&mut stack);
@@ -76,32 +146,114 @@ impl<'a> MessageChain<'a> {
     }
 
     fn desugar_operators(self) -> Self {
-        let mut stack = Vec::new();
+        let mut stack: Vec<Message> = Vec::new();
         let mut output = Vec::new();
 
-        for msg in self.0.into_iter() {
-            match msg.symbol {
-                Symbol::Operator(_) => {
-                    if let Some(top) = stack.pop() {
-                        output.push(top);
-                    }
-                    stack.push(msg);
-                }
-                _ => match stack.last_mut() {
+        for msg in self.messages.into_iter() {
+            let arity = match msg.symbol {
+                Symbol::Operator(ref op) => Some(op.arity()),
+                _ => None,
+            };
+
+            match arity {
+                None | Some(Arity::Postfix) => match stack.last_mut() {
                     Some(top) => top.push_to_first_arg(msg),
                     None => output.push(msg),
                 },
+                Some(arity) => {
+                    // an operator in place of the operand is applied to the next operand: `a * -b`
+                    let prefix = arity == Arity::Prefix
+                        || stack.last().is_some_and(|top| top.args.is_empty());
+                    if !prefix {
+                        Self::collapse(&mut stack, &mut output);
+                    }
+                    stack.push(msg);
+                }
+            }
+        }
+
+        Self::collapse(&mut stack, &mut output);
+        output.into()
+    }
+
+    /// Push each operator on the stack to the first argument of the one below it and move the
+    /// result to the output.
+    fn collapse(stack: &mut Vec<Message<'a>>, output: &mut Vec<Message<'a>>) {
+        if let Some(mut msg) = stack.pop() {
+            while let Some(mut outer) = stack.pop() {
+                outer.push_to_first_arg(msg);
+                msg = outer;
+            }
+            output.push(msg);
+        }
+    }
+
+    /// Rewrite the assignments to the slot messages: `a := b` to `setSlot("a", b)`.
+    ///
+    /// The chain must be sorted, so the right side of the assignment is in the first argument of
+    /// the operator message. The arguments aren't rewritten.
+    fn rewrite_assignments(self, operators: &OperatorTable) -> Self {
+        let mut output: Vec<Message> = Vec::with_capacity(self.messages.len());
+
+        for msg in self.messages.into_iter() {
+            let assign = match msg.symbol {
+                Symbol::Operator(ref op) => operators.get_assign(op.symbol()),
+                _ => None,
+            };
+            // only a plain name can be assigned to: `a := b`, but not `a(1) := b` or `1 := b`
+            let target = output
+                .last()
+                .filter(|target| matches!(target.symbol, Symbol::Identifier(_)))
+                .filter(|target| target.args.is_empty());
+
+            match (assign, target) {
+                (Some(assign), Some(_)) => {
+                    let target = output.pop().unwrap();
+                    output.push(Self::assignment(assign, target, msg, operators));
+                }
+                _ => output.push(msg),
             }
         }
 
-        output.append(&mut stack);
         output.into()
     }
 
+    fn assignment(
+        assign: &AssignOperator,
+        target: Message<'a>,
+        msg: Message<'a>,
+        operators: &OperatorTable,
+    ) -> Message<'a> {
+        let span = target.span.merge(msg.span);
+        let Symbol::Identifier(ref name) = target.symbol else {
+            unreachable!("only names are assigned to");
+        };
+        let name = Message::new(Symbol::Quote(name.as_str().into()), vec![]).with_span(target.span);
+
+        let mut args = vec![Argument::from([MessageChain::new(vec![name])])];
+        match assign.operator {
+            Some(symbol) => {
+                let op = match operators.get(symbol) {
+                    Some(op) => op.clone(),
+                    None => OperatorRef::new(CustomOperator::new(symbol, ASSIGN_PRECEDENCE)),
+                };
+                let value = Message::new(Symbol::Operator(op), msg.args).with_span(msg.span);
+                args.push(Argument::from([MessageChain::new(vec![target, value])]));
+            }
+            None => args.extend(msg.args),
+        }
+
+        Message::new(Symbol::Identifier(assign.message.into()), args).with_span(span)
+    }
+
     fn fold_op_args(stack: &mut Vec<Message>) {
         if let Some(mut top) = stack.pop() {
             while let Some(mut next) = stack.pop() {
-                if top.symbol.as_ref_op().precedence() < next.symbol.as_ref_op().precedence() {
+                if top
+                    .symbol
+                    .as_ref_op()
+                    .binds_tighter(next.symbol.as_ref_op())
+                {
                     next.push_to_first_arg(top);
                     top = next;
                 } else {
@@ -115,6 +267,27 @@ impl<'a> MessageChain<'a> {
     }
 }
 
+/// The operator shuffler, which turns the operator sugar into ordinary messages.
+struct Shuffle;
+
+impl<'a> Fold<'a> for Shuffle {
+    fn fold_chain(&mut self, chain: MessageChain<'a>) -> MessageChain<'a> {
+        // desugaring moves the operands into the arguments, so it goes before sorting them
+        visit::fold_chain(self, chain.desugar_operators()).sort_operators()
+    }
+}
+
+/// Rewrites the assignments to the slot messages.
+struct RewriteAssignments<'t> {
+    operators: &'t OperatorTable,
+}
+
+impl<'a> Fold<'a> for RewriteAssignments<'_> {
+    fn fold_chain(&mut self, chain: MessageChain<'a>) -> MessageChain<'a> {
+        visit::fold_chain(self, chain).rewrite_assignments(self.operators)
+    }
+}
+
 impl<'a, M: Into<Vec<Message<'a>>>> From<M> for MessageChain<'a> {
     fn from(messages: M) -> Self {
         Self::new(messages.into())
@@ -125,24 +298,79 @@ impl<'a> Deref for MessageChain<'a> {
     type Target = Vec<Message<'a>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.messages
     }
 }
 
 impl DerefMut for MessageChain<'_> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.messages
+    }
+}
+
+// spans are ignored, so that the trees parsed from different sources can be compared
+impl PartialEq for MessageChain<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.messages == other.messages
+    }
+}
+
+/// Prints the chain as Io code.
+///
+/// Operator messages are printed in the operator form (`a + b` instead of `a +(b)`) when parsing
+/// the output gives the same tree.
+impl fmt::Display for MessageChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, msg) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            if msg.is_operator_form(self.get(i + 1)) {
+                write!(f, "{} {}", msg.symbol, msg.args[0][0])?;
+            } else {
+                write!(f, "{msg}")?;
+            }
+        }
+
+        Ok(())
     }
 }
 
 /// Argument type.
-#[derive(Debug, Default, PartialEq, Clone)]
-pub struct Argument<'a>(Vec<MessageChain<'a>>);
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Argument<'a> {
+    chains: Vec<MessageChain<'a>>,
+    span: Span,
+}
 
 impl<'a> Argument<'a> {
     /// Create a new argument.
-    pub fn new(messages: Vec<MessageChain<'a>>) -> Self {
-        Self(messages)
+    ///
+    /// The span of the argument covers the spans of the chains.
+    pub fn new(chains: Vec<MessageChain<'a>>) -> Self {
+        let span = chains
+            .iter()
+            .fold(Span::default(), |span, chain| span.merge(chain.span));
+        Self { chains, span }
+    }
+
+    /// The location of the argument in the source.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Convert to an argument which doesn't borrow the source.
+    pub fn into_owned(self) -> Argument<'static> {
+        Argument {
+            chains: self
+                .chains
+                .into_iter()
+                .map(MessageChain::into_owned)
+                .collect(),
+            span: self.span,
+        }
     }
 }
 
@@ -156,23 +384,45 @@ impl<'a> Deref for Argument<'a> {
     type Target = Vec<MessageChain<'a>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.chains
     }
 }
 
 impl DerefMut for Argument<'_> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.chains
+    }
+}
+
+impl PartialEq for Argument<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.chains == other.chains
+    }
+}
+
+impl fmt::Display for Argument<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chain) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{chain}")?;
+        }
+
+        Ok(())
     }
 }
 
 /// The Message type.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message<'a> {
     /// The message.
     pub symbol: Symbol<'a>,
     /// Arguments.
     pub args: Vec<Argument<'a>>,
+    /// Location of the message in the source, starting at the symbol and including the arguments.
+    pub span: Span,
 }
 
 impl<'a> Message<'a> {
@@ -180,17 +430,71 @@ impl<'a> Message<'a> {
     pub fn new(symbol: Symbol<'a>, args: Vec<Argument<'a>>) -> Self {
         Self {
             symbol,
-            args: args.into(),
+            args,
+            span: Span::default(),
+        }
+    }
+
+    /// Convert to a message which doesn't borrow the source.
+    pub fn into_owned(self) -> Message<'static> {
+        Message {
+            symbol: self.symbol.into_owned(),
+            args: self.args.into_iter().map(Argument::into_owned).collect(),
+            span: self.span,
         }
     }
 
+    /// Set the location of the message.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
     /// Push a message to the first argument.
+    ///
+    /// The spans of the first argument and of the message are extended to cover the pushed
+    /// message.
     pub fn push_to_first_arg(&mut self, msg: Message<'a>) {
         if self.args.is_empty() {
             self.args.push(Argument::from([MessageChain::default()]));
         }
 
-        self.args[0][0].push(msg);
+        let span = msg.span;
+        let arg = &mut self.args[0];
+        arg[0].push(msg);
+        arg.span = arg.span.merge(span);
+        self.span = self.span.merge(span);
+    }
+
+    /// Check if the operator message can be printed without brackets when followed by `next`.
+    ///
+    /// The single argument must bind tighter than the operator and `next` must not bind tighter,
+    /// otherwise parsing the output would group the messages differently.
+    fn is_operator_form(&self, next: Option<&Message<'_>>) -> bool {
+        let Symbol::Operator(ref op) = self.symbol else {
+            return false;
+        };
+        let [arg] = self.args.as_slice() else {
+            return false;
+        };
+        let [chain] = arg.as_slice() else {
+            return false;
+        };
+
+        let binds_tighter = |msg: &Message<'_>| match msg.symbol {
+            Symbol::Operator(ref other) if other.arity() != Arity::Postfix => {
+                other.binds_tighter(&**op)
+            }
+            _ => true,
+        };
+
+        chain
+            .first()
+            .is_some_and(|first| !matches!(first.symbol, Symbol::Operator(_)))
+            && chain.iter().all(binds_tighter)
+            && next.is_none_or(|next| {
+                matches!(next.symbol, Symbol::Operator(_)) && !binds_tighter(next)
+            })
     }
 }
 
@@ -206,51 +510,358 @@ impl<'a, A: Into<Vec<Argument<'a>>>> From<(Symbol<'a>, A)> for Message<'a> {
     }
 }
 
+impl PartialEq for Message<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.symbol == other.symbol && self.args == other.args
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol)?;
+
+        if !self.args.is_empty() {
+            f.write_str("(")?;
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{arg}")?;
+            }
+            f.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Io parser.
+///
+/// Each parser owns its operator table, so parsers with different sets of operators can be used
+/// at the same time.
+///
+/// ```
+/// use iowa_parser::{CustomOperator, Parser};
+///
+/// let mut parser = Parser::default();
+/// parser.add_operator(CustomOperator::new("~>", 6));
+/// assert!(parser.parse("a ~> b").is_ok());
+/// assert!(iowa_parser::parse("a ~> b").is_err());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Parser {
+    operators: OperatorTable,
+}
+
+impl Parser {
+    /// Create a parser with the operator table.
+    pub fn new(operators: OperatorTable) -> Self {
+        Self { operators }
+    }
+
+    /// The shared parser with the default operator table.
+    pub(crate) fn default_ref() -> &'static Self {
+        static INSTANCE: OnceLock<Parser> = OnceLock::new();
+        INSTANCE.get_or_init(Self::default)
+    }
+
+    /// The operator table of the parser.
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
+
+    /// The mutable operator table of the parser.
+    pub fn operators_mut(&mut self) -> &mut OperatorTable {
+        &mut self.operators
+    }
+
+    /// Add an operator (if it's not there already).
+    ///
+    /// See [`OperatorTable::add_operator`].
+    pub fn add_operator(&mut self, operator: impl Operator) -> bool {
+        self.operators.add_operator(operator)
+    }
+
+    /// Remove the operator with the symbol.
+    ///
+    /// See [`OperatorTable::remove_operator`].
+    pub fn remove_operator(&mut self, symbol: &str) -> Option<OperatorRef> {
+        self.operators.remove_operator(symbol)
+    }
+
+    /// Change the precedence of the operator with the symbol.
+    ///
+    /// See [`OperatorTable::set_precedence`].
+    pub fn set_precedence(&mut self, symbol: &str, precedence: u32) -> bool {
+        self.operators.set_precedence(symbol, precedence)
+    }
+
+    fn state<'a>(
+        &'a self,
+        input: &'a str,
+        errors: Option<Rc<RefCell<Vec<ParseError>>>>,
+    ) -> State<'a> {
+        State {
+            source: input,
+            errors,
+            operators: &self.operators,
+        }
+    }
+
+    /// Parse the input.
+    pub fn parse<'a>(&'a self, input: &'a str) -> Result<Vec<MessageChain<'a>>, ParseError> {
+        let (_, chains) = all_consuming(many0(delimited(
+            many0(span::wcpad),
+            message_chain,
+            many0(span::wcpad),
+        )))(self.state(input, None).input())
+        .map_err(|err| match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => ParseError::from(err),
+            nom::Err::Incomplete(_) => unreachable!("the parser works on complete input"),
+        })?;
+
+        Ok(self.finish(chains))
+    }
+
+    /// Parse the input recovering from errors.
+    ///
+    /// See [`parse_with_recovery`].
+    pub fn parse_with_recovery<'a>(
+        &'a self,
+        input: &'a str,
+    ) -> (Vec<MessageChain<'a>>, Vec<ParseError>) {
+        let errors = Rc::default();
+        let chains = recover_chains(self.state(input, Some(Rc::clone(&errors))).input());
+
+        (self.finish(chains), errors.take())
+    }
+
+    /// Sort the operators and rewrite the assignments.
+    fn finish<'a>(&self, chains: Vec<MessageChain<'a>>) -> Vec<MessageChain<'a>> {
+        chains
+            .into_par_iter()
+            .map(|chain| {
+                let mut rewrite = RewriteAssignments {
+                    operators: &self.operators,
+                };
+                rewrite.fold_chain(chain.sort())
+            })
+            .collect()
+    }
+
+    /// Build the [concrete syntax tree](cst) of the input.
+    pub fn parse_cst<'a>(&'a self, input: &'a str) -> cst::Node<'a> {
+        cst::build(self.state(input, None))
+    }
+}
+
 /// Parser entry-point.
-pub fn parse(input: &str) -> IResult<&str, Vec<MessageChain<'_>>> {
-    let (rest, chains) = all_consuming(many0(delimited(
-        many0(span::wcpad),
-        message_chain,
-        many0(span::wcpad),
-    )))(input)?;
+///
+/// Parses the input with the default operators, see [`Parser`] for custom ones.
+pub fn parse(input: &str) -> Result<Vec<MessageChain<'_>>, ParseError> {
+    Parser::default_ref().parse(input)
+}
 
-    Ok((rest, chains.into_par_iter().map(|c| c.sort()).collect()))
+/// Parse the input recovering from errors.
+///
+/// Unlike [`parse`], it doesn't stop at the first error. The parts of the source which can't be
+/// parsed are replaced by messages with [`Symbol::Error`] and parsing continues from the next
+/// terminator, or inside brackets also the next separator or closing bracket. Returns the tree
+/// along with all the errors in the input.
+pub fn parse_with_recovery(input: &str) -> (Vec<MessageChain<'_>>, Vec<ParseError>) {
+    Parser::default_ref().parse_with_recovery(input)
 }
 
-fn message_chain(input: &str) -> IResult<&str, MessageChain<'_>> {
+fn recover_chains(mut input: Input<'_>) -> Vec<MessageChain<'_>> {
+    let mut chains = Vec::new();
+
+    loop {
+        let res = many0(span::wcpad)(input.clone()).and_then(|(rest, _)| {
+            if rest.is_empty() {
+                Ok((rest, None))
+            } else {
+                message_chain(rest).map(|(rest, chain)| (rest, Some(chain)))
+            }
+        });
+
+        match res {
+            Ok((_, None)) => break,
+            Ok((rest, Some(chain))) => {
+                chains.push(chain);
+                input = rest;
+            }
+            Err(err) => {
+                recovery::record(&err);
+                let (rest, skipped) = recovery::skip_until(input, recovery::CHAIN_STOPS);
+                chains.push(recovery::error_chain(&skipped, &rest));
+                input = match opt(span::terminator)(rest.clone()) {
+                    Ok((rest, _)) => rest,
+                    Err(_) => rest,
+                };
+            }
+        }
+    }
+
+    chains
+}
+
+fn message_chain(input: Input<'_>) -> PResult<'_, MessageChain<'_>> {
     let (input, messages) = many1(message)(input)?;
     let (input, _) = opt(span::terminator)(input)?;
     Ok((input, MessageChain::new(messages)))
 }
 
-fn message(input: &str) -> IResult<&str, Message<'_>> {
-    let (rest, _) = many0(span::scpad)(input)?;
-    let (rest, symbol) = symbol(rest)?;
-    let (rest, _) = opt(span::scpad)(rest)?;
+fn message(input: Input<'_>) -> PResult<'_, Message<'_>> {
+    let (start, _) = many0(span::scpad)(input)?;
+    let (end, symbol) = symbol(start.clone())?;
+    let (rest, _) = opt(span::scpad)(end.clone())?;
     let (rest, args) = opt(arguments)(rest)?;
-    Ok((rest, Message::new(symbol, args.unwrap_or_default())))
+    let end = if args.is_some() { rest.clone() } else { end };
+    Ok((
+        rest,
+        Message::new(symbol, args.unwrap_or_default()).with_span(Span::between(&start, &end)),
+    ))
 }
 
-fn arguments(input: &str) -> IResult<&str, Vec<Argument<'_>>> {
+fn arguments(input: Input<'_>) -> PResult<'_, Vec<Argument<'_>>> {
     alt((
-        delimited(
-            char('('),
+        bracketed(
+            '(',
             terminated(
                 separated_list0(char(','), argument),
                 opt(preceded(char(','), many0(span::wcpad))),
             ),
-            char(')'),
+            ')',
         ),
-        delimited(char('['), separated_list0(char(','), argument), char(']')),
-        delimited(char('{'), separated_list0(char(','), argument), char('}')),
+        bracketed('[', separated_list0(char(','), argument), ']'),
+        bracketed('{', separated_list0(char(','), argument), '}'),
     ))(input)
 }
 
-fn argument(input: &str) -> IResult<&str, Argument<'_>> {
-    let (input, _) = many0(span::wcpad)(input)?;
-    let (input, messages) = many1(message_chain)(input)?;
-    let (input, _) = many0(span::wcpad)(input)?;
-    Ok((input, Argument::new(messages)))
+/// Once the opening bracket is matched, any error inside is reported as a failure. Reaching the
+/// end of the input before the closing bracket is reported at the opening bracket.
+///
+/// In the recovering mode, anything else than the closing bracket after the arguments is skipped
+/// up to the next separator or terminator, and the arguments after it are kept.
+fn bracketed<'a>(
+    open: char,
+    inner: impl FnMut(Input<'a>) -> PResult<'a, Vec<Argument<'a>>>,
+    close: char,
+) -> impl FnMut(Input<'a>) -> PResult<'a, Vec<Argument<'a>>> {
+    let mut inner = cut(inner);
+
+    move |input: Input<'a>| {
+        let unbalanced = |err: nom::Err<error::Error<'a>>| {
+            err.map(|err| {
+                if err.kind == ErrorKind::UnexpectedEnd {
+                    error::Error {
+                        input: input.clone(),
+                        kind: ErrorKind::UnbalancedBracket(open),
+                        ..err
+                    }
+                } else {
+                    err
+                }
+            })
+        };
+
+        let (mut rest, _) = char(open)(input.clone())?;
+        let mut args = Vec::new();
+        loop {
+            match inner(rest.clone()) {
+                Ok((after, more)) => {
+                    args.extend(more);
+                    rest = after;
+                }
+                Err(err) => {
+                    let err = unbalanced(err);
+                    if !recovery::record(&err) {
+                        return Err(err);
+                    }
+                }
+            }
+
+            match cut(char(close))(rest.clone()) {
+                Ok((rest, _)) => return Ok((rest, args)),
+                Err(err) => {
+                    let err = unbalanced(err);
+                    if !recovery::record(&err) {
+                        return Err(err);
+                    }
+                    if rest.is_empty() {
+                        return Ok((rest, args));
+                    }
+                }
+            }
+
+            let (after, _) = recovery::skip_until(rest, recovery::ARGUMENT_CHAIN_STOPS);
+            let (after, _) = opt(alt((value((), char(',')), span::terminator)))(after)?;
+            let (after, _) = many0(span::wcpad)(after)?;
+            // the error is the missing bracket, which is reported already
+            if after.is_empty() {
+                return Ok((after, args));
+            }
+            rest = after;
+        }
+    }
+}
+
+fn argument(mut input: Input<'_>) -> PResult<'_, Argument<'_>> {
+    let mut chains = Vec::new();
+
+    loop {
+        let (rest, _) = many0(span::wcpad)(input.clone())?;
+        if !starts_argument(&rest) {
+            input = rest;
+            break;
+        }
+
+        match message_chain(rest.clone()) {
+            Ok((after, chain)) => {
+                chains.push(chain);
+                input = after;
+            }
+            Err(err) if rest.extra.errors.is_none() => match err {
+                // anything else before the end of the argument is an error of the brackets
+                nom::Err::Error(_) if !chains.is_empty() => {
+                    input = rest;
+                    break;
+                }
+                err => return Err(err),
+            },
+            Err(err) => {
+                recovery::record(&err);
+                let (after, skipped) = recovery::skip_until(rest, recovery::ARGUMENT_CHAIN_STOPS);
+                chains.push(recovery::error_chain(&skipped, &after));
+                let (after, _) = opt(span::terminator)(after)?;
+                input = after;
+            }
+        }
+    }
+
+    // an empty argument isn't an error: the list may be empty or have a trailing comma
+    if chains.is_empty() {
+        return Err(nom::Err::Error(error::Error::unexpected(input)));
+    }
+    Ok((input, Argument::new(chains)))
+}
+
+/// Check if there's something in the input before the end of the argument.
+fn starts_argument(input: &Input<'_>) -> bool {
+    input
+        .fragment()
+        .trim_start()
+        .starts_with(|ch| !recovery::ARGUMENT_STOPS.contains(&ch))
+}
+
+#[cfg(test)]
+pub(crate) fn test_run<'a, O>(
+    mut parser: impl FnMut(Input<'a>) -> PResult<'a, O>,
+    input: &'a str,
+) -> IResult<&'a str, O, ErrorKind> {
+    parser(Parser::default_ref().state(input, None).input())
+        .map(|(rest, out)| (*rest.fragment(), out))
+        .map_err(|err| err.map(|err| err.kind))
 }
 
 #[cfg(test)]
@@ -292,27 +903,27 @@ mod tests {
         ]
         .into();
 
-        assert_eq!(arguments(input), Ok(("", expected)));
+        assert_eq!(test_run(arguments, input), Ok(("", expected)));
     }
 
     #[test]
     fn test_parse_message() {
         let input = "foo";
         assert_eq!(
-            message(input),
+            test_run(message, input),
             Ok(("", Symbol::Identifier("foo".into()).into()))
         );
 
         let input = "foo()";
         assert_eq!(
-            message(input),
+            test_run(message, input),
             Ok(("", Symbol::Identifier("foo".into()).into()))
         );
 
         let input = "foo(1, bar baz)";
 
         assert_eq!(
-            message(input),
+            test_run(message, input),
             Ok((
                 "",
                 Message::new(
@@ -336,7 +947,7 @@ mod tests {
     fn test_parse_message_chain() {
         let input = "foo bar baz";
         assert_eq!(
-            message_chain(input),
+            test_run(message_chain, input),
             Ok((
                 "",
                 MessageChain::new(vec![
@@ -349,7 +960,7 @@ mod tests {
 
         let input = "foo bar baz;";
         assert_eq!(
-            message_chain(input),
+            test_run(message_chain, input),
             Ok((
                 "",
                 MessageChain::new(vec![
@@ -362,7 +973,7 @@ mod tests {
 
         let input = "foo() bar(1) baz;";
         assert_eq!(
-            message_chain(input),
+            test_run(message_chain, input),
             Ok((
                 "",
                 [
@@ -379,42 +990,333 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_blank_lines_in_argument() {
+        let input = "(\n  foo\n\n  // comment\n\n  bar\n)";
+        let expected: Vec<Argument<'_>> = [[
+            [Symbol::Identifier("foo".into()).into()].into(),
+            [Symbol::Identifier("bar".into()).into()].into(),
+        ]
+        .into()]
+        .into();
+
+        assert_eq!(test_run(arguments, input), Ok(("", expected)));
+    }
+
+    #[test]
+    fn test_parse_trailing_comments() {
+        for input in ["x := 1 # one\ny := 2\n", "x := 1 // one\r\ny := 2"] {
+            let chains = parse(input).unwrap();
+            assert_eq!(chains.len(), 2, "{input:?}");
+            assert_eq!(chains[1].span().line, 2, "{input:?}");
+        }
+    }
+
     #[test]
     fn test_desugar_operators() {
         let input = "foo bar + baz qux * foo bar";
-        let chain = message_chain(input).unwrap().1;
-        let expected = message_chain("foo bar +(baz qux) *(foo bar)").unwrap().1;
+        let chain = test_run(message_chain, input).unwrap().1;
+        let expected = test_run(message_chain, "foo bar +(baz qux) *(foo bar)")
+            .unwrap()
+            .1;
         assert_eq!(chain.desugar_operators(), expected,);
     }
 
     #[test]
     fn test_sort_message_chain() {
         let input = "1 >> 2 + 3";
-        let expected = message_chain("1 >>(2 +(3))").unwrap().1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+        let expected = test_run(message_chain, "1 >>(2 +(3))").unwrap().1;
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
 
         let input = "1 * 2 + 3 >> 4";
-        let expected = message_chain("1 *(2) +(3) >>(4)").unwrap().1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+        let expected = test_run(message_chain, "1 *(2) +(3) >>(4)").unwrap().1;
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
 
         let input = "1 + 2 * 3 + 4 >> 5";
-        let expected = message_chain("1 +(2 *(3)) +(4) >>(5)").unwrap().1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+        let expected = test_run(message_chain, "1 +(2 *(3)) +(4) >>(5)").unwrap().1;
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
 
         let input = "1 >> 2 + 3 * 4 + 5 >> 6";
-        let expected = message_chain("1 >>(2 +(3 *(4)) +(5)) >>(6)").unwrap().1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+        let expected = test_run(message_chain, "1 >>(2 +(3 *(4)) +(5)) >>(6)")
+            .unwrap()
+            .1;
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
 
         let input = "1 >> 2 bar + 3 * baz qux + 4 >> 5";
-        let expected = message_chain("1 >>(2 bar +(3 *(baz qux)) +(4)) >>(5)")
+        let expected = test_run(message_chain, "1 >>(2 bar +(3 *(baz qux)) +(4)) >>(5)")
             .unwrap()
             .1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
 
         let input = "1 >> 2 bar + 3 * baz qux(2 + 2 * 2 >> 3) + 4 >> 5";
-        let expected = message_chain("1 >>(2 bar +(3 *(baz qux(2 +(2 *(2)) >>(3)))) +(4)) >>(5)")
+        let expected = test_run(
+            message_chain,
+            "1 >>(2 bar +(3 *(baz qux(2 +(2 *(2)) >>(3)))) +(4)) >>(5)",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(test_run(message_chain, input).unwrap().1.sort(), expected);
+    }
+
+    #[test]
+    fn test_message_spans() {
+        let input = "foo bar(1,\n  2) baz";
+        let chain = test_run(message_chain, input).unwrap().1;
+
+        let spans: Vec<_> = chain.iter().map(|msg| msg.span).collect();
+        assert_eq!(
+            spans,
+            [
+                Span {
+                    start: 0,
+                    end: 3,
+                    line: 1,
+                    column: 1,
+                },
+                Span {
+                    start: 4,
+                    end: 15,
+                    line: 1,
+                    column: 5,
+                },
+                Span {
+                    start: 16,
+                    end: 19,
+                    line: 2,
+                    column: 6,
+                },
+            ]
+        );
+        assert_eq!(
+            &input[chain[1].span.start..chain[1].span.end],
+            "bar(1,\n  2)"
+        );
+
+        let second_arg = chain[1].args[1].span();
+        assert_eq!(&input[second_arg.start..second_arg.end], "2");
+        assert_eq!((second_arg.line, second_arg.column), (2, 3));
+
+        assert_eq!(chain.span().start, 0);
+        assert_eq!(chain.span().end, input.len());
+    }
+
+    #[test]
+    fn test_operator_spans() {
+        let input = "a +  b c * d";
+        let chain = parse(input).unwrap().remove(0);
+
+        let plus = &chain[1];
+        assert_eq!(&input[plus.span.start..plus.span.end], "+  b c * d");
+
+        let arg = plus.args[0].span();
+        assert_eq!(&input[arg.start..arg.end], "b c * d");
+        assert_eq!(arg.column, 6);
+
+        let mul = &plus.args[0][0][2];
+        assert_eq!(&input[mul.span.start..mul.span.end], "* d");
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            (
+                "foo bar(1, baz qux) \"a\\nb\"",
+                "foo bar(1, baz qux) \"a\\nb\"",
+            ),
+            ("foo(a; b)\n", "foo(a; b)"),
+            ("1 + 2 * 3 + 4 >> 5", "1 + 2 * 3 + 4 >> 5"),
+            ("1 *(2 + 3)", "1 *(2 + 3)"),
+            ("1 -(2 - 3) - 4", "1 -(2 - 3) - 4"),
+            ("a +(b) foo", "a + b foo"),
+            (
+                "ack := method(m, n, return n + 1)",
+                "setSlot(\"ack\", method(m, n, return n + 1))",
+            ),
+            ("0x1F 1.5 1e3", "0x1F 1.5 1000"),
+        ];
+
+        for (input, expected) in cases {
+            let chains = parse(input).unwrap();
+            let output = chains[0].to_string();
+            assert_eq!(output, expected);
+            assert_eq!(parse(&output).unwrap(), chains, "{output}");
+        }
+    }
+
+    #[test]
+    fn test_rewrite_assignments() {
+        let cases = [
+            ("a := b c", "setSlot(\"a\", b c)"),
+            ("a = 1 + 2", "updateSlot(\"a\", 1 + 2)"),
+            ("a ::= 1", "newSlot(\"a\", 1)"),
+            ("foo bar := baz", "foo setSlot(\"bar\", baz)"),
+            ("a += 2 * 3", "updateSlot(\"a\", a + 2 * 3)"),
+            ("a <<= 1", "updateSlot(\"a\", a << 1)"),
+            (
+                "x := method(y := 1)",
+                "setSlot(\"x\", method(setSlot(\"y\", 1)))",
+            ),
+            // not a name on the left
+            ("a(1) := 2", "a(1) := 2"),
+            ("a == b", "a == b"),
+        ];
+
+        for (input, expected) in cases {
+            let chains = parse(input).unwrap();
+            assert_eq!(chains[0].to_string(), expected, "{input}");
+        }
+
+        let input = "foo := 1";
+        let msg = &parse(input).unwrap()[0][0];
+        assert_eq!(&input[msg.span.start..msg.span.end], "foo := 1");
+        assert_eq!(
+            &input[msg.args[0].span().start..msg.args[0].span().end],
+            "foo"
+        );
+    }
+
+    #[test]
+    fn test_assign_operator_table() {
+        let mut parser = Parser::default();
+        assert!(parser
+            .operators_mut()
+            .add_assign_operator(AssignOperator::new("<-", "setSlot")));
+        assert!(parser.operators_mut().remove_assign_operator("=").is_some());
+
+        let chains = parser.parse("a <- 1; b = 2").unwrap();
+        assert_eq!(chains[0].to_string(), "setSlot(\"a\", 1)");
+        assert_eq!(chains[1].to_string(), "b = 2");
+    }
+
+    #[test]
+    fn test_operator_semantics() {
+        let mut parser = Parser::default();
+        parser.add_operator(CustomOperator::new("++", 0).with_arity(Arity::Postfix));
+        parser.add_operator(CustomOperator::new("!", 0).with_arity(Arity::Prefix));
+
+        // the expected trees are written without operator sugar
+        let cases = [
+            ("2 ** 3 ** 2", "2 **(3 **(2))"),
+            ("2 ** 3 * 2", "2 **(3) *(2)"),
+            ("2 * 3 ** 2", "2 *(3 **(2))"),
+            ("a - b - c", "a -(b) -(c)"),
+            ("a := b := c", "setSlot(\"a\", setSlot(\"b\", c))"),
+            (
+                "a := b = c + 1",
+                "setSlot(\"a\", updateSlot(\"b\", c +(1)))",
+            ),
+            ("- a", "-(a)"),
+            ("- a * b", "-(a *(b))"),
+            ("- a + b", "-(a) +(b)"),
+            ("a * - b", "a *(-(b))"),
+            ("a * - b + c", "a *(-(b)) +(c)"),
+            ("a - - b", "a -(-(b))"),
+            ("a + return b", "a +(return(b))"),
+            ("return a + b", "return(a +(b))"),
+            ("if(a, return b, c)", "if(a, return(b), c)"),
+            ("a ++ b", "a ++ b"),
+            ("a + b ++ * c", "a +(b ++ *(c))"),
+            ("! a * b", "!(a) *(b)"),
+            ("x + ! a * b", "x +(!(a) *(b))"),
+            ("x ! a", "x !(a)"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                parser.parse(input).unwrap(),
+                parser.parse(expected).unwrap(),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_display_associativity() {
+        let cases = [
+            ("2 ** 3 ** 2", "2 ** 3 ** 2"),
+            ("a - b - c", "a - b - c"),
+            ("a -(b - c)", "a -(b - c)"),
+            ("a * - b", "a *(- b)"),
+        ];
+
+        for (input, expected) in cases {
+            let chains = parse(input).unwrap();
+            let output = chains[0].to_string();
+            assert_eq!(output, expected);
+            assert_eq!(parse(&output).unwrap(), chains, "{output}");
+        }
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let source = String::from("ack := method(m, n, if(m < 1, return n + 1)) ; ack(3, 4) print");
+        let borrowed = parse(&source).unwrap();
+        let owned: Vec<MessageChain<'static>> = parse(&source)
             .unwrap()
-            .1;
-        assert_eq!(message_chain(input).unwrap().1.sort(), expected);
+            .into_iter()
+            .map(MessageChain::into_owned)
+            .collect();
+        assert_eq!(owned, borrowed);
+        assert_eq!(owned[1].span(), borrowed[1].span());
+        assert_eq!(owned[0][0].args[1].span(), borrowed[0][0].args[1].span());
+
+        let expected: Vec<_> = borrowed.iter().map(ToString::to_string).collect();
+        drop(borrowed);
+        drop(source);
+
+        let printed =
+            std::thread::spawn(move || owned.iter().map(ToString::to_string).collect::<Vec<_>>());
+        assert_eq!(printed.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parser_operator_tables() {
+        let mut custom = Parser::default();
+        assert!(custom.add_operator(CustomOperator::new("~>", 6)));
+        assert!(!custom.add_operator(CustomOperator::new("~>", 1)));
+        assert!(custom.remove_operator("and").is_some());
+        assert!(custom.remove_operator("and").is_none());
+
+        let chain = &custom.parse("a ~> b and c").unwrap()[0];
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[1].symbol, CustomOperator::new("~>", 6).into());
+        assert_eq!(chain[1].args[0][0].len(), 3);
+        assert_eq!(
+            chain[1].args[0][0][1].symbol,
+            Symbol::Identifier("and".into())
+        );
+
+        // the default parser isn't affected
+        assert!(parse("a ~> b").is_err());
+        assert_eq!(parse("a and c").unwrap()[0][1].symbol, AndKey.into());
+    }
+
+    #[test]
+    fn test_parser_set_precedence() {
+        let mut parser = Parser::default();
+        assert!(parser.set_precedence("+", 1));
+        assert!(!parser.set_precedence("+++", 1));
+        assert_eq!(parser.operators().get("+").unwrap().precedence(), 1);
+
+        // `+` binds tighter than `*` now: `(1 + 2) * 3`
+        let chain = &parser.parse("1 + 2 * 3").unwrap()[0];
+        let symbols: Vec<_> = chain.iter().map(|msg| msg.symbol.to_string()).collect();
+        assert_eq!(symbols, ["1", "+", "*"]);
+
+        let chain = &parse("1 + 2 * 3").unwrap()[0];
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn test_parsers_in_parallel() {
+        let mut parser = Parser::default();
+        parser.add_operator(CustomOperator::new("~>", 3));
+
+        std::thread::scope(|scope| {
+            let custom = scope.spawn(|| parser.parse("a ~> b").is_ok());
+            let default = scope.spawn(|| parse("a ~> b").is_ok());
+            assert!(custom.join().unwrap());
+            assert!(!default.join().unwrap());
+        });
     }
 }
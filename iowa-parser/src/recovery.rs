@@ -0,0 +1,190 @@
+//! Error recovery.
+//!
+//! When the parser runs in the recovering mode, a part of the source which failed to parse is
+//! skipped up to the next synchronisation point and replaced by a message with [`Symbol::Error`].
+//! The synchronisation points are the terminators, and inside brackets also the separators and the
+//! closing brackets.
+
+use nom::InputTake;
+
+use crate::{error::Error, Input, Message, MessageChain, ParseError, Span, Symbol};
+
+/// The ends of an argument.
+pub(crate) const ARGUMENT_STOPS: &[char] = &[',', ')', ']', '}'];
+/// Synchronisation points for the chains of an argument.
+pub(crate) const ARGUMENT_CHAIN_STOPS: &[char] = &[',', ')', ']', '}', ';', '\n', '\r'];
+/// Synchronisation points for top-level chains.
+pub(crate) const CHAIN_STOPS: &[char] = &[';', '\n', '\r'];
+
+/// Record the error in the state of its input if the parser is in the recovering mode.
+///
+/// Returns `false` if the parser should stop at the error instead.
+pub(crate) fn record(err: &nom::Err<Error<'_>>) -> bool {
+    match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => match &err.input.extra.errors {
+            Some(errors) => {
+                errors.borrow_mut().push(ParseError::from(err.clone()));
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Skip the input until one of the `stops` is found outside of brackets and strings.
+///
+/// At least one character is skipped. Closing brackets which don't match an opening one are
+/// skipped too, unless they are in `stops`. Returns the rest of the input and the skipped part.
+pub(crate) fn skip_until<'a>(input: Input<'a>, stops: &[char]) -> (Input<'a>, Input<'a>) {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut end = input.len();
+
+    for (i, ch) in input.fragment().char_indices() {
+        if in_string {
+            // mono quotes can't span lines, so a new line ends a broken string
+            if ch != '\n' {
+                in_string = ch != '"';
+                continue;
+            }
+            in_string = false;
+        }
+
+        if i > 0 && depth == 0 && stops.contains(&ch) {
+            end = i;
+            break;
+        }
+
+        match ch {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    input.take_split(end)
+}
+
+/// Create a message which covers the skipped input.
+pub(crate) fn error_message<'a>(skipped: &Input<'a>, rest: &Input<'a>) -> Message<'a> {
+    Message::new(Symbol::Error, vec![]).with_span(Span::between(skipped, rest))
+}
+
+/// Create a chain which covers the skipped input.
+pub(crate) fn error_chain<'a>(skipped: &Input<'a>, rest: &Input<'a>) -> MessageChain<'a> {
+    MessageChain::new(vec![error_message(skipped, rest)])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, parse_with_recovery, ErrorKind, Symbol};
+
+    #[test]
+    fn test_recover_in_arguments() {
+        let input = "a := 1\nb := foo($, bar $ baz, 2)\nc := 3";
+        let (chains, errors) = parse_with_recovery(input);
+
+        let kinds: Vec<_> = errors.iter().map(|err| err.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                ErrorKind::UnexpectedCharacter('$'),
+                ErrorKind::UnexpectedCharacter('$')
+            ]
+        );
+        assert_eq!(chains.len(), 3);
+
+        let foo = &chains[1][0].args[1][0][0];
+        assert_eq!(foo.args.len(), 3);
+        assert_eq!(foo.args[0][0][0].symbol, Symbol::Error);
+
+        let bar = &foo.args[1];
+        assert_eq!(bar.len(), 2);
+        assert_eq!(bar[0], parse("bar").unwrap()[0]);
+        assert_eq!(bar[1][0].symbol, Symbol::Error);
+        assert_eq!(&input[bar[1].span().start..bar[1].span().end], "$ baz");
+
+        assert_eq!(foo.args[2], [parse("2").unwrap().remove(0)].into());
+    }
+
+    #[test]
+    fn test_recover_top_level() {
+        let input = "foo\n) bar\nbaz; ]\n\"qux\nquux";
+        let (chains, errors) = parse_with_recovery(input);
+
+        let kinds: Vec<_> = errors.iter().map(|err| err.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                ErrorKind::UnexpectedCharacter(')'),
+                ErrorKind::UnexpectedCharacter(']'),
+                ErrorKind::UnterminatedString,
+            ]
+        );
+        let lines: Vec<_> = errors.iter().map(|err| err.span.line).collect();
+        assert_eq!(lines, [2, 3, 4]);
+
+        let symbols: Vec<_> = chains.iter().map(|chain| chain[0].symbol.clone()).collect();
+        assert_eq!(
+            symbols,
+            [
+                Symbol::Identifier("foo".into()),
+                Symbol::Error,
+                Symbol::Identifier("baz".into()),
+                Symbol::Error,
+                Symbol::Error,
+                Symbol::Identifier("quux".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recover_unclosed_bracket() {
+        let (chains, errors) = parse_with_recovery("foo(bar,\n  baz");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::UnbalancedBracket('('));
+        assert_eq!(chains, parse("foo(bar, baz)").unwrap());
+
+        let (chains, errors) = parse_with_recovery("foo(bar $ baz\nqux");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].kind, ErrorKind::UnexpectedCharacter('$'));
+        assert_eq!(errors[1].kind, ErrorKind::UnbalancedBracket('('));
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0][0].args[0][2], parse("qux").unwrap()[0]);
+
+        // the content after the bracket is kept
+        let (chains, errors) = parse_with_recovery("foo(bar baz(]\nqux");
+        let kinds: Vec<_> = errors.iter().map(|err| err.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                ErrorKind::UnexpectedCharacter(']'),
+                ErrorKind::UnbalancedBracket('('),
+                ErrorKind::UnbalancedBracket('('),
+            ]
+        );
+        assert_eq!(chains, parse("foo(bar baz(qux))").unwrap());
+
+        let (chains, errors) = parse_with_recovery("a(1, \"x)\nb");
+        assert_eq!(errors[0].kind, ErrorKind::UnterminatedString);
+        let arg = &chains[0][0].args[1];
+        assert_eq!(arg[0][0].symbol, Symbol::Error);
+        assert_eq!(arg[1], parse("b").unwrap()[0]);
+
+        let (chains, errors) = parse_with_recovery("foo(bar ], baz)\nqux");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(chains, parse("foo(bar, baz)\nqux").unwrap());
+    }
+
+    #[test]
+    fn test_recover_without_errors() {
+        let input = "ack := method(m, n,\n  if(m < 1, return n + 1)\n)\nack(3, 4) print";
+        let (chains, errors) = parse_with_recovery(input);
+
+        assert!(errors.is_empty());
+        assert_eq!(chains, parse(input).unwrap());
+    }
+}
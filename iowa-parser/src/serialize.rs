@@ -0,0 +1,392 @@
+//! Serialization of the tree.
+//!
+//! Identifiers are serialized as their names and operators as their symbols. On load, the
+//! operators are looked up in the default table, or in the table of the seed returned by
+//! [`OperatorTable::seed`].
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{
+    self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{
+    Argument, Identifier, Message, MessageChain, OperatorRef, OperatorTable, Parser, Symbol,
+    SymbolId,
+};
+
+const CHAIN_FIELDS: &[&str] = &["messages", "span"];
+const MESSAGE_FIELDS: &[&str] = &["symbol", "args", "span"];
+const ARGUMENT_FIELDS: &[&str] = &["chains", "span"];
+const SYMBOL_VARIANTS: &[&str] = &["Identifier", "Number", "Operator", "Quote", "Error"];
+
+/// Deserializes a `T` of the tree, looking up its operators in a table.
+///
+/// `T` is one of `Vec<MessageChain>`, `MessageChain`, `Message`, `Argument`, `Symbol` or
+/// `OperatorRef`.
+pub struct TreeSeed<'a, T> {
+    operators: &'a OperatorTable,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TreeSeed<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TreeSeed<'_, T> {}
+
+impl<'a, T> TreeSeed<'a, T> {
+    fn cast<U>(self) -> TreeSeed<'a, U> {
+        self.operators.seed()
+    }
+}
+
+impl OperatorTable {
+    /// A seed deserializing a `T` of the tree with the operators of this table.
+    ///
+    /// ```
+    /// use serde::de::DeserializeSeed;
+    /// # use iowa_parser::{CustomOperator, MessageChain, Parser};
+    ///
+    /// let mut parser = Parser::default();
+    /// parser.add_operator(CustomOperator::new("~>", 3));
+    /// let json = serde_json::to_string(&parser.parse("a ~> b").unwrap()).unwrap();
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(&json);
+    /// let seed = parser.operators().seed::<Vec<MessageChain>>();
+    /// let chains = seed.deserialize(&mut de).unwrap();
+    /// ```
+    pub fn seed<T>(&self) -> TreeSeed<'_, T> {
+        TreeSeed {
+            operators: self,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// The index of a field or a variant, from its name or its index. Unknown ones are `None`.
+struct Index(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for Index {
+    type Value = Option<usize>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl Visitor<'_> for Index {
+    type Value = Option<usize>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "one of {:?}", self.0)
+    }
+
+    fn visit_u64<E: de::Error>(self, index: u64) -> Result<Self::Value, E> {
+        Ok(usize::try_from(index).ok().filter(|&i| i < self.0.len()))
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        Ok(self.0.iter().position(|field| *field == name))
+    }
+
+    fn visit_bytes<E: de::Error>(self, name: &[u8]) -> Result<Self::Value, E> {
+        Ok(self.0.iter().position(|field| field.as_bytes() == name))
+    }
+}
+
+impl<'de, 'a, T> DeserializeSeed<'de> for TreeSeed<'a, Vec<T>>
+where
+    TreeSeed<'a, T>: DeserializeSeed<'de, Value = T>,
+{
+    type Value = Vec<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, T> Visitor<'de> for TreeSeed<'a, Vec<T>>
+where
+    TreeSeed<'a, T>: DeserializeSeed<'de, Value = T>,
+{
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1024));
+        while let Some(item) = seq.next_element_seed(self.cast::<T>())? {
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_, MessageChain<'static>> {
+    type Value = MessageChain<'static>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("MessageChain", CHAIN_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed<'_, MessageChain<'static>> {
+    type Value = MessageChain<'static>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct MessageChain")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let messages = seq
+            .next_element_seed(self.cast::<Vec<Message>>())?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let span = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(MessageChain { messages, span })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut messages, mut span) = (None, None);
+        while let Some(field) = map.next_key_seed(Index(CHAIN_FIELDS))? {
+            match field {
+                Some(0) => messages = Some(map.next_value_seed(self.cast::<Vec<Message>>())?),
+                Some(1) => span = Some(map.next_value()?),
+                _ => _ = map.next_value::<IgnoredAny>()?,
+            }
+        }
+        Ok(MessageChain {
+            messages: messages.ok_or_else(|| de::Error::missing_field("messages"))?,
+            span: span.ok_or_else(|| de::Error::missing_field("span"))?,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_, Message<'static>> {
+    type Value = Message<'static>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Message", MESSAGE_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed<'_, Message<'static>> {
+    type Value = Message<'static>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct Message")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let symbol = seq
+            .next_element_seed(self.cast::<Symbol>())?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let args = seq
+            .next_element_seed(self.cast::<Vec<Argument>>())?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let span = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        Ok(Message { symbol, args, span })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut symbol, mut args, mut span) = (None, None, None);
+        while let Some(field) = map.next_key_seed(Index(MESSAGE_FIELDS))? {
+            match field {
+                Some(0) => symbol = Some(map.next_value_seed(self.cast::<Symbol>())?),
+                Some(1) => args = Some(map.next_value_seed(self.cast::<Vec<Argument>>())?),
+                Some(2) => span = Some(map.next_value()?),
+                _ => _ = map.next_value::<IgnoredAny>()?,
+            }
+        }
+        Ok(Message {
+            symbol: symbol.ok_or_else(|| de::Error::missing_field("symbol"))?,
+            args: args.ok_or_else(|| de::Error::missing_field("args"))?,
+            span: span.ok_or_else(|| de::Error::missing_field("span"))?,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_, Argument<'static>> {
+    type Value = Argument<'static>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Argument", ARGUMENT_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed<'_, Argument<'static>> {
+    type Value = Argument<'static>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct Argument")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let chains = seq
+            .next_element_seed(self.cast::<Vec<MessageChain>>())?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let span = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Argument { chains, span })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut chains, mut span) = (None, None);
+        while let Some(field) = map.next_key_seed(Index(ARGUMENT_FIELDS))? {
+            match field {
+                Some(0) => chains = Some(map.next_value_seed(self.cast::<Vec<MessageChain>>())?),
+                Some(1) => span = Some(map.next_value()?),
+                _ => _ = map.next_value::<IgnoredAny>()?,
+            }
+        }
+        Ok(Argument {
+            chains: chains.ok_or_else(|| de::Error::missing_field("chains"))?,
+            span: span.ok_or_else(|| de::Error::missing_field("span"))?,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_, Symbol<'static>> {
+    type Value = Symbol<'static>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum("Symbol", SYMBOL_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed<'_, Symbol<'static>> {
+    type Value = Symbol<'static>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("enum Symbol")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (variant, value) = data.variant_seed(Index(SYMBOL_VARIANTS))?;
+        match variant {
+            Some(0) => value.newtype_variant().map(Symbol::Identifier),
+            Some(1) => value.newtype_variant().map(Symbol::Number),
+            Some(2) => value
+                .newtype_variant_seed(self.cast::<OperatorRef>())
+                .map(Symbol::Operator),
+            Some(3) => value.newtype_variant().map(Symbol::Quote),
+            Some(4) => value.unit_variant().map(|()| Symbol::Error),
+            _ => Err(de::Error::custom("unknown variant of `Symbol`")),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_, OperatorRef> {
+    type Value = OperatorRef;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let symbol = String::deserialize(deserializer)?;
+        self.operators
+            .get(&symbol)
+            .cloned()
+            .ok_or_else(|| de::Error::custom(format!("unknown operator `{symbol}`")))
+    }
+}
+
+impl Serialize for Identifier<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Identifier<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Identifier::from(SymbolId::intern(&name)))
+    }
+}
+
+impl Serialize for OperatorRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.symbol())
+    }
+}
+
+impl<'de> Deserialize<'de> for OperatorRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Parser::default_ref()
+            .operators()
+            .seed::<Self>()
+            .deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::Options;
+    use serde::de::DeserializeSeed;
+
+    use crate::{parse, CustomOperator, MessageChain, Parser};
+
+    const INPUT: &str =
+        "ack := method(m, n,\n  if(m < 1, return n + 1)\n)\nack(0x3, 4.5) print(\"a\\tb\")";
+
+    #[test]
+    fn test_json() {
+        let chains = parse(INPUT).unwrap();
+        let json = serde_json::to_string(&chains).unwrap();
+        assert!(json.contains(r#"{"Identifier":"ack"}"#));
+        assert!(json.contains(r#"{"Operator":"<"}"#));
+        assert!(json.contains(r#"{"Number":{"Hex":3}}"#));
+
+        let loaded: Vec<MessageChain<'static>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, chains);
+        assert_eq!(loaded[1][1].span, chains[1][1].span);
+    }
+
+    #[test]
+    fn test_binary() {
+        let chains = parse(INPUT).unwrap();
+        let bytes = bincode::serialize(&chains).unwrap();
+        let loaded: Vec<MessageChain<'static>> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, chains);
+    }
+
+    #[test]
+    fn test_operator_lookup() {
+        let mut parser = Parser::default();
+        parser.add_operator(CustomOperator::new("~>", 3));
+        let chains = parser.parse("a ~> b").unwrap();
+        let json = serde_json::to_string(&chains).unwrap();
+
+        let res: Result<Vec<MessageChain<'_>>, _> = serde_json::from_str(&json);
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("unknown operator `~>`"));
+
+        let mut de = serde_json::Deserializer::from_str(&json);
+        let loaded: Vec<MessageChain<'_>> = parser
+            .operators()
+            .seed::<Vec<MessageChain>>()
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(loaded, chains);
+
+        let bytes = bincode::options().serialize(&chains).unwrap();
+        let mut de = bincode::Deserializer::from_slice(&bytes, bincode::options());
+        let loaded: Vec<MessageChain<'_>> = parser
+            .operators()
+            .seed::<Vec<MessageChain>>()
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(loaded, chains);
+    }
+}
@@ -6,20 +6,90 @@ use nom::{
     character::complete::{char, line_ending, one_of},
     combinator::{opt, recognize, value},
     sequence::tuple,
-    IResult,
 };
 
 use comment::comment;
 
-pub(crate) fn scpad(input: &str) -> IResult<&str, ()> {
+use crate::{Input, PResult};
+
+/// Location of a node in the source text.
+///
+/// The default span is a placeholder for nodes which weren't produced by the parser.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset right after the last character.
+    pub end: usize,
+    /// Line of the first character (starting from 1).
+    pub line: u32,
+    /// Column of the first character in characters (starting from 1).
+    pub column: usize,
+}
+
+impl Span {
+    /// Create a span covering the input consumed between `start` and `end`.
+    pub(crate) fn between(start: &Input<'_>, end: &Input<'_>) -> Self {
+        Self {
+            start: start.location_offset(),
+            end: end.location_offset(),
+            line: start.location_line(),
+            column: start.get_utf8_column(),
+        }
+    }
+
+    /// Check if the span is a placeholder rather than a real location.
+    pub fn is_dummy(&self) -> bool {
+        self.line == 0
+    }
+
+    /// Length of the span in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check if the span is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Get the smallest span covering both spans.
+    ///
+    /// Placeholder spans are ignored.
+    pub fn merge(self, other: Self) -> Self {
+        if self.is_dummy() {
+            return other;
+        }
+
+        if other.is_dummy() {
+            return self;
+        }
+
+        let first = if other.start < self.start {
+            other
+        } else {
+            self
+        };
+
+        Self {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
+}
+
+pub(crate) fn scpad(input: Input<'_>) -> PResult<'_, ()> {
     value((), alt((separator, comment)))(input)
 }
 
-pub(crate) fn wcpad(input: &str) -> IResult<&str, ()> {
+pub(crate) fn wcpad(input: Input<'_>) -> PResult<'_, ()> {
     value((), alt((whitespace, comment)))(input)
 }
 
-pub(crate) fn terminator(input: &str) -> IResult<&str, ()> {
+pub(crate) fn terminator(input: Input<'_>) -> PResult<'_, ()> {
     value(
         (),
         alt((
@@ -30,45 +100,74 @@ pub(crate) fn terminator(input: &str) -> IResult<&str, ()> {
     )(input)
 }
 
-fn separator(input: &str) -> IResult<&str, ()> {
+fn separator(input: Input<'_>) -> PResult<'_, ()> {
     value((), alt((char(' '), char('\t'), char('\x0c'), char('\x0b'))))(input)
 }
 
-pub(crate) fn whitespace(input: &str) -> IResult<&str, ()> {
+pub(crate) fn whitespace(input: Input<'_>) -> PResult<'_, ()> {
     value((), one_of(" \t\r\n\x0b\x0c"))(input)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_run;
+
+    #[test]
+    fn test_span_merge() {
+        let a = Span {
+            start: 4,
+            end: 7,
+            line: 1,
+            column: 5,
+        };
+        let b = Span {
+            start: 10,
+            end: 12,
+            line: 2,
+            column: 1,
+        };
+
+        assert_eq!(
+            b.merge(a),
+            Span {
+                start: 4,
+                end: 12,
+                line: 1,
+                column: 5,
+            }
+        );
+        assert_eq!(a.merge(Span::default()), a);
+        assert_eq!(Span::default().merge(a), a);
+    }
 
     #[test]
     fn test_parse_separator() {
-        assert_eq!(separator(" "), Ok(("", ())));
-        assert_eq!(separator("\t"), Ok(("", ())));
-        assert_eq!(separator("\x0c"), Ok(("", ())));
-        assert_eq!(separator("\x0b"), Ok(("", ())));
+        assert_eq!(test_run(separator, " "), Ok(("", ())));
+        assert_eq!(test_run(separator, "\t"), Ok(("", ())));
+        assert_eq!(test_run(separator, "\x0c"), Ok(("", ())));
+        assert_eq!(test_run(separator, "\x0b"), Ok(("", ())));
     }
 
     #[test]
     fn test_parse_terminator() {
-        assert_eq!(terminator(";"), Ok(("", ())));
-        assert_eq!(terminator(";\n"), Ok(("\n", ())));
-        assert_eq!(terminator("; \r"), Ok((" \r", ())));
-        assert_eq!(terminator("\r"), Ok(("", ())));
+        assert_eq!(test_run(terminator, ";"), Ok(("", ())));
+        assert_eq!(test_run(terminator, ";\n"), Ok(("\n", ())));
+        assert_eq!(test_run(terminator, "; \r"), Ok((" \r", ())));
+        assert_eq!(test_run(terminator, "\r"), Ok(("", ())));
     }
 
     #[test]
     fn test_parse_scpad() {
-        assert_eq!(scpad(" "), Ok(("", ())));
-        assert_eq!(scpad("# comment\n"), Ok(("", ())));
+        assert_eq!(test_run(scpad, " "), Ok(("", ())));
+        assert_eq!(test_run(scpad, "# comment\n"), Ok(("\n", ())));
     }
 
     #[test]
     fn test_parse_wcpad() {
-        assert_eq!(wcpad(" "), Ok(("", ())));
-        assert_eq!(wcpad("\n"), Ok(("", ())));
-        assert_eq!(wcpad("\r"), Ok(("", ())));
-        assert_eq!(wcpad("# comment\n"), Ok(("", ())));
+        assert_eq!(test_run(wcpad, " "), Ok(("", ())));
+        assert_eq!(test_run(wcpad, "\n"), Ok(("", ())));
+        assert_eq!(test_run(wcpad, "\r"), Ok(("", ())));
+        assert_eq!(test_run(wcpad, "# comment\n"), Ok(("\n", ())));
     }
 }
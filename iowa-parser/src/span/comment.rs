@@ -1,35 +1,43 @@
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag, take_until},
-    character::complete::line_ending,
-    combinator::value,
+    combinator::{opt, value},
     sequence::tuple,
-    IResult,
 };
 
-pub(crate) fn comment(input: &str) -> IResult<&str, ()> {
+use crate::{
+    error::{Error, ErrorKind},
+    Input, PResult,
+};
+
+pub(crate) fn comment(input: Input<'_>) -> PResult<'_, ()> {
     alt((line_comment, block_comment))(input)
 }
 
-fn line_comment(input: &str) -> IResult<&str, ()> {
-    value(
-        (),
-        tuple((alt((tag("#"), tag("//"))), is_not("\n\r"), line_ending)),
-    )(input)
+/// A comment to the end of the line, which leaves the line ending to terminate the chain.
+fn line_comment(input: Input<'_>) -> PResult<'_, ()> {
+    value((), tuple((alt((tag("#"), tag("//"))), opt(is_not("\n\r")))))(input)
 }
 
-fn block_comment(input: &str) -> IResult<&str, ()> {
-    value((), tuple((tag("/*"), take_until("*/"), tag("*/"))))(input)
+fn block_comment(input: Input<'_>) -> PResult<'_, ()> {
+    let (rest, _) = tag("/*")(input.clone())?;
+    let res: PResult<'_, ()> = value((), tuple((take_until("*/"), tag("*/"))))(rest);
+    res.map_err(|_| {
+        nom::Err::Failure(Error::new(input, ErrorKind::UnterminatedComment).expected("*/"))
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_run;
 
     #[test]
     fn test_comment() {
-        assert_eq!(line_comment("# comment\n"), Ok(("", ())));
-        assert_eq!(line_comment("// comment\n"), Ok(("", ())));
+        assert_eq!(test_run(line_comment, "# comment\n"), Ok(("\n", ())));
+        assert_eq!(test_run(line_comment, "// comment\r\n"), Ok(("\r\n", ())));
+        assert_eq!(test_run(line_comment, "#\n"), Ok(("\n", ())));
+        assert_eq!(test_run(line_comment, "# comment"), Ok(("", ())));
     }
 
     #[test]
@@ -39,6 +47,10 @@ mod tests {
                             multiple
                             lines
                             */"#;
-        assert_eq!(block_comment(comment), Ok(("", ())));
+        assert_eq!(test_run(block_comment, comment), Ok(("", ())));
+        assert_eq!(
+            test_run(block_comment, "/* comment"),
+            Err(nom::Err::Failure(ErrorKind::UnterminatedComment))
+        );
     }
 }
@@ -0,0 +1,361 @@
+//! Streaming parser.
+//!
+//! A [`StreamParser`] takes the input in chunks and yields each top-level chain once it's
+//! terminated, which is what a REPL or a parser of piped input needs. Only the complete lines of
+//! the input are parsed, so a token split between two chunks isn't an error. When the input ends
+//! inside a bracket, a triple-quoted string or a block comment, the parser waits for more of it and
+//! [`StreamParser::pending`] tells what's still open.
+
+use nom::{combinator::opt, multi::many0, multi::many1, InputTake};
+
+use crate::error::{self, ErrorKind};
+use crate::recovery;
+use crate::visit::{self, VisitMut};
+use crate::{message, span, Argument, Input, Message, MessageChain, ParseError, Parser, Span};
+
+/// The construct which needs more input to be complete.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Incomplete {
+    /// A chain without a terminator.
+    Chain,
+    /// An opening bracket without the closing one.
+    Bracket(char),
+    /// A triple-quoted string without the closing quotes.
+    String,
+    /// A block comment without the closing `*/`.
+    Comment,
+}
+
+/// Parser of the input coming in chunks.
+///
+/// The locations in the chains and errors are relative to the whole input pushed so far.
+#[derive(Debug, Clone)]
+pub struct StreamParser<'p> {
+    parser: &'p Parser,
+    /// The input from the start of the line with the first unparsed chain.
+    buffer: String,
+    /// The position of the first unparsed chain in the buffer.
+    pos: usize,
+    /// The offset of the buffer in the whole input.
+    offset: usize,
+    /// The number of lines before the buffer.
+    lines: u32,
+    pending: Option<Incomplete>,
+}
+
+impl<'p> StreamParser<'p> {
+    /// Create a parser using the operators of `parser`.
+    pub fn new(parser: &'p Parser) -> Self {
+        Self {
+            parser,
+            buffer: String::new(),
+            pos: 0,
+            offset: 0,
+            lines: 0,
+            pending: None,
+        }
+    }
+
+    /// Append a chunk of the input.
+    pub fn push(&mut self, chunk: &str) {
+        self.buffer.push_str(chunk);
+    }
+
+    /// What's waiting for more input as of the last call to [`next_chain`](Self::next_chain).
+    ///
+    /// `None` means that all the input pushed so far is parsed.
+    pub fn pending(&self) -> Option<Incomplete> {
+        self.pending
+    }
+
+    /// Discard the unparsed input, e.g. when the user of a REPL cancels the entry.
+    pub fn clear(&mut self) {
+        self.skip(self.buffer.len());
+        self.pending = None;
+    }
+
+    /// Parse the next terminated chain.
+    ///
+    /// Returns `None` if more input is needed. After an error, the input is skipped up to the next
+    /// terminator, so parsing goes on with the chain after the one with the error.
+    pub fn next_chain(&mut self) -> Option<Result<MessageChain<'static>, ParseError>> {
+        let typing = !self.line().trim().is_empty();
+        let res = self.parse_chain(false);
+
+        if res.is_none() && self.pending.is_none() && typing {
+            self.pending = Some(Incomplete::Chain);
+        }
+        res
+    }
+
+    /// Parse the rest of the input, which has ended.
+    pub fn finish(mut self) -> Result<Vec<MessageChain<'static>>, ParseError> {
+        let mut chains = Vec::new();
+        while let Some(chain) = self.parse_chain(true) {
+            chains.push(chain?);
+        }
+        Ok(chains)
+    }
+
+    /// The incomplete line at the end of the buffer.
+    fn line(&self) -> &str {
+        let start = self.buffer.rfind('\n').map_or(0, |pos| pos + 1);
+        &self.buffer[start..]
+    }
+
+    /// Parse a chain from the complete lines of the buffer, or the whole buffer at the end of the
+    /// input.
+    ///
+    /// Unless it's the end of the input, a chain without a terminator could continue on the next
+    /// line and errors caused by the end of the input mean that more is needed.
+    fn parse_chain(&mut self, eof: bool) -> Option<Result<MessageChain<'static>, ParseError>> {
+        self.pending = None;
+
+        // the line being typed could still change the tokens at its end
+        let end = if eof {
+            self.buffer.len()
+        } else {
+            self.buffer.len() - self.line().len()
+        };
+
+        if end < self.pos {
+            return None;
+        }
+
+        let whole = self.parser.state(&self.buffer[..end], None).input();
+        let (input, _) = whole.clone().take_split(self.pos);
+        let res = many0(span::wcpad)(input).and_then(|(rest, _)| {
+            if rest.is_empty() {
+                return Ok((rest, None));
+            }
+            let (rest, messages) = many1(message)(rest)?;
+            let (rest, terminator) = opt(span::terminator)(rest)?;
+            Ok((
+                rest,
+                Some((MessageChain::new(messages), terminator.is_some())),
+            ))
+        });
+
+        let (rest, chain) = match res {
+            Ok((rest, None)) => {
+                self.pos = rest.location_offset();
+                self.trim();
+                return None;
+            }
+            // the next line could start with another message
+            Ok((rest, Some((_, false)))) if !eof && continues(&rest) => {
+                self.pending = Some(Incomplete::Chain);
+                return None;
+            }
+            Ok((rest, Some((chain, _)))) => (rest.location_offset(), chain),
+            Err(nom::Err::Error(err) | nom::Err::Failure(err)) => {
+                match incomplete(&err).filter(|_| !eof) {
+                    Some(pending) => {
+                        self.pending = Some(pending);
+                        return None;
+                    }
+                    None => {
+                        let mut err = ParseError::from(err);
+                        let at = if err.span.is_dummy() {
+                            self.pos
+                        } else {
+                            err.span.start.max(self.pos)
+                        };
+                        let (input, _) = whole.take_split(at);
+                        let resume = resync(input);
+                        err.span = self.shift(err.span);
+                        self.pos = resume;
+                        self.trim();
+                        return Some(Err(err));
+                    }
+                }
+            }
+            Err(nom::Err::Incomplete(_)) => unreachable!("the parser works on complete input"),
+        };
+
+        let mut chain = self
+            .parser
+            .finish(vec![chain])
+            .pop()
+            .map(MessageChain::into_owned)
+            .unwrap_or_default();
+        let mut shift = &*self;
+        shift.visit_chain_mut(&mut chain);
+
+        self.pos = rest;
+        self.trim();
+        Some(Ok(chain))
+    }
+
+    /// Drop the parsed lines from the buffer.
+    fn trim(&mut self) {
+        let line = self.buffer[..self.pos].rfind('\n').map_or(0, |pos| pos + 1);
+        self.skip(line);
+    }
+
+    /// Drop the start of the buffer.
+    fn skip(&mut self, len: usize) {
+        self.lines += self.buffer[..len].matches('\n').count() as u32;
+        self.offset += len;
+        self.buffer.drain(..len);
+        self.pos = self.pos.saturating_sub(len);
+    }
+
+    /// Move the span from the buffer to the whole input.
+    fn shift(&self, span: Span) -> Span {
+        if span.is_dummy() {
+            return span;
+        }
+
+        Span {
+            start: span.start + self.offset,
+            end: span.end + self.offset,
+            line: span.line + self.lines,
+            ..span
+        }
+    }
+}
+
+impl<'a> VisitMut<'a> for &StreamParser<'_> {
+    fn visit_chain_mut(&mut self, chain: &mut MessageChain<'a>) {
+        chain.span = self.shift(chain.span);
+        visit::walk_chain_mut(self, chain);
+    }
+
+    fn visit_message_mut(&mut self, msg: &mut Message<'a>) {
+        msg.span = self.shift(msg.span);
+        visit::walk_message_mut(self, msg);
+    }
+
+    fn visit_argument_mut(&mut self, arg: &mut Argument<'a>) {
+        arg.span = self.shift(arg.span);
+        visit::walk_argument_mut(self, arg);
+    }
+}
+
+/// Check if the chain without a terminator could continue after the end of the input.
+fn continues(input: &Input<'_>) -> bool {
+    many0(span::scpad)(input.clone()).is_ok_and(|(rest, _)| rest.is_empty())
+}
+
+/// The position after the terminator which ends the chain with an error at the input.
+fn resync(input: Input<'_>) -> usize {
+    let rest = if input.fragment().starts_with(recovery::CHAIN_STOPS) {
+        input
+    } else {
+        recovery::skip_until(input, recovery::CHAIN_STOPS).0
+    };
+    rest.location_offset() + usize::from(!rest.is_empty())
+}
+
+/// Check if the error is caused by the end of the input.
+fn incomplete(err: &error::Error<'_>) -> Option<Incomplete> {
+    match err.kind {
+        ErrorKind::UnbalancedBracket(open) => Some(Incomplete::Bracket(open)),
+        ErrorKind::UnterminatedComment => Some(Incomplete::Comment),
+        // a single-quoted string can't span lines
+        ErrorKind::UnterminatedString if err.input.fragment().starts_with("\"\"\"") => {
+            Some(Incomplete::String)
+        }
+        ErrorKind::UnexpectedEnd => Some(Incomplete::Chain),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "ack := method(m, n,\n  if(m < 1, return n + 1)\n  /* recurse */ ack(m - 1, ack(m, n - 1))\n)\ndoc := \"\"\"\nack\n\"\"\"; ack(2, 3) println // 9\nx += 1";
+
+    fn collect(stream: &mut StreamParser<'_>, chains: &mut Vec<MessageChain<'static>>) {
+        while let Some(chain) = stream.next_chain() {
+            chains.push(chain.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let parser = Parser::default_ref();
+        let expected = format!("{:?}", parser.parse(SOURCE).unwrap());
+
+        for size in 1..=SOURCE.len() {
+            let mut stream = StreamParser::new(parser);
+            let mut chains = Vec::new();
+            let mut chunks = SOURCE.as_bytes().chunks(size);
+            for chunk in chunks.by_ref() {
+                stream.push(std::str::from_utf8(chunk).unwrap());
+                collect(&mut stream, &mut chains);
+            }
+            chains.extend(stream.finish().unwrap());
+            assert_eq!(format!("{chains:?}"), expected, "{size}");
+        }
+    }
+
+    #[test]
+    fn test_pending() {
+        let mut stream = StreamParser::new(Parser::default_ref());
+        let mut chains = Vec::new();
+        let mut lines = SOURCE.split_inclusive('\n');
+        let mut push = |stream: &mut StreamParser<'_>| {
+            stream.push(lines.next().unwrap());
+            collect(stream, &mut chains);
+            (stream.pending(), chains.len())
+        };
+
+        assert_eq!(push(&mut stream), (Some(Incomplete::Bracket('(')), 0));
+        assert_eq!(push(&mut stream), (Some(Incomplete::Bracket('(')), 0));
+        assert_eq!(push(&mut stream), (Some(Incomplete::Bracket('(')), 0));
+        assert_eq!(push(&mut stream), (None, 1));
+        assert_eq!(push(&mut stream), (Some(Incomplete::String), 1));
+        assert_eq!(push(&mut stream), (Some(Incomplete::String), 1));
+        // the newline after the line comment terminates the chain
+        assert_eq!(push(&mut stream), (None, 3));
+        assert_eq!(push(&mut stream), (Some(Incomplete::Chain), 3));
+
+        stream.push("\n/* ");
+        assert_eq!(
+            stream.next_chain().unwrap().unwrap().to_string(),
+            "updateSlot(\"x\", x + 1)"
+        );
+        assert!(stream.next_chain().is_none());
+        assert_eq!(stream.pending(), Some(Incomplete::Chain));
+        stream.push("\n");
+        assert!(stream.next_chain().is_none());
+        assert_eq!(stream.pending(), Some(Incomplete::Comment));
+        assert!(stream.clone().finish().is_err());
+
+        stream.clear();
+        assert!(stream.next_chain().is_none());
+        assert_eq!(stream.pending(), None);
+        assert!(stream.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut stream = StreamParser::new(Parser::default_ref());
+        stream.push("foo\nbar(1]\nbaz\nqux(\"a\n");
+        assert_eq!(stream.next_chain().unwrap().unwrap().to_string(), "foo");
+
+        let err = stream.next_chain().unwrap().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedCharacter(']'));
+        assert_eq!((err.span.line, err.span.column, err.span.start), (2, 6, 9));
+        assert!(err.to_string().contains("2 | bar(1]"));
+        assert_eq!(stream.next_chain().unwrap().unwrap().to_string(), "baz");
+
+        let err = stream.next_chain().unwrap().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnterminatedString);
+        assert_eq!(err.span.line, 4);
+        assert!(stream.next_chain().is_none());
+        assert_eq!(stream.pending(), None);
+
+        // the chains after an error on the same line
+        stream.push("a(]; b\nqux\n");
+        let err = stream.next_chain().unwrap().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedCharacter(']'));
+        assert_eq!(stream.next_chain().unwrap().unwrap().to_string(), "b");
+        let chain = stream.next_chain().unwrap().unwrap();
+        assert_eq!(chain.to_string(), "qux");
+        assert_eq!((chain.span.line, chain.span.start), (6, 29));
+    }
+}
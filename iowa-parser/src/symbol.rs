@@ -1,49 +1,94 @@
+mod interner;
 mod number;
 mod operator;
 mod quote;
 
+use std::sync::Arc;
+
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while1},
     combinator::map,
-    IResult,
 };
 
 use self::quote::quote;
+use crate::{error::Error, Input, PResult};
 
+pub use interner::SymbolId;
 pub use number::Number;
 pub use operator::*;
 pub use quote::Quote;
 
 /// The Symbol type.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Symbol<'a> {
     /// Identifier.
     Identifier(Identifier<'a>),
     /// Number.
     Number(Number),
     /// Operator.
-    Operator(Box<dyn Operator>),
+    Operator(OperatorRef),
     /// Quote.
     Quote(Quote),
+    /// A part of the source which failed to parse.
+    ///
+    /// It's produced only by [`parse_with_recovery`](crate::parse_with_recovery).
+    Error,
 }
 
 impl Symbol<'_> {
-    pub(crate) fn as_ref_op(&self) -> &Box<dyn Operator> {
+    /// Convert to a symbol which doesn't borrow the source.
+    pub fn into_owned(self) -> Symbol<'static> {
         match self {
-            Self::Operator(ref op) => op,
+            Self::Identifier(ident) => Symbol::Identifier(ident.into_owned()),
+            Self::Number(num) => Symbol::Number(num),
+            Self::Operator(op) => Symbol::Operator(op),
+            Self::Quote(quote) => Symbol::Quote(quote),
+            Self::Error => Symbol::Error,
+        }
+    }
+
+    /// The interned name of an identifier or an operator.
+    pub fn id(&self) -> Option<SymbolId> {
+        match self {
+            Self::Identifier(ident) => Some(ident.id()),
+            Self::Operator(op) => Some(op.id()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_ref_op(&self) -> &dyn Operator {
+        match self {
+            Self::Operator(ref op) => &**op,
             _ => unreachable!(),
         }
     }
 }
 
+/// Prints the symbol as Io code.
+///
+/// The error symbol is printed as nothing.
+impl std::fmt::Display for Symbol<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Identifier(ident) => f.write_str(ident.as_str()),
+            Self::Number(num) => write!(f, "{num}"),
+            Self::Operator(op) => f.write_str(op.symbol()),
+            Self::Quote(quote) => write!(f, "{quote}"),
+            Self::Error => Ok(()),
+        }
+    }
+}
+
 impl PartialEq for Symbol<'_> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Identifier(a), Self::Identifier(b)) => a == b,
             (Self::Number(a), Self::Number(b)) => a == b,
-            (Self::Operator(a), Self::Operator(b)) => a.symbol() == b.symbol(),
+            (Self::Operator(a), Self::Operator(b)) => a.id() == b.id(),
             (Self::Quote(a), Self::Quote(b)) => a == b,
+            (Self::Error, Self::Error) => true,
             _ => false,
         }
     }
@@ -63,7 +108,7 @@ impl From<Number> for Symbol<'_> {
 
 impl<T: Operator> From<T> for Symbol<'_> {
     fn from(input: T) -> Self {
-        Self::Operator(Box::new(input))
+        Self::Operator(OperatorRef::new(input))
     }
 }
 
@@ -74,16 +119,74 @@ impl From<Quote> for Symbol<'_> {
 }
 
 /// Identifier token.
-#[derive(Debug, PartialEq, Clone)]
-pub struct Identifier<'a>(&'a str);
+///
+/// The name is interned, so identifiers compare cheaply. A parsed identifier also borrows its name
+/// from the source, see [`Identifier::into_owned`].
+#[derive(Clone, Copy)]
+pub struct Identifier<'a> {
+    id: SymbolId,
+    source: Option<&'a str>,
+}
+
+impl<'a> Identifier<'a> {
+    /// The interned name.
+    pub fn id(&self) -> SymbolId {
+        self.id
+    }
+
+    /// The name.
+    pub fn as_str(&self) -> &'a str {
+        self.source.unwrap_or_else(|| self.id.as_str())
+    }
+
+    /// Convert to an identifier which doesn't borrow the source.
+    pub fn into_owned(self) -> Identifier<'static> {
+        Identifier::from(self.id)
+    }
+}
+
+impl PartialEq for Identifier<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Identifier<'_> {}
+
+impl std::hash::Hash for Identifier<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl std::fmt::Debug for Identifier<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Identifier").field(&self.as_str()).finish()
+    }
+}
+
+impl From<SymbolId> for Identifier<'_> {
+    fn from(id: SymbolId) -> Self {
+        Self { id, source: None }
+    }
+}
 
 impl<'a> From<&'a str> for Identifier<'a> {
     fn from(input: &'a str) -> Self {
-        Self(input)
+        Self {
+            id: SymbolId::intern(input),
+            source: Some(input),
+        }
+    }
+}
+
+impl From<Arc<str>> for Identifier<'_> {
+    fn from(input: Arc<str>) -> Self {
+        SymbolId::intern(&input).into()
     }
 }
 
-pub(crate) fn symbol(input: &str) -> IResult<&str, Symbol<'_>> {
+pub(crate) fn symbol(input: Input<'_>) -> PResult<'_, Symbol<'_>> {
     alt((
         map(op_token, Symbol::Operator),
         map(quote, Symbol::Quote),
@@ -92,42 +195,51 @@ pub(crate) fn symbol(input: &str) -> IResult<&str, Symbol<'_>> {
     ))(input)
 }
 
-fn identifier(input: &str) -> IResult<&str, Identifier<'_>> {
+fn identifier(input: Input<'_>) -> PResult<'_, Identifier<'_>> {
     map(
         take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        Identifier,
+        |ident: Input<'_>| Identifier::from(*ident.fragment()),
     )(input)
 }
 
-fn op_token(input: &str) -> IResult<&str, Box<dyn Operator>> {
-    let table = OperatorTable::global().table.lock().unwrap();
-    for op in &*table {
-        let res: IResult<&str, &str> = tag(op.symbol())(input);
+fn op_token(input: Input<'_>) -> PResult<'_, OperatorRef> {
+    for op in &input.extra.operators.table {
+        let res: PResult<'_, Input<'_>> = tag(op.symbol())(input.clone());
         if let Ok((input, _)) = res {
             return Ok((input, op.clone()));
         }
     }
-    Err(nom::Err::Error(nom::error::Error::new(
-        input,
-        nom::error::ErrorKind::Tag,
-    )))
+    Err(nom::Err::Error(Error::unexpected(input)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{test_run, State};
 
     #[test]
     fn test_parse_identifier() {
-        assert_eq!(identifier("foo"), Ok(("", Identifier("foo"))));
-        assert_eq!(identifier("foo_bar"), Ok(("", Identifier("foo_bar"))));
         assert_eq!(
-            identifier("foo_bar_123_"),
-            Ok(("", Identifier("foo_bar_123_")))
+            test_run(identifier, "foo"),
+            Ok(("", Identifier::from("foo")))
+        );
+        assert_eq!(
+            test_run(identifier, "foo_bar"),
+            Ok(("", Identifier::from("foo_bar")))
+        );
+        assert_eq!(
+            test_run(identifier, "foo_bar_123_"),
+            Ok(("", Identifier::from("foo_bar_123_")))
+        );
+        assert_eq!(
+            test_run(identifier, "тест"),
+            Ok(("", Identifier::from("тест")))
         );
-        assert_eq!(identifier("тест"), Ok(("", Identifier("тест"))));
-        assert_eq!(identifier("_тест"), Ok(("", Identifier("_тест"))));
-        assert_eq!(identifier("_"), Ok(("", Identifier("_"))));
+        assert_eq!(
+            test_run(identifier, "_тест"),
+            Ok(("", Identifier::from("_тест")))
+        );
+        assert_eq!(test_run(identifier, "_"), Ok(("", Identifier::from("_"))));
     }
 
     #[test]
@@ -139,7 +251,32 @@ mod tests {
         ];
 
         for op in ops {
-            assert_eq!(op_token(op).unwrap().1.symbol(), op);
+            assert_eq!(
+                op_token(
+                    State {
+                        source: op,
+                        errors: None,
+                        operators: &OperatorTable::default(),
+                    }
+                    .input()
+                )
+                .unwrap()
+                .1
+                .symbol(),
+                op
+            );
         }
     }
+
+    #[test]
+    fn test_interned_ids() {
+        let chain = &crate::parse("foo + foo bar").unwrap()[0];
+        let foo = SymbolId::intern("foo");
+
+        assert_eq!(chain[0].symbol.id(), Some(foo));
+        assert_eq!(chain[1].symbol.id(), Some(SymbolId::intern("+")));
+        assert_eq!(chain[1].args[0][0][0].symbol.id(), Some(foo));
+        assert_eq!(chain[1].args[0][0][1].symbol.id(), Some("bar".into()));
+        assert_eq!(Symbol::Number(Number::Hex(1)).id(), None);
+    }
 }
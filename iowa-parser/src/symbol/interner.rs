@@ -0,0 +1,141 @@
+//! Symbol interner.
+//!
+//! The interner is global, so the same name gets the same [`SymbolId`] in every parser, compiler
+//! and runtime of the process. Interned names are never freed, so names which are only looked up,
+//! like the argument of `getSlot`, should go through [`SymbolId::get`].
+//!
+//! The names are kept in an append-only table of chunks which double in size, so reading a name
+//! doesn't take the lock.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+/// The number of chunks, chunk `n` holds `2^n` names.
+const CHUNKS: usize = u32::BITS as usize;
+
+/// An interned identifier or operator name.
+///
+/// Comparing and hashing the ids is much cheaper than comparing the names.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct SymbolId(u32);
+
+impl SymbolId {
+    /// Intern the name.
+    pub fn intern(name: &str) -> Self {
+        let interner = Interner::global();
+        if let Some(&id) = interner.ids.read().unwrap().get(name) {
+            return id;
+        }
+
+        let mut ids = interner.ids.write().unwrap();
+        // the name could've been interned while the lock was released
+        if let Some(&id) = ids.get(name) {
+            return id;
+        }
+
+        let id = Self(ids.len() as u32);
+        let name: &'static str = Box::leak(name.into());
+        let (chunk, offset) = id.position();
+        let names = interner.names[chunk]
+            .get_or_init(|| (0..1usize << chunk).map(|_| OnceLock::new()).collect());
+        names[offset].set(name).unwrap();
+        ids.insert(name, id);
+        id
+    }
+
+    /// The id of the name if it's interned, without interning it.
+    pub fn get(name: &str) -> Option<Self> {
+        Interner::global().ids.read().unwrap().get(name).copied()
+    }
+
+    /// The interned name.
+    pub fn as_str(self) -> &'static str {
+        let interner = Interner::global();
+        interner.name(self).unwrap_or_else(|| {
+            // the id was passed from the interning thread without synchronising with it
+            let _ids = interner.ids.read().unwrap();
+            interner.name(self).unwrap()
+        })
+    }
+
+    /// The index of the id, which can be used to build dense tables.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The chunk of the name table and the offset in it.
+    fn position(self) -> (usize, usize) {
+        let index = self.0 as usize + 1;
+        let chunk = index.ilog2() as usize;
+        (chunk, index - (1 << chunk))
+    }
+}
+
+impl fmt::Debug for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SymbolId({}, {:?})", self.0, self.as_str())
+    }
+}
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for SymbolId {
+    fn from(name: &str) -> Self {
+        Self::intern(name)
+    }
+}
+
+struct Interner {
+    ids: RwLock<HashMap<&'static str, SymbolId>>,
+    names: [OnceLock<Box<[OnceLock<&'static str>]>>; CHUNKS],
+}
+
+impl Interner {
+    fn global() -> &'static Self {
+        static INSTANCE: OnceLock<Interner> = OnceLock::new();
+        INSTANCE.get_or_init(|| Self {
+            ids: Default::default(),
+            names: std::array::from_fn(|_| OnceLock::new()),
+        })
+    }
+
+    fn name(&self, id: SymbolId) -> Option<&'static str> {
+        let (chunk, offset) = id.position();
+        self.names[chunk].get()?[offset].get().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let foo = SymbolId::intern("interner_test_foo");
+        let bar = SymbolId::intern("interner_test_bar");
+
+        assert_ne!(foo, bar);
+        assert_eq!(SymbolId::intern("interner_test_foo"), foo);
+        assert_eq!(foo.as_str(), "interner_test_foo");
+        assert_eq!(bar.to_string(), "interner_test_bar");
+        assert_eq!(SymbolId::get("interner_test_foo"), Some(foo));
+        assert_eq!(SymbolId::get("interner_test_missing"), None);
+
+        let ids: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| SymbolId::intern("interner_test_baz")))
+            .collect();
+        let ids: Vec<_> = ids.into_iter().map(|id| id.join().unwrap()).collect();
+        assert!(ids.iter().all(|&id| id == ids[0]));
+
+        let names: Vec<_> = (0..100).map(|i| format!("interner_test_{i}")).collect();
+        let ids: Vec<_> = names.iter().map(|name| SymbolId::intern(name)).collect();
+        for (id, name) in ids.into_iter().zip(&names) {
+            assert_eq!(id.as_str(), name);
+        }
+    }
+}
@@ -5,11 +5,13 @@ use nom::{
     combinator::{map_res, opt, recognize},
     multi::many1,
     sequence::{pair, preceded, tuple},
-    IResult,
 };
 
+use crate::{Input, PResult};
+
 /// The Number type.
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Number {
     /// Hexadecimal number.
     Hex(u64),
@@ -29,20 +31,29 @@ impl From<u64> for Number {
     }
 }
 
-pub(crate) fn number(input: &str) -> IResult<&str, Number> {
+impl std::fmt::Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hex(num) => write!(f, "0x{num:X}"),
+            Self::Decimal(num) => write!(f, "{num}"),
+        }
+    }
+}
+
+pub(crate) fn number(input: Input<'_>) -> PResult<'_, Number> {
     alt((hex_number, decimal_number))(input)
 }
 
 // Define a parser for hexadecimal digits
-fn hex_number(input: &str) -> IResult<&str, Number> {
+fn hex_number(input: Input<'_>) -> PResult<'_, Number> {
     map_res(
         preceded(alt((tag("0x"), tag("0X"))), recognize(many1(hex_digit1))),
-        |out: &str| u64::from_str_radix(out, 16).map(Number::Hex),
+        |out: Input<'_>| u64::from_str_radix(out.fragment(), 16).map(Number::Hex),
     )(input)
 }
 
 // Define a parser for decimal numbers
-fn decimal_number(input: &str) -> IResult<&str, Number> {
+fn decimal_number(input: Input<'_>) -> PResult<'_, Number> {
     let num = recognize(pair(
         opt(one_of("+-")),
         alt((
@@ -64,31 +75,53 @@ fn decimal_number(input: &str) -> IResult<&str, Number> {
             recognize(digit1),
         )),
     ));
-    map_res(num, |out: &str| out.parse::<f64>().map(Number::Decimal))(input)
+    map_res(num, |out: Input<'_>| {
+        out.fragment().parse::<f64>().map(Number::Decimal)
+    })(input)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_run;
 
     #[test]
     fn test_parse_hex_number() {
-        assert_eq!(hex_number("0x1234"), Ok(("", Number::Hex(0x1234))));
-        assert_eq!(hex_number("0Xabcd"), Ok(("", Number::Hex(0xABCD))));
-        assert_eq!(hex_number("0x1a2b3c4d"), Ok(("", Number::Hex(0x1A2B3C4D))));
+        assert_eq!(
+            test_run(hex_number, "0x1234"),
+            Ok(("", Number::Hex(0x1234)))
+        );
+        assert_eq!(
+            test_run(hex_number, "0Xabcd"),
+            Ok(("", Number::Hex(0xABCD)))
+        );
+        assert_eq!(
+            test_run(hex_number, "0x1a2b3c4d"),
+            Ok(("", Number::Hex(0x1A2B3C4D)))
+        );
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_decimal_number() {
-        assert_eq!(decimal_number("42"), Ok(("", Number::Decimal(42.0))));
-        assert_eq!(decimal_number("3.1415"), Ok(("", Number::Decimal(3.1415))));
         assert_eq!(
-            decimal_number("123.456e+10"),
+            test_run(decimal_number, "42"),
+            Ok(("", Number::Decimal(42.0)))
+        );
+        assert_eq!(
+            test_run(decimal_number, "3.1415"),
+            Ok(("", Number::Decimal(3.1415)))
+        );
+        assert_eq!(
+            test_run(decimal_number, "123.456e+10"),
             Ok(("", Number::Decimal(1234560000000.0)))
         );
-        assert_eq!(decimal_number("0.5e-3"), Ok(("", Number::Decimal(0.0005))));
         assert_eq!(
-            decimal_number("-2.5e-3"),
+            test_run(decimal_number, "0.5e-3"),
+            Ok(("", Number::Decimal(0.0005)))
+        );
+        assert_eq!(
+            test_run(decimal_number, "-2.5e-3"),
             Ok(("", Number::Decimal(-0.0025)))
         );
     }
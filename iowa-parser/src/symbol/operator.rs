@@ -1,105 +1,362 @@
 //! Operator table.
 
-use std::sync::Mutex;
-use std::sync::OnceLock;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
 
 use dyn_clone::DynClone;
 use rayon::prelude::*;
 
+use super::SymbolId;
+
 /// A table of operators.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OperatorTable {
     // we very rarely push operators and we access precedence during parse directly from a concrete
     // operator, but we need to preserve order of insertion to prevent ambiguity during parsing, so
     // it's better to use Vec instead of HashMap here
-    pub(crate) table: Mutex<Vec<Box<dyn Operator>>>,
+    pub(crate) table: Vec<OperatorRef>,
+    assign_table: Vec<AssignOperator>,
 }
 
 impl OperatorTable {
-    /// Get the global operator table.
-    pub fn global() -> &'static Self {
-        static INSTANCE: OnceLock<OperatorTable> = OnceLock::new();
-        INSTANCE.get_or_init(|| Self::default())
+    /// Add an operator to the table (if it's not there already).
+    ///
+    /// Returns `false` if there's an operator with the same symbol.
+    pub fn add_operator(&mut self, operator: impl Operator) -> bool {
+        if self.get(operator.symbol()).is_some() {
+            return false;
+        }
+
+        self.table.push(OperatorRef::new(operator));
+        // the longest symbol must be tried first, so `<=` isn't parsed as `<` followed by `=`
+        self.table.sort_unstable_by_key(|op| op.symbol());
+        self.table.reverse();
+        true
     }
 
-    /// Add an operator to the table (if it's not there already).
-    pub fn add_operator(operator: impl Operator) {
-        let mut table = Self::global().table.lock().unwrap();
-        if table
-            .binary_search_by_key(&operator.symbol(), |op| op.symbol())
-            .is_err()
-        {
-            table.push(Box::new(operator));
-            table.sort_unstable_by_key(|op| op.symbol());
-            table.reverse();
+    /// Remove the operator with the symbol from the table.
+    pub fn remove_operator(&mut self, symbol: &str) -> Option<OperatorRef> {
+        let index = self.table.iter().position(|op| op.symbol() == symbol)?;
+        Some(self.table.remove(index))
+    }
+
+    /// Change the precedence of the operator with the symbol.
+    ///
+    /// Returns `false` if there's no such operator.
+    pub fn set_precedence(&mut self, symbol: &str, precedence: u32) -> bool {
+        let Some(op) = self.table.iter_mut().find(|op| op.symbol() == symbol) else {
+            return false;
+        };
+
+        *op = OperatorRef::new(
+            CustomOperator::new(op.symbol(), precedence)
+                .with_associativity(op.associativity())
+                .with_arity(op.arity()),
+        );
+        true
+    }
+
+    /// Get the operator with the symbol.
+    pub fn get(&self, symbol: &str) -> Option<&OperatorRef> {
+        self.table.iter().find(|op| op.symbol() == symbol)
+    }
+
+    /// Iterate over the operators.
+    pub fn iter(&self) -> impl Iterator<Item = &OperatorRef> {
+        self.table.iter()
+    }
+
+    /// Add an assignment operator (if it's not there already).
+    ///
+    /// The symbol is added to the operators with [`ASSIGN_PRECEDENCE`] if it's unknown, so it can
+    /// be parsed. Returns `false` if there's an assignment operator with the same symbol.
+    pub fn add_assign_operator(&mut self, operator: AssignOperator) -> bool {
+        if self.get_assign(operator.symbol).is_some() {
+            return false;
         }
+
+        self.add_operator(
+            CustomOperator::new(operator.symbol, ASSIGN_PRECEDENCE)
+                .with_associativity(Associativity::Right),
+        );
+        self.assign_table.push(operator);
+        true
+    }
+
+    /// Remove the assignment operator with the symbol.
+    ///
+    /// The symbol stays in the operators, so it's parsed as an ordinary binary operator.
+    pub fn remove_assign_operator(&mut self, symbol: &str) -> Option<AssignOperator> {
+        let index = self
+            .assign_table
+            .iter()
+            .position(|op| op.symbol == symbol)?;
+        Some(self.assign_table.remove(index))
+    }
+
+    /// Get the assignment operator with the symbol.
+    pub fn get_assign(&self, symbol: &str) -> Option<&AssignOperator> {
+        self.assign_table.iter().find(|op| op.symbol == symbol)
+    }
+
+    /// Iterate over the assignment operators.
+    pub fn assign_operators(&self) -> impl Iterator<Item = &AssignOperator> {
+        self.assign_table.iter()
     }
 }
 
 impl Default for OperatorTable {
     fn default() -> Self {
         let mut table = vec![
-            Box::new(QuestionMark) as Box<dyn Operator>,
-            Box::new(AtAt),
-            Box::new(ColonAssign),
-            Box::new(ColonColonAssign),
-            Box::new(ModuloAssign),
-            Box::new(MultiplyAssign),
-            Box::new(DivideAssign),
-            Box::new(PlusAssign),
-            Box::new(MinusAssign),
-            Box::new(ShiftLeftAssign),
-            Box::new(ShiftRightAssign),
-            Box::new(BitwiseAndAssign),
-            Box::new(BitwiseXorAssign),
-            Box::new(BitwiseOrAssign),
-            Box::new(Power),
-            Box::new(At),
-            Box::new(Modulo),
-            Box::new(Multiply),
-            Box::new(Divide),
-            Box::new(Plus),
-            Box::new(Minus),
-            Box::new(ShiftLeft),
-            Box::new(ShiftRight),
-            Box::new(LessThanEquals),
-            Box::new(GreaterThanEquals),
-            Box::new(LessThan),
-            Box::new(GreaterThan),
-            Box::new(NotEquals),
-            Box::new(Assign),
-            Box::new(EqualsEquals),
-            Box::new(And),
-            Box::new(BitwiseAnd),
-            Box::new(BitwiseXor),
-            Box::new(BitwiseOr),
-            Box::new(AndKey),
-            Box::new(Or),
-            Box::new(OrKey),
-            Box::new(DotDot),
-            Box::new(Return),
+            OperatorRef::new(QuestionMark),
+            OperatorRef::new(AtAt),
+            OperatorRef::new(ColonAssign),
+            OperatorRef::new(ColonColonAssign),
+            OperatorRef::new(ModuloAssign),
+            OperatorRef::new(MultiplyAssign),
+            OperatorRef::new(DivideAssign),
+            OperatorRef::new(PlusAssign),
+            OperatorRef::new(MinusAssign),
+            OperatorRef::new(ShiftLeftAssign),
+            OperatorRef::new(ShiftRightAssign),
+            OperatorRef::new(BitwiseAndAssign),
+            OperatorRef::new(BitwiseXorAssign),
+            OperatorRef::new(BitwiseOrAssign),
+            OperatorRef::new(Power),
+            OperatorRef::new(At),
+            OperatorRef::new(Modulo),
+            OperatorRef::new(Multiply),
+            OperatorRef::new(Divide),
+            OperatorRef::new(Plus),
+            OperatorRef::new(Minus),
+            OperatorRef::new(ShiftLeft),
+            OperatorRef::new(ShiftRight),
+            OperatorRef::new(LessThanEquals),
+            OperatorRef::new(GreaterThanEquals),
+            OperatorRef::new(LessThan),
+            OperatorRef::new(GreaterThan),
+            OperatorRef::new(NotEquals),
+            OperatorRef::new(Assign),
+            OperatorRef::new(EqualsEquals),
+            OperatorRef::new(And),
+            OperatorRef::new(BitwiseAnd),
+            OperatorRef::new(BitwiseXor),
+            OperatorRef::new(BitwiseOr),
+            OperatorRef::new(AndKey),
+            OperatorRef::new(Or),
+            OperatorRef::new(OrKey),
+            OperatorRef::new(DotDot),
+            OperatorRef::new(Return),
         ];
         table.par_sort_unstable_by_key(|op| op.symbol());
         table.reverse();
 
+        let assign_table = vec![
+            AssignOperator::new("::=", "newSlot"),
+            AssignOperator::new(":=", "setSlot"),
+            AssignOperator::new("=", "updateSlot"),
+            AssignOperator::compound("%=", "%"),
+            AssignOperator::compound("*=", "*"),
+            AssignOperator::compound("/=", "/"),
+            AssignOperator::compound("+=", "+"),
+            AssignOperator::compound("-=", "-"),
+            AssignOperator::compound("<<=", "<<"),
+            AssignOperator::compound(">>=", ">>"),
+            AssignOperator::compound("&=", "&"),
+            AssignOperator::compound("^=", "^"),
+            AssignOperator::compound("|=", "|"),
+        ];
+
+        Self {
+            table,
+            assign_table,
+        }
+    }
+}
+
+/// The precedence of the assignment operators.
+pub const ASSIGN_PRECEDENCE: u32 = 13;
+
+/// An assignment operator, like Io's `OperatorTable assignOperators`.
+///
+/// After parsing, `a := b` is rewritten to `setSlot("a", b)`. A compound operator applies its
+/// binary operator to the slot first: `a += b` is rewritten to `updateSlot("a", a + b)`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AssignOperator {
+    /// The operator symbol (`:=`, `+=`, etc.).
+    pub symbol: &'static str,
+    /// The slot message the assignment is rewritten to (`setSlot`, `updateSlot`, etc.).
+    pub message: &'static str,
+    /// The binary operator of a compound assignment (`+` for `+=`).
+    pub operator: Option<&'static str>,
+}
+
+impl AssignOperator {
+    /// Create an assignment operator which sends the slot message.
+    pub fn new(symbol: &'static str, message: &'static str) -> Self {
         Self {
-            table: Mutex::new(table),
+            symbol,
+            message,
+            operator: None,
+        }
+    }
+
+    /// Create a compound assignment operator, which updates the slot with the binary operator.
+    pub fn compound(symbol: &'static str, operator: &'static str) -> Self {
+        Self {
+            symbol,
+            message: "updateSlot",
+            operator: Some(operator),
         }
     }
 }
 
 /// Each operator should implement this trait.
-pub trait Operator: std::fmt::Debug + DynClone + Send + Sync + 'static {
+pub trait Operator: fmt::Debug + DynClone + Send + Sync + 'static {
     /// The operator symbol (`=`, `>`, etc.).
     fn symbol(&self) -> &'static str;
     /// The operator precedence.
     fn precedence(&self) -> u32;
+    /// The operator associativity.
+    fn associativity(&self) -> Associativity {
+        Associativity::Left
+    }
+    /// The operator arity.
+    fn arity(&self) -> Arity {
+        Arity::Binary
+    }
+}
+
+/// A shared operator along with its interned symbol.
+///
+/// Cloning the reference doesn't clone the operator.
+#[derive(Clone)]
+pub struct OperatorRef {
+    id: SymbolId,
+    operator: Arc<dyn Operator>,
+}
+
+impl OperatorRef {
+    /// Create a reference to the operator.
+    pub fn new(operator: impl Operator) -> Self {
+        Self {
+            id: SymbolId::intern(operator.symbol()),
+            operator: Arc::new(operator),
+        }
+    }
+
+    /// The interned symbol of the operator.
+    pub fn id(&self) -> SymbolId {
+        self.id
+    }
+}
+
+impl Deref for OperatorRef {
+    type Target = dyn Operator;
+
+    fn deref(&self) -> &Self::Target {
+        self.operator.as_ref()
+    }
+}
+
+impl fmt::Debug for OperatorRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.operator.fmt(f)
+    }
+}
+
+/// How the operators of the same precedence are grouped.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ** b ** c` is `a ** (b ** c)`.
+    Right,
+}
+
+/// What an operator is applied to.
+///
+/// A binary operator in place of an operand (at the start of a chain or right after another
+/// operator) is applied as a prefix one, like in `a * -b`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Arity {
+    /// `a + b`.
+    Binary,
+    /// `return a`, it never takes the operand on the left.
+    Prefix,
+    /// `a++`, it's sent to the operand on the left like an ordinary message.
+    Postfix,
 }
 
 dyn_clone::clone_trait_object!(Operator);
 
+impl dyn Operator {
+    /// Check if the operator on the right of `other` takes the operand between them.
+    pub(crate) fn binds_tighter(&self, other: &dyn Operator) -> bool {
+        self.precedence() < other.precedence()
+            || self.precedence() == other.precedence()
+                && other.associativity() == Associativity::Right
+    }
+}
+
+/// An operator defined at runtime.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomOperator {
+    symbol: &'static str,
+    precedence: u32,
+    associativity: Associativity,
+    arity: Arity,
+}
+
+impl CustomOperator {
+    /// Create a new left-associative binary operator.
+    pub fn new(symbol: &'static str, precedence: u32) -> Self {
+        Self {
+            symbol,
+            precedence,
+            associativity: Associativity::Left,
+            arity: Arity::Binary,
+        }
+    }
+
+    /// Set the associativity of the operator.
+    pub fn with_associativity(mut self, associativity: Associativity) -> Self {
+        self.associativity = associativity;
+        self
+    }
+
+    /// Set the arity of the operator.
+    pub fn with_arity(mut self, arity: Arity) -> Self {
+        self.arity = arity;
+        self
+    }
+}
+
+impl Operator for CustomOperator {
+    fn symbol(&self) -> &'static str {
+        self.symbol
+    }
+
+    fn precedence(&self) -> u32 {
+        self.precedence
+    }
+
+    fn associativity(&self) -> Associativity {
+        self.associativity
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+}
+
 macro_rules! impl_op {
-    ($name:ident, $symbol:expr, $precedence:expr) => {
+    (
+        $name:ident, $symbol:expr, $precedence:expr
+        $(, associativity = $associativity:ident)?
+        $(, arity = $arity:ident)?
+    ) => {
         #[derive(Debug, Clone, Copy)]
         #[allow(missing_docs)]
         pub struct $name;
@@ -112,6 +369,18 @@ macro_rules! impl_op {
             fn precedence(&self) -> u32 {
                 $precedence
             }
+
+            $(
+                fn associativity(&self) -> Associativity {
+                    Associativity::$associativity
+                }
+            )?
+
+            $(
+                fn arity(&self) -> Arity {
+                    Arity::$arity
+                }
+            )?
         }
     };
 }
@@ -119,7 +388,7 @@ macro_rules! impl_op {
 impl_op!(QuestionMark, "?", 0);
 impl_op!(At, "@", 0);
 impl_op!(AtAt, "@@", 0);
-impl_op!(Power, "**", 1);
+impl_op!(Power, "**", 1, associativity = Right);
 impl_op!(Modulo, "%", 2);
 impl_op!(Multiply, "*", 2);
 impl_op!(Divide, "/", 2);
@@ -141,17 +410,52 @@ impl_op!(AndKey, "and", 10);
 impl_op!(Or, "||", 11);
 impl_op!(OrKey, "or", 11);
 impl_op!(DotDot, "..", 12);
-impl_op!(Assign, "=", 13);
-impl_op!(ColonAssign, ":=", 13);
-impl_op!(ColonColonAssign, "::=", 13);
-impl_op!(ModuloAssign, "%=", 13);
-impl_op!(MultiplyAssign, "*=", 13);
-impl_op!(DivideAssign, "/=", 13);
-impl_op!(PlusAssign, "+=", 13);
-impl_op!(MinusAssign, "-=", 13);
-impl_op!(ShiftLeftAssign, "<<=", 13);
-impl_op!(ShiftRightAssign, ">>=", 13);
-impl_op!(BitwiseAndAssign, "&=", 13);
-impl_op!(BitwiseXorAssign, "^=", 13);
-impl_op!(BitwiseOrAssign, "|=", 13);
-impl_op!(Return, "return", u32::MAX);
+impl_op!(Assign, "=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(ColonAssign, ":=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(
+    ColonColonAssign,
+    "::=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(ModuloAssign, "%=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(
+    MultiplyAssign,
+    "*=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(DivideAssign, "/=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(PlusAssign, "+=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(MinusAssign, "-=", ASSIGN_PRECEDENCE, associativity = Right);
+impl_op!(
+    ShiftLeftAssign,
+    "<<=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(
+    ShiftRightAssign,
+    ">>=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(
+    BitwiseAndAssign,
+    "&=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(
+    BitwiseXorAssign,
+    "^=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(
+    BitwiseOrAssign,
+    "|=",
+    ASSIGN_PRECEDENCE,
+    associativity = Right
+);
+impl_op!(Return, "return", u32::MAX, arity = Prefix);
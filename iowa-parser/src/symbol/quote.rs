@@ -2,28 +2,95 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take_until},
     combinator::map,
-    sequence::{delimited, preceded},
-    IResult,
+    sequence::terminated,
+    InputTake,
+};
+
+use crate::{
+    error::{Error, ErrorKind},
+    Input, PResult,
 };
 
 /// Quote (string) token.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quote(String);
 
-pub(crate) fn quote(input: &str) -> IResult<&str, Quote> {
+impl Quote {
+    /// The contents of the quote.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Quote {
+    fn from(input: &str) -> Self {
+        Self(input.to_owned())
+    }
+}
+
+impl From<String> for Quote {
+    fn from(input: String) -> Self {
+        Self(input)
+    }
+}
+
+/// Prints the quote in single double quotes with the special characters escaped.
+impl std::fmt::Display for Quote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("\"")?;
+        for ch in self.0.chars() {
+            match ch {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                '\0' => f.write_str("\\0")?,
+                ch if ch.is_control() && (ch as u32) < 0x80 => write!(f, "\\x{:02x}", ch as u32)?,
+                ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+                ch => write!(f, "{ch}")?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+pub(crate) fn quote(input: Input<'_>) -> PResult<'_, Quote> {
     map(alt((tri_quote, mono_quote)), Quote)(input)
 }
 
-fn mono_quote(input: &str) -> IResult<&str, String> {
-    preceded(tag("\""), unescape)(input)
+fn mono_quote(input: Input<'_>) -> PResult<'_, String> {
+    let (rest, _) = tag("\"")(input.clone())?;
+    unescape(rest).map_err(|err| {
+        err.map(|err| match err.kind {
+            // point at the opening quote
+            ErrorKind::UnterminatedString => Error {
+                input: input.clone(),
+                ..err
+            },
+            _ => err,
+        })
+    })
 }
 
-fn unescape(input: &str) -> IResult<&str, String> {
+fn unescape(input: Input<'_>) -> PResult<'_, String> {
     let mut output = String::new();
-    let chars = &mut input.chars();
+    let chars = &mut input.fragment().chars();
+    let unterminated = || {
+        nom::Err::Failure(Error::new(input.clone(), ErrorKind::UnterminatedString).expected("\""))
+    };
+
+    loop {
+        let offset = input.len() - chars.as_str().len();
+        let Some(ch) = chars.next() else {
+            return Err(unterminated());
+        };
 
-    while let Some(ch) = chars.next() {
         if ch == '\\' {
+            let bad_escape =
+                || nom::Err::Failure(Error::new(input.take_split(offset).0, ErrorKind::BadEscape));
+
             match chars.next() {
                 Some('a') => output.push('\x07'),
                 Some('b') => output.push('\x08'),
@@ -39,72 +106,93 @@ fn unescape(input: &str) -> IResult<&str, String> {
                 Some('0') => output.push('\0'),
                 Some('x') => {
                     let hex_str: String = chars.take(2).collect();
-                    let byte = u8::from_str_radix(&hex_str, 16).unwrap();
+                    let byte = u8::from_str_radix(&hex_str, 16).map_err(|_| bad_escape())?;
                     output.push(byte as char);
                 }
-                Some('u') => {
-                    let hex_str: String = chars.take(4).collect();
-                    let code_point = u32::from_str_radix(&hex_str, 16).unwrap();
-                    output.push(std::char::from_u32(code_point).unwrap());
-                }
-                Some('U') => {
-                    let hex_str: String = chars.take(8).collect();
-                    let code_point = u32::from_str_radix(&hex_str, 16).unwrap();
-                    output.push(std::char::from_u32(code_point).unwrap());
-                }
+                Some('u') => output.push(code_point(chars, 4).ok_or_else(bad_escape)?),
+                Some('U') => output.push(code_point(chars, 8).ok_or_else(bad_escape)?),
                 Some(ch) => output.push(ch),
-                None => {
-                    return Err(nom::Err::Error(nom::error::Error::new(
-                        input,
-                        nom::error::ErrorKind::Fail,
-                    )))
-                }
+                None => return Err(unterminated()),
             }
         } else if ch == '"' {
-            return Ok((chars.as_str(), output));
+            let consumed = input.len() - chars.as_str().len();
+            return Ok((input.take_split(consumed).0, output));
         } else if ch == '\n' {
-            return Err(nom::Err::Failure(nom::error::Error::new(
-                input,
-                nom::error::ErrorKind::Fail,
-            )));
+            return Err(unterminated());
         } else {
             output.push(ch);
         }
     }
-
-    Err(nom::Err::Incomplete(nom::Needed::Unknown))
 }
 
-fn tri_quote(input: &str) -> IResult<&str, String> {
-    delimited(tag("\"\"\""), take_until("\"\"\""), tag("\"\"\""))(input)
-        .map(|(i, o)| (i, o.to_string()))
+fn code_point(chars: &mut std::str::Chars<'_>, len: usize) -> Option<char> {
+    let hex_str: String = chars.take(len).collect();
+    u32::from_str_radix(&hex_str, 16)
+        .ok()
+        .and_then(std::char::from_u32)
 }
 
+fn tri_quote(input: Input<'_>) -> PResult<'_, String> {
+    let (rest, _) = tag("\"\"\"")(input.clone())?;
+    let res: PResult<'_, Input<'_>> = terminated(take_until("\"\"\""), tag("\"\"\""))(rest);
+    res.map(|(i, o)| (i, o.fragment().to_string()))
+        .map_err(|_| {
+            nom::Err::Failure(Error::new(input, ErrorKind::UnterminatedString).expected("\"\"\""))
+        })
+}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_run;
 
     #[test]
     fn test_parse_mono_quote() {
-        assert_eq!(mono_quote(r#""test""#), Ok(("", "test".to_string())));
-        assert_eq!(mono_quote(r#""\n""#), Ok(("", "\n".to_string())));
         assert_eq!(
-            mono_quote(r#""hello, \"world\"""#),
+            test_run(mono_quote, r#""test""#),
+            Ok(("", "test".to_string()))
+        );
+        assert_eq!(test_run(mono_quote, r#""\n""#), Ok(("", "\n".to_string())));
+        assert_eq!(
+            test_run(mono_quote, r#""hello, \"world\"""#),
             Ok(("", "hello, \"world\"".to_string()))
         );
-        assert_eq!(mono_quote(r#""""#), Ok(("", "".to_string())));
-        assert_eq!(mono_quote(r#""test"\n"#), Ok(("\\n", "test".to_string())));
+        assert_eq!(test_run(mono_quote, r#""""#), Ok(("", "".to_string())));
+        assert_eq!(
+            test_run(mono_quote, r#""test"\n"#),
+            Ok(("\\n", "test".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_bad_quote() {
+        assert_eq!(
+            test_run(mono_quote, r#""test"#),
+            Err(nom::Err::Failure(ErrorKind::UnterminatedString))
+        );
+        assert_eq!(
+            test_run(mono_quote, "\"test\n\""),
+            Err(nom::Err::Failure(ErrorKind::UnterminatedString))
+        );
+        assert_eq!(
+            test_run(mono_quote, r#""\u12""#),
+            Err(nom::Err::Failure(ErrorKind::BadEscape))
+        );
+        assert_eq!(
+            test_run(tri_quote, r#""""test""#),
+            Err(nom::Err::Failure(ErrorKind::UnterminatedString))
+        );
     }
 
     #[test]
     fn test_parse_tri_quote() {
-        assert_eq!(tri_quote(r#""""""""#), Ok(("", String::new())));
+        assert_eq!(test_run(tri_quote, r#""""""""#), Ok(("", String::new())));
         assert_eq!(
-            tri_quote(r#""""Hello, world!""""#),
+            test_run(tri_quote, r#""""Hello, world!""""#),
             Ok(("", "Hello, world!".to_string()))
         );
         assert_eq!(
-            tri_quote(
+            test_run(
+                tri_quote,
                 r#""""This is a "test" test,
     hello!""""#
             ),
@@ -112,16 +200,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_quote() {
+        for text in ["test", "a \"b\" \\ c", "\n\t\r\0\x07\u{85}", "тест"] {
+            let printed = Quote(text.to_string()).to_string();
+            assert_eq!(test_run(quote, &printed), Ok(("", Quote(text.to_string()))));
+        }
+    }
+
     #[test]
     fn test_parse_quote() {
-        assert_eq!(quote(r#""test""#), Ok(("", Quote("test".to_string()))));
         assert_eq!(
-            quote(r#""hello, \"world\"""#),
+            test_run(quote, r#""test""#),
+            Ok(("", Quote("test".to_string())))
+        );
+        assert_eq!(
+            test_run(quote, r#""hello, \"world\"""#),
             Ok(("", Quote("hello, \"world\"".to_string())))
         );
-        assert_eq!(quote(r#""""""""#), Ok(("", Quote(String::new()))));
+        assert_eq!(test_run(quote, r#""""""""#), Ok(("", Quote(String::new()))));
         assert_eq!(
-            quote(r#""""Hello, world!""""#),
+            test_run(quote, r#""""Hello, world!""""#),
             Ok(("", Quote("Hello, world!".to_string())))
         );
     }
@@ -0,0 +1,269 @@
+//! Tree traversal.
+//!
+//! [`Visit`] walks the tree by reference, [`VisitMut`] by mutable reference and [`Fold`] takes the
+//! tree by value and builds a new one. Each method has a default implementation which calls the
+//! function of the same name from this module (`walk_*` or `fold_*`) to go through the children,
+//! so a pass only overrides the methods it's interested in:
+//!
+//! ```
+//! use iowa_parser::visit::{self, Visit};
+//! use iowa_parser::{Message, Symbol};
+//!
+//! #[derive(Default)]
+//! struct Sends(Vec<String>);
+//!
+//! impl<'a> Visit<'a> for Sends {
+//!     fn visit_message(&mut self, msg: &Message<'a>) {
+//!         if let Symbol::Identifier(ident) = &msg.symbol {
+//!             self.0.push(ident.as_str().to_owned());
+//!         }
+//!         visit::walk_message(self, msg);
+//!     }
+//! }
+//!
+//! let mut sends = Sends::default();
+//! for chain in iowa_parser::parse("foo bar(baz)").unwrap() {
+//!     sends.visit_chain(&chain);
+//! }
+//! assert_eq!(sends.0, ["foo", "bar", "baz"]);
+//! ```
+
+use crate::{Argument, Message, MessageChain, Symbol};
+
+/// Walk the tree by reference.
+pub trait Visit<'a> {
+    /// Visit a chain.
+    fn visit_chain(&mut self, chain: &MessageChain<'a>) {
+        walk_chain(self, chain);
+    }
+
+    /// Visit a message.
+    fn visit_message(&mut self, msg: &Message<'a>) {
+        walk_message(self, msg);
+    }
+
+    /// Visit an argument.
+    fn visit_argument(&mut self, arg: &Argument<'a>) {
+        walk_argument(self, arg);
+    }
+
+    /// Visit the symbol of a message.
+    fn visit_symbol(&mut self, _symbol: &Symbol<'a>) {}
+}
+
+/// Visit the messages of the chain.
+pub fn walk_chain<'a, V: Visit<'a> + ?Sized>(visitor: &mut V, chain: &MessageChain<'a>) {
+    for msg in chain.iter() {
+        visitor.visit_message(msg);
+    }
+}
+
+/// Visit the symbol and the arguments of the message.
+pub fn walk_message<'a, V: Visit<'a> + ?Sized>(visitor: &mut V, msg: &Message<'a>) {
+    visitor.visit_symbol(&msg.symbol);
+    for arg in &msg.args {
+        visitor.visit_argument(arg);
+    }
+}
+
+/// Visit the chains of the argument.
+pub fn walk_argument<'a, V: Visit<'a> + ?Sized>(visitor: &mut V, arg: &Argument<'a>) {
+    for chain in arg.iter() {
+        visitor.visit_chain(chain);
+    }
+}
+
+/// Walk the tree by mutable reference.
+///
+/// The spans aren't updated, a pass which moves the messages around should fix them itself.
+pub trait VisitMut<'a> {
+    /// Visit a chain.
+    fn visit_chain_mut(&mut self, chain: &mut MessageChain<'a>) {
+        walk_chain_mut(self, chain);
+    }
+
+    /// Visit a message.
+    fn visit_message_mut(&mut self, msg: &mut Message<'a>) {
+        walk_message_mut(self, msg);
+    }
+
+    /// Visit an argument.
+    fn visit_argument_mut(&mut self, arg: &mut Argument<'a>) {
+        walk_argument_mut(self, arg);
+    }
+
+    /// Visit the symbol of a message.
+    fn visit_symbol_mut(&mut self, _symbol: &mut Symbol<'a>) {}
+}
+
+/// Visit the messages of the chain.
+pub fn walk_chain_mut<'a, V: VisitMut<'a> + ?Sized>(visitor: &mut V, chain: &mut MessageChain<'a>) {
+    for msg in chain.iter_mut() {
+        visitor.visit_message_mut(msg);
+    }
+}
+
+/// Visit the symbol and the arguments of the message.
+pub fn walk_message_mut<'a, V: VisitMut<'a> + ?Sized>(visitor: &mut V, msg: &mut Message<'a>) {
+    visitor.visit_symbol_mut(&mut msg.symbol);
+    for arg in &mut msg.args {
+        visitor.visit_argument_mut(arg);
+    }
+}
+
+/// Visit the chains of the argument.
+pub fn walk_argument_mut<'a, V: VisitMut<'a> + ?Sized>(visitor: &mut V, arg: &mut Argument<'a>) {
+    for chain in arg.iter_mut() {
+        visitor.visit_chain_mut(chain);
+    }
+}
+
+/// Transform the tree into a new one.
+///
+/// The folded nodes keep their original spans.
+pub trait Fold<'a> {
+    /// Fold a chain.
+    fn fold_chain(&mut self, chain: MessageChain<'a>) -> MessageChain<'a> {
+        fold_chain(self, chain)
+    }
+
+    /// Fold a message.
+    fn fold_message(&mut self, msg: Message<'a>) -> Message<'a> {
+        fold_message(self, msg)
+    }
+
+    /// Fold an argument.
+    fn fold_argument(&mut self, arg: Argument<'a>) -> Argument<'a> {
+        fold_argument(self, arg)
+    }
+
+    /// Fold the symbol of a message.
+    fn fold_symbol(&mut self, symbol: Symbol<'a>) -> Symbol<'a> {
+        symbol
+    }
+}
+
+/// Fold the messages of the chain.
+pub fn fold_chain<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    chain: MessageChain<'a>,
+) -> MessageChain<'a> {
+    MessageChain {
+        messages: chain
+            .messages
+            .into_iter()
+            .map(|msg| folder.fold_message(msg))
+            .collect(),
+        span: chain.span,
+    }
+}
+
+/// Fold the symbol and the arguments of the message.
+pub fn fold_message<'a, F: Fold<'a> + ?Sized>(folder: &mut F, msg: Message<'a>) -> Message<'a> {
+    Message {
+        symbol: folder.fold_symbol(msg.symbol),
+        args: msg
+            .args
+            .into_iter()
+            .map(|arg| folder.fold_argument(arg))
+            .collect(),
+        span: msg.span,
+    }
+}
+
+/// Fold the chains of the argument.
+pub fn fold_argument<'a, F: Fold<'a> + ?Sized>(folder: &mut F, arg: Argument<'a>) -> Argument<'a> {
+    Argument {
+        chains: arg
+            .chains
+            .into_iter()
+            .map(|chain| folder.fold_chain(chain))
+            .collect(),
+        span: arg.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Identifier, Number};
+
+    #[test]
+    fn test_visit() {
+        #[derive(Default)]
+        struct Counter {
+            messages: usize,
+            arguments: usize,
+            chains: usize,
+        }
+
+        impl<'a> Visit<'a> for Counter {
+            fn visit_chain(&mut self, chain: &MessageChain<'a>) {
+                self.chains += 1;
+                walk_chain(self, chain);
+            }
+
+            fn visit_message(&mut self, msg: &Message<'a>) {
+                self.messages += 1;
+                walk_message(self, msg);
+            }
+
+            fn visit_argument(&mut self, arg: &Argument<'a>) {
+                self.arguments += 1;
+                walk_argument(self, arg);
+            }
+        }
+
+        let mut counter = Counter::default();
+        for chain in parse("foo(a b, c; d) bar\nbaz").unwrap() {
+            counter.visit_chain(&chain);
+        }
+        assert_eq!(counter.chains, 5);
+        assert_eq!(counter.arguments, 2);
+        assert_eq!(counter.messages, 7);
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        struct Rename;
+
+        impl<'a> VisitMut<'a> for Rename {
+            fn visit_symbol_mut(&mut self, symbol: &mut Symbol<'a>) {
+                if *symbol == Symbol::Identifier("foo".into()) {
+                    *symbol = Symbol::Identifier(Identifier::from("qux"));
+                }
+            }
+        }
+
+        let mut chains = parse("foo bar(foo)").unwrap();
+        Rename.visit_chain_mut(&mut chains[0]);
+        assert_eq!(chains, parse("qux bar(qux)").unwrap());
+    }
+
+    #[test]
+    fn test_fold() {
+        struct Increment;
+
+        impl<'a> Fold<'a> for Increment {
+            fn fold_symbol(&mut self, symbol: Symbol<'a>) -> Symbol<'a> {
+                match symbol {
+                    Symbol::Number(Number::Hex(num)) => Symbol::Number(Number::Hex(num + 1)),
+                    symbol => symbol,
+                }
+            }
+
+            fn fold_chain(&mut self, chain: MessageChain<'a>) -> MessageChain<'a> {
+                // drop the `skip` messages
+                let mut chain = fold_chain(self, chain);
+                chain.retain(|msg| msg.symbol != Symbol::Identifier("skip".into()));
+                chain
+            }
+        }
+
+        let chains = parse("list(0x1 skip, 0x2)").unwrap();
+        let span = chains[0].span();
+        let folded = Increment.fold_chain(chains.into_iter().next().unwrap());
+        assert_eq!(folded, parse("list(0x2, 0x3)").unwrap()[0]);
+        assert_eq!(folded.span(), span);
+    }
+}
@@ -0,0 +1,122 @@
+//! Runtime support library for Io programs compiled ahead of time.
+//!
+//! The objects made by [`iowa_compiler::aot::build`] are linked with the static library of this
+//! crate, which runs them on the [`Vm`].
+
+#![warn(
+    clippy::all,
+    deprecated_in_future,
+    missing_docs,
+    unused_import_braces,
+    unused_labels,
+    unused_lifetimes,
+    unused_qualifications,
+    unreachable_pub
+)]
+
+use std::ffi::{c_char, CStr};
+use std::io::Write;
+
+use iowa_compiler::aot::{NativeChunk, NativeFrame};
+use iowa_compiler::Vm;
+use iowa_runtime::{exit_status, Error};
+
+/// The size of the stack the program runs on, deep enough for the activations the machine allows
+/// before it raises a stack overflow.
+const STACK_SIZE: usize = 1 << 30;
+
+/// Run the program with the arguments of `main` as `System args`, printing an error with its stack
+/// trace to the standard error. Returns the exit status.
+///
+/// # Safety
+///
+/// The source and the name of its file must be valid UTF-8 of the lengths, the chunks must be the
+/// functions compiled from the source, of the count, and the arguments must be the ones of `main`.
+/// The program is rejected if the fingerprint isn't the one of the source compiled by this
+/// runtime.
+#[no_mangle]
+pub unsafe extern "C" fn iowa_rt_main(
+    source: *const u8,
+    len: usize,
+    name: *const u8,
+    name_len: usize,
+    fingerprint: u64,
+    chunks: *const NativeChunk,
+    count: usize,
+    argc: i32,
+    argv: *const *const c_char,
+) -> i32 {
+    let source = std::str::from_utf8_unchecked(std::slice::from_raw_parts(source, len));
+    let name = std::str::from_utf8_unchecked(std::slice::from_raw_parts(name, name_len));
+    let chunks = std::slice::from_raw_parts(chunks, count);
+    let args: Vec<String> = (0..argc.max(0) as usize)
+        .map(|i| CStr::from_ptr(*argv.add(i)).to_string_lossy().into_owned())
+        .collect();
+
+    let run = move || run(source, name, fingerprint, chunks, args.clone());
+    std::thread::scope(|scope| {
+        let main = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, run.clone());
+        match main {
+            Ok(main) => main.join().unwrap_or(101),
+            // the stack can't be reserved, run with the default one
+            Err(_) => run(),
+        }
+    })
+}
+
+fn run(
+    source: &str,
+    name: &str,
+    fingerprint: u64,
+    chunks: &[NativeChunk],
+    args: Vec<String>,
+) -> i32 {
+    let mut vm = Vm::new();
+    vm.interpreter().set_args(args);
+    let res = vm.eval_native(source, fingerprint, chunks);
+    let _ = std::io::stdout().flush();
+    match res {
+        Ok(_) => 0,
+        Err(Error::Exit(status)) => i32::from(exit_status(status)),
+        Err(err) => {
+            eprintln!("{}", err.report(name));
+            1
+        }
+    }
+}
+
+/// Run the instruction at the index. Returns non-zero when it stops the code.
+///
+/// # Safety
+///
+/// The frame must be the one the chunk was called with.
+#[no_mangle]
+pub unsafe extern "C" fn iowa_rt_exec(frame: *mut NativeFrame, pc: u32) -> i32 {
+    i32::from(!(*frame).exec(pc))
+}
+
+/// Run the check of the arguments or of the inlined method at the index. Returns 1 when it sent
+/// the message lazily, -1 when it stops the code and 0 otherwise.
+///
+/// # Safety
+///
+/// The frame must be the one the chunk was called with.
+#[no_mangle]
+pub unsafe extern "C" fn iowa_rt_lazy(frame: *mut NativeFrame, pc: u32) -> i32 {
+    match (*frame).lazy_args(pc) {
+        Some(sent) => i32::from(sent),
+        None => -1,
+    }
+}
+
+/// Pop the condition of a jump. Returns non-zero when it's true.
+///
+/// # Safety
+///
+/// The frame must be the one the chunk was called with.
+#[no_mangle]
+pub unsafe extern "C" fn iowa_rt_test(frame: *mut NativeFrame) -> i32 {
+    i32::from((*frame).test())
+}
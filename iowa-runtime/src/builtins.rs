@@ -0,0 +1,118 @@
+//! The built-in objects and their methods.
+
+mod block;
+mod call;
+mod exception;
+mod list;
+mod message;
+mod number;
+mod object;
+mod sequence;
+mod system;
+
+use crate::interpreter::{NativeFn, Protos};
+use crate::object::{Data, Native, ObjectRef};
+
+/// Create the object graph the code runs in.
+///
+/// `Lobby` inherits from `Protos`, which inherits from `Core` with all the built-in protos.
+/// `Object` inherits from `Lobby` in turn, so the names defined at the top level are visible to
+/// every object.
+pub(crate) fn boot() -> (ObjectRef, Protos) {
+    let object = ObjectRef::new(Vec::new(), Data::None);
+    let core = ObjectRef::new(vec![object.clone()], Data::None);
+    let protos = ObjectRef::new(vec![core.clone()], Data::None);
+    let lobby = ObjectRef::new(vec![protos.clone()], Data::None);
+    object.append_proto(lobby.clone());
+
+    let proto = |data| ObjectRef::new(vec![object.clone()], data);
+    let builtins = Protos {
+        number: proto(Data::Number(0.0)),
+        sequence: proto(Data::Sequence("".into())),
+        list: proto(Data::List(Vec::new())),
+        block: proto(Data::None),
+        nil: proto(Data::None),
+        true_: proto(Data::None),
+        false_: proto(Data::None),
+        system: proto(Data::None),
+        exception: proto(Data::None),
+        message: proto(Data::None),
+        call: proto(Data::None),
+        object,
+    };
+
+    let sequence =
+        |seq: &str| ObjectRef::new(vec![builtins.sequence.clone()], Data::Sequence(seq.into()));
+    for (name, obj) in [
+        ("Lobby", &lobby),
+        ("Protos", &protos),
+        ("Core", &core),
+        ("Object", &builtins.object),
+        ("Number", &builtins.number),
+        ("Sequence", &builtins.sequence),
+        ("List", &builtins.list),
+        ("Block", &builtins.block),
+        ("nil", &builtins.nil),
+        ("true", &builtins.true_),
+        ("false", &builtins.false_),
+        ("System", &builtins.system),
+        ("Exception", &builtins.exception),
+        ("Message", &builtins.message),
+        ("Call", &builtins.call),
+    ] {
+        obj.set_slot("type", sequence(name));
+    }
+    lobby.set_slot("Lobby", lobby.clone());
+    lobby.set_slot("Protos", protos.clone());
+    protos.set_slot("Core", core.clone());
+    for (name, obj) in [
+        ("Object", &builtins.object),
+        ("Number", &builtins.number),
+        ("Sequence", &builtins.sequence),
+        ("List", &builtins.list),
+        ("Block", &builtins.block),
+        ("nil", &builtins.nil),
+        ("true", &builtins.true_),
+        ("false", &builtins.false_),
+        ("System", &builtins.system),
+        ("Exception", &builtins.exception),
+        ("Message", &builtins.message),
+        ("Call", &builtins.call),
+    ] {
+        core.set_slot(name, obj.clone());
+    }
+    let args = ObjectRef::new(vec![builtins.list.clone()], Data::List(Vec::new()));
+    builtins.system.set_slot("args", args);
+    builtins.exception.set_slot("error", builtins.nil.clone());
+
+    let define = |obj: &ObjectRef, methods: &[(&'static str, NativeFn)]| {
+        for &(name, func) in methods {
+            let native = Data::Native(Native { name, func });
+            obj.set_slot(name, ObjectRef::new(vec![builtins.object.clone()], native));
+        }
+    };
+    define(&builtins.object, object::METHODS);
+    define(&builtins.number, number::METHODS);
+    define(&builtins.sequence, sequence::METHODS);
+    define(&builtins.list, list::METHODS);
+    define(&builtins.block, block::METHODS);
+    define(&builtins.system, system::METHODS);
+    define(&builtins.exception, exception::METHODS);
+    define(&builtins.message, message::METHODS);
+    define(&builtins.call, call::METHODS);
+    define(&builtins.nil, &[("asString", object::nil_as_string)]);
+    define(&builtins.nil, exception::NIL_METHODS);
+    define(&builtins.true_, &[("asString", object::true_as_string)]);
+    define(&builtins.false_, &[("asString", object::false_as_string)]);
+
+    (lobby, builtins)
+}
+
+/// Check if the objects are equal: numbers and sequences by value, others by identity.
+pub(crate) fn equals(a: &ObjectRef, b: &ObjectRef) -> bool {
+    match (&*a.data(), &*b.data()) {
+        (Data::Number(a), Data::Number(b)) => a == b,
+        (Data::Sequence(a), Data::Sequence(b)) => a == b,
+        _ => a.ptr_eq(b),
+    }
+}
@@ -0,0 +1,77 @@
+//! The methods of `Block`, the methods and the blocks.
+
+use std::rc::Rc;
+
+use iowa_parser::{Argument, Identifier, Message, MessageChain, Symbol};
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Block, Data};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("call", call),
+    ("argumentNames", argument_names),
+    ("code", code),
+    ("scope", scope),
+    ("setScope", set_scope),
+];
+
+fn block(interp: &Interpreter, act: &Activation<'_>) -> Result<Rc<Block>, Stop> {
+    act.target
+        .as_block()
+        .ok_or_else(|| interp.error(act.msg, "the receiver is not a Block"))
+}
+
+/// Run the block with the arguments.
+fn call(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let block = block(interp, act)?;
+    interp.call_block(&block, act)
+}
+
+fn argument_names(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let names = block(interp, act)?
+        .params
+        .iter()
+        .map(|param| interp.sequence(param.as_str()))
+        .collect();
+    Ok(interp.list(names))
+}
+
+/// The source of the block, `method(...)` or `block(...)`.
+fn code(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let block = block(interp, act)?;
+    let name = match block.scope {
+        Some(_) => "block",
+        None => "method",
+    };
+    let mut args: Vec<_> = block
+        .params
+        .iter()
+        .map(|param| {
+            let symbol = Symbol::Identifier(Identifier::from(*param));
+            Argument::new(vec![MessageChain::new(vec![Message::new(
+                symbol,
+                Vec::new(),
+            )])])
+        })
+        .collect();
+    args.push(Argument::new(block.body.clone()));
+    let msg = Message::new(Symbol::Identifier(Identifier::from(name)), args);
+    Ok(interp.sequence(&msg.to_string()))
+}
+
+/// The context of the block, `nil` for a method.
+fn scope(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let block = block(interp, act)?;
+    Ok(block.scope.clone().unwrap_or_else(|| interp.nil()))
+}
+
+/// Change the context of the block. With `nil`, it runs in the context of the receiver like a
+/// method.
+fn set_scope(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let block = block(interp, act)?;
+    let scope = interp.arg(act, 0)?;
+    let scope = (!scope.ptr_eq(&interp.nil())).then_some(scope);
+    let block = Block::new(block.params.clone(), block.body.clone(), scope);
+    *act.target.data_mut() = Data::Block(Rc::new(block));
+    Ok(act.target.clone())
+}
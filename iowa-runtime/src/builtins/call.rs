@@ -0,0 +1,84 @@
+//! The methods of `Call`, the activation of a method.
+
+use std::rc::Rc;
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Call, Data};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("sender", sender),
+    ("target", target),
+    ("message", message),
+    ("argAt", arg_at),
+    ("argCount", arg_count),
+    ("evalArgAt", eval_arg_at),
+    ("evalArgs", eval_args),
+];
+
+fn call(interp: &Interpreter, act: &Activation<'_>) -> Result<Rc<Call>, Stop> {
+    match &*act.target.data() {
+        Data::Call(call) => Ok(call.clone()),
+        _ => Err(interp.error(act.msg, "the receiver is not a Call")),
+    }
+}
+
+/// The index of the argument of the call, if it has one.
+fn index(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    call: &Call,
+) -> Result<Option<usize>, Stop> {
+    let index = interp.number_arg(act, 0)?;
+    Ok((index >= 0.0 && (index as usize) < call.msg.args.len()).then_some(index as usize))
+}
+
+fn sender(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(call(interp, act)?.sender.clone())
+}
+
+fn target(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(call(interp, act)?.target.clone())
+}
+
+/// The message which activated the method, the same object each time.
+fn message(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let call = call(interp, act)?;
+    let message = call
+        .message
+        .get_or_init(|| interp.chain_object(&call.msg, &call.next));
+    Ok(message.clone())
+}
+
+/// The unevaluated argument as a `Message`.
+fn arg_at(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let call = call(interp, act)?;
+    match index(interp, act, &call)? {
+        Some(index) => Ok(interp.message(&call.msg.args[index])),
+        None => Ok(interp.nil()),
+    }
+}
+
+fn arg_count(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let call = call(interp, act)?;
+    Ok(interp.number(call.msg.args.len() as f64))
+}
+
+/// Evaluate the argument in the sender's locals, each time it's asked for.
+fn eval_arg_at(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let call = call(interp, act)?;
+    match index(interp, act, &call)? {
+        Some(index) => interp.eval_in(&call.msg.args[index], &call.sender),
+        None => Ok(interp.nil()),
+    }
+}
+
+fn eval_args(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let call = call(interp, act)?;
+    let args = call
+        .msg
+        .args
+        .iter()
+        .map(|arg| interp.eval_in(arg, &call.sender))
+        .collect::<Result<_, _>>()?;
+    Ok(interp.list(args))
+}
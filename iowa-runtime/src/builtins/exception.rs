@@ -0,0 +1,106 @@
+//! The methods of `Exception`, the errors raised by the code and by the runtime.
+
+use std::collections::HashSet;
+
+use crate::error::RuntimeError;
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Data, ObjectRef};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("raise", raise),
+    ("pass", pass),
+    ("catch", catch),
+    ("stack", stack),
+];
+
+/// The methods of `nil`, which `try` returns when nothing was raised.
+pub(super) const NIL_METHODS: &[(&str, NativeFn)] = &[("catch", ignore), ("pass", ignore)];
+
+/// Raise a clone of the receiver with the error.
+fn raise(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let exception = ObjectRef::new(vec![act.target.clone()], Data::None);
+    let error = interp.arg(act, 0)?;
+    exception.set_slot("error", error.clone());
+    Err(raise_error(interp, act, exception, &error)?)
+}
+
+fn raise_error(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    exception: ObjectRef,
+    error: &ObjectRef,
+) -> Result<Stop, Stop> {
+    let message = format!(
+        "{}: {}",
+        interp.type_name(&exception),
+        interp.as_string(error)?
+    );
+    let mut err = RuntimeError::new(message, act.msg.span);
+    err.exception = Some(exception);
+    Ok(Stop::Error(err).unwind(act.msg))
+}
+
+/// Raise the exception again, with the stack trace it was caught with.
+fn pass(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let caught = match &*act.target.data() {
+        Data::Exception(err) => Some(RuntimeError::clone(err)),
+        _ => None,
+    };
+    match caught {
+        Some(mut err) => {
+            err.exception = Some(act.target.clone());
+            Err(Stop::Error(err))
+        }
+        None => {
+            let error = interp.send_name(&act.target, "error")?;
+            Err(raise_error(interp, act, act.target.clone(), &error)?)
+        }
+    }
+}
+
+/// Evaluate the last argument if the exception inherits from the first one, after setting the
+/// name of the second one to the exception in the sender's locals. Returns `nil` when it's caught,
+/// otherwise the exception for the next `catch`.
+fn catch(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let proto = interp.arg(act, 0)?;
+    if !inherits(&act.target, &proto) {
+        return Ok(act.target.clone());
+    }
+    if act.argc() > 2 {
+        let name = interp.name_arg(act, 1)?;
+        act.locals.set_slot(name, act.target.clone());
+    }
+    if act.argc() > 1 {
+        interp.arg(act, act.argc() - 1)?;
+    }
+    Ok(interp.nil())
+}
+
+/// The messages of the stack trace, from the one which raised the exception.
+fn stack(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let trace = match &*act.target.data() {
+        Data::Exception(err) => err.trace.iter().map(ToString::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let trace = trace.iter().map(|site| interp.sequence(site)).collect();
+    Ok(interp.list(trace))
+}
+
+fn ignore(interp: &mut Interpreter, _: &Activation<'_>) -> EvalResult {
+    Ok(interp.nil())
+}
+
+/// Check if the object is the proto or one of its protos is.
+fn inherits(obj: &ObjectRef, proto: &ObjectRef) -> bool {
+    let mut seen = HashSet::new();
+    let mut objects = vec![obj.clone()];
+    while let Some(obj) = objects.pop() {
+        if obj.ptr_eq(proto) {
+            return true;
+        }
+        if seen.insert(obj.id()) {
+            objects.extend(obj.protos().iter().cloned());
+        }
+    }
+    false
+}
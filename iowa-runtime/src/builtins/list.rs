@@ -0,0 +1,93 @@
+//! The methods of `List`.
+
+use std::rc::Rc;
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Data, ObjectRef};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("append", append),
+    ("at", at),
+    ("size", size),
+    ("first", first),
+    ("last", last),
+    ("foreach", foreach),
+    ("asString", as_string),
+];
+
+/// The items of the target, copied so the list can change while they're used.
+fn items(interp: &Interpreter, act: &Activation<'_>) -> Result<Vec<ObjectRef>, Stop> {
+    match &*act.target.data() {
+        Data::List(items) => Ok(items.clone()),
+        _ => Err(interp.error(act.msg, "the receiver is not a List")),
+    }
+}
+
+fn append(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    items(interp, act)?;
+    for i in 0..act.argc() {
+        let value = interp.arg(act, i)?;
+        if let Data::List(items) = &mut *act.target.data_mut() {
+            items.push(value);
+        }
+    }
+    Ok(act.target.clone())
+}
+
+fn at(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    let index = interp.number_arg(act, 0)?;
+    let item = (index >= 0.0)
+        .then(|| items.get(index as usize).cloned())
+        .flatten();
+    Ok(item.unwrap_or_else(|| interp.nil()))
+}
+
+fn size(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    Ok(interp.number(items.len() as f64))
+}
+
+fn first(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    Ok(items.first().cloned().unwrap_or_else(|| interp.nil()))
+}
+
+fn last(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    Ok(items.last().cloned().unwrap_or_else(|| interp.nil()))
+}
+
+/// `foreach(item, body)` or `foreach(index, item, body)`, the names are set in the locals.
+fn foreach(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    let (index, item, body) = match act.msg.args.len() {
+        2 => (None, interp.name_arg(act, 0)?, 1),
+        3 => (Some(interp.name_arg(act, 0)?), interp.name_arg(act, 1)?, 2),
+        _ => return Err(interp.error(act.msg, "foreach takes 2 or 3 arguments")),
+    };
+
+    let mut value = interp.nil();
+    for (i, obj) in items.into_iter().enumerate() {
+        if let Some(index) = index {
+            act.locals.set_slot(index, interp.number(i as f64));
+        }
+        act.locals.set_slot(item, obj);
+        match interp.arg(act, body) {
+            Ok(res) => value = res,
+            Err(Stop::Continue(_)) => {}
+            Err(Stop::Break(res, _)) => return Ok(res),
+            Err(stop) => return Err(stop),
+        }
+    }
+    Ok(value)
+}
+
+fn as_string(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = items(interp, act)?;
+    let strings = items
+        .iter()
+        .map(|item| interp.as_string(item))
+        .collect::<Result<Vec<Rc<str>>, _>>()?;
+    Ok(interp.sequence(&format!("list({})", strings.join(", "))))
+}
@@ -0,0 +1,160 @@
+//! The methods of `Message`, the code as objects.
+
+use iowa_parser::{Argument, Identifier, Message, MessageChain, Symbol, SymbolId};
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Data, MessageData, ObjectRef};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("name", name),
+    ("setName", set_name),
+    ("arguments", arguments),
+    ("setArguments", set_arguments),
+    ("argAt", arg_at),
+    ("argCount", arg_count),
+    ("next", next),
+    ("setNext", set_next),
+    ("doInContext", do_in_context),
+    ("code", code),
+    ("asString", code),
+];
+
+/// The chains starting at the message, split at the `;` messages.
+pub(crate) fn chains(first: &ObjectRef) -> Vec<MessageChain<'static>> {
+    let terminator = SymbolId::intern(";");
+    let mut all = Vec::new();
+    let mut chain = MessageChain::default();
+    let mut next = first.as_message();
+
+    while let Some(msg) = next {
+        if msg.symbol.id() == Some(terminator) {
+            all.push(std::mem::take(&mut chain));
+        } else {
+            let args = msg.args.iter().map(|arg| Argument::new(chains(arg)));
+            chain.push(Message::new(msg.symbol, args.collect()).with_span(msg.span));
+        }
+        next = msg.next.as_ref().and_then(ObjectRef::as_message);
+    }
+
+    all.push(chain);
+    all.retain(|chain| !chain.is_empty());
+    all
+}
+
+fn message(interp: &Interpreter, act: &Activation<'_>) -> Result<MessageData, Stop> {
+    act.target
+        .as_message()
+        .ok_or_else(|| interp.error(act.msg, "the receiver is not a Message"))
+}
+
+/// Change the message of the target.
+fn update(act: &Activation<'_>, f: impl FnOnce(&mut MessageData)) -> EvalResult {
+    if let Data::Message(msg) = &mut *act.target.data_mut() {
+        f(msg);
+    }
+    Ok(act.target.clone())
+}
+
+/// Evaluate the argument which must be a message or `nil`.
+fn message_arg(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    index: usize,
+) -> Result<ObjectRef, Stop> {
+    let value = interp.arg(act, index)?;
+    if value.as_message().is_some() || value.ptr_eq(&interp.nil()) {
+        Ok(value)
+    } else {
+        let ty = interp.type_name(&value);
+        Err(interp.error(
+            act.msg,
+            format!(
+                "argument {index} to method '{}' must be a Message, not a '{ty}'",
+                act.msg.symbol
+            ),
+        ))
+    }
+}
+
+fn name(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let msg = message(interp, act)?;
+    Ok(interp.sequence(&msg.symbol.to_string()))
+}
+
+fn set_name(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    message(interp, act)?;
+    let name = interp.sequence_arg(act, 0)?;
+    update(act, |msg| {
+        msg.symbol = Symbol::Identifier(Identifier::from(SymbolId::intern(&name)))
+    })
+}
+
+fn arguments(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let msg = message(interp, act)?;
+    Ok(interp.list(msg.args))
+}
+
+fn set_arguments(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    message(interp, act)?;
+    let list = interp.arg(act, 0)?;
+    let args = match &*list.data() {
+        Data::List(items) if items.iter().all(|arg| arg.as_message().is_some()) => {
+            Some(items.clone())
+        }
+        _ => None,
+    };
+    let Some(args) = args else {
+        return Err(interp.error(
+            act.msg,
+            "argument 0 to method 'setArguments' must be a List of Messages",
+        ));
+    };
+    update(act, |msg| msg.args = args)
+}
+
+fn arg_at(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let msg = message(interp, act)?;
+    let index = interp.number_arg(act, 0)?;
+    let arg = (index >= 0.0)
+        .then(|| msg.args.get(index as usize).cloned())
+        .flatten();
+    Ok(arg.unwrap_or_else(|| interp.nil()))
+}
+
+fn arg_count(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let msg = message(interp, act)?;
+    Ok(interp.number(msg.args.len() as f64))
+}
+
+fn next(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let msg = message(interp, act)?;
+    Ok(msg.next.unwrap_or_else(|| interp.nil()))
+}
+
+fn set_next(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    message(interp, act)?;
+    let next = message_arg(interp, act, 0)?;
+    let next = next.as_message().is_some().then_some(next);
+    update(act, |msg| msg.next = next)
+}
+
+/// Evaluate the chains starting at the message with the argument as the locals, or the sender's
+/// locals without one.
+fn do_in_context(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    message(interp, act)?;
+    let locals = match act.argc() {
+        0 => act.locals.clone(),
+        _ => interp.arg(act, 0)?,
+    };
+    interp.eval_in(&chains(&act.target), &locals)
+}
+
+/// The code of the chains starting at the message.
+fn code(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    message(interp, act)?;
+    let code: Vec<String> = chains(&act.target)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    Ok(interp.sequence(&code.join("; ")))
+}
@@ -0,0 +1,59 @@
+//! The methods of `Number`.
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("+", |interp, act| arithmetic(interp, act, |a, b| a + b)),
+    ("-", |interp, act| arithmetic(interp, act, |a, b| a - b)),
+    ("*", |interp, act| arithmetic(interp, act, |a, b| a * b)),
+    ("/", |interp, act| arithmetic(interp, act, |a, b| a / b)),
+    ("%", |interp, act| arithmetic(interp, act, |a, b| a % b)),
+    ("**", |interp, act| arithmetic(interp, act, f64::powf)),
+    ("pow", |interp, act| arithmetic(interp, act, f64::powf)),
+    ("min", |interp, act| arithmetic(interp, act, f64::min)),
+    ("max", |interp, act| arithmetic(interp, act, f64::max)),
+    ("<", |interp, act| compare(interp, act, |a, b| a < b)),
+    ("<=", |interp, act| compare(interp, act, |a, b| a <= b)),
+    (">", |interp, act| compare(interp, act, |a, b| a > b)),
+    (">=", |interp, act| compare(interp, act, |a, b| a >= b)),
+    ("floor", |interp, act| unary(interp, act, f64::floor)),
+    ("ceil", |interp, act| unary(interp, act, f64::ceil)),
+    ("round", |interp, act| unary(interp, act, f64::round)),
+    ("abs", |interp, act| unary(interp, act, f64::abs)),
+    ("sqrt", |interp, act| unary(interp, act, f64::sqrt)),
+    ("negate", |interp, act| unary(interp, act, |a| -a)),
+    ("asString", as_string),
+];
+
+/// The number of the target, which is a clone of `Number` when the method is found.
+fn target(interp: &Interpreter, act: &Activation<'_>) -> Result<f64, Stop> {
+    act.target
+        .as_number()
+        .ok_or_else(|| interp.error(act.msg, "the receiver is not a Number"))
+}
+
+fn arithmetic(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    op: fn(f64, f64) -> f64,
+) -> EvalResult {
+    let a = target(interp, act)?;
+    let b = interp.number_arg(act, 0)?;
+    Ok(interp.number(op(a, b)))
+}
+
+fn compare(interp: &mut Interpreter, act: &Activation<'_>, op: fn(f64, f64) -> bool) -> EvalResult {
+    let a = target(interp, act)?;
+    let b = interp.number_arg(act, 0)?;
+    Ok(interp.boolean(op(a, b)))
+}
+
+fn unary(interp: &mut Interpreter, act: &Activation<'_>, op: fn(f64) -> f64) -> EvalResult {
+    let a = target(interp, act)?;
+    Ok(interp.number(op(a)))
+}
+
+fn as_string(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let a = target(interp, act)?;
+    Ok(interp.sequence(&a.to_string()))
+}
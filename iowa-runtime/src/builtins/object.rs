@@ -0,0 +1,372 @@
+//! The methods of `Object`, which every object responds to.
+
+use std::io::Write;
+use std::rc::Rc;
+
+use iowa_parser::SymbolId;
+
+use super::equals;
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+use crate::object::{Block, Data, ObjectRef};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("clone", clone),
+    ("setSlot", set_slot),
+    ("newSlot", set_slot),
+    ("updateSlot", update_slot),
+    ("getSlot", get_slot),
+    ("hasSlot", has_slot),
+    ("slotNames", slot_names),
+    ("protos", protos),
+    ("appendProto", append_proto),
+    ("setProto", set_proto),
+    ("method", method),
+    ("block", block),
+    ("message", message),
+    ("if", if_),
+    ("while", while_),
+    ("loop", loop_),
+    ("break", break_),
+    ("continue", continue_),
+    ("return", return_),
+    ("try", try_),
+    ("ensure", ensure),
+    ("self", self_),
+    ("do", do_),
+    ("list", list),
+    ("print", print),
+    ("println", println),
+    ("write", write),
+    ("writeln", writeln),
+    ("asString", as_string),
+    ("==", eq),
+    ("!=", ne),
+    ("not", not),
+    ("isNil", is_nil),
+    ("and", and),
+    ("&&", and),
+    ("or", or),
+    ("||", or),
+    ("-", negate),
+];
+
+fn clone(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let obj = act.target.clone_object();
+    if obj.lookup(SymbolId::intern("init")).is_some() {
+        interp.send_name(&obj, "init")?;
+    }
+    Ok(obj)
+}
+
+/// Assign the slot. A capitalised slot names the type of a plain object without its own, like a
+/// fresh clone.
+fn set_slot(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let name = interp.sequence_arg(act, 0)?;
+    let value = interp.arg(act, 1)?;
+    let type_ = SymbolId::intern("type");
+    if name.starts_with(|c: char| c.is_ascii_uppercase())
+        && matches!(*value.data(), Data::None)
+        && !value.slots().contains_key(&type_)
+    {
+        value.set_slot(type_, interp.sequence(&name));
+    }
+    act.target.set_slot(&*name, value.clone());
+    Ok(value)
+}
+
+/// Assign an existing slot. The locals of an activation pass the slots they don't have to the
+/// scope of the block or to the receiver of the method.
+fn update_slot(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let name = interp.sequence_arg(act, 0)?;
+    let Some(id) = SymbolId::get(&name).filter(|&id| act.target.lookup(id).is_some()) else {
+        return Err(interp.error(
+            act.msg,
+            format!("slot '{name}' not found, assign with ':=' to create it"),
+        ));
+    };
+    let value = interp.arg(act, 1)?;
+
+    let mut holder = act.target.clone();
+    while holder.local_slot(id).is_none() {
+        let next = match &*holder.data() {
+            Data::Locals(_) => holder.protos().first().cloned(),
+            _ => None,
+        };
+        match next {
+            Some(next) => holder = next,
+            None => break,
+        }
+    }
+    holder.set_slot(id, value.clone());
+    Ok(value)
+}
+
+fn get_slot(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let name = interp.sequence_arg(act, 0)?;
+    Ok(SymbolId::get(&name)
+        .and_then(|id| act.target.lookup(id))
+        .map_or_else(|| interp.nil(), |(value, _)| value))
+}
+
+fn has_slot(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let name = interp.sequence_arg(act, 0)?;
+    let found = SymbolId::get(&name).and_then(|id| act.target.lookup(id));
+    Ok(interp.boolean(found.is_some()))
+}
+
+fn slot_names(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let mut names: Vec<_> = act
+        .target
+        .slots()
+        .keys()
+        .map(|name| name.as_str())
+        .collect();
+    names.sort_unstable();
+    let names = names
+        .into_iter()
+        .map(|name| interp.sequence(name))
+        .collect();
+    Ok(interp.list(names))
+}
+
+fn protos(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let protos = act.target.protos().clone();
+    Ok(interp.list(protos))
+}
+
+fn append_proto(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let proto = interp.arg(act, 0)?;
+    act.target.append_proto(proto);
+    Ok(act.target.clone())
+}
+
+fn set_proto(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let proto = interp.arg(act, 0)?;
+    act.target.set_protos(vec![proto]);
+    Ok(act.target.clone())
+}
+
+/// Create a method or a block from the arguments, the last one is the body.
+fn make_block(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    scope: Option<ObjectRef>,
+) -> EvalResult {
+    let params = (0..act.msg.args.len().saturating_sub(1))
+        .map(|i| interp.name_arg(act, i))
+        .collect::<Result<_, _>>()?;
+    let body = act
+        .msg
+        .args
+        .last()
+        .map_or_else(Vec::new, |arg| arg.to_vec());
+    Ok(interp.block(Block::new(params, body, scope)))
+}
+
+fn method(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    make_block(interp, act, None)
+}
+
+fn block(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    make_block(interp, act, Some(act.locals.clone()))
+}
+
+/// The unevaluated argument as a `Message`.
+fn message(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(act
+        .msg
+        .args
+        .first()
+        .map_or_else(|| interp.nil(), |arg| interp.message(arg)))
+}
+
+fn if_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let cond = interp.arg(act, 0)?;
+    let cond = interp.is_true(&cond);
+    let branch = if cond { 1 } else { 2 };
+    if act.argc() > branch {
+        interp.arg(act, branch)
+    } else {
+        Ok(interp.boolean(cond))
+    }
+}
+
+/// Run the body of a loop, returning the value to break with.
+fn iteration(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    body: usize,
+) -> Result<Option<ObjectRef>, Stop> {
+    match interp.arg(act, body) {
+        Ok(_) | Err(Stop::Continue(_)) => Ok(None),
+        Err(Stop::Break(value, _)) => Ok(Some(value)),
+        Err(stop) => Err(stop),
+    }
+}
+
+fn while_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    loop {
+        let cond = interp.arg(act, 0)?;
+        if !interp.is_true(&cond) {
+            return Ok(interp.nil());
+        }
+        if let Some(value) = iteration(interp, act, 1)? {
+            return Ok(value);
+        }
+    }
+}
+
+fn loop_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    loop {
+        if let Some(value) = iteration(interp, act, 0)? {
+            return Ok(value);
+        }
+    }
+}
+
+fn break_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Err(Stop::Break(interp.arg(act, 0)?, act.msg.span))
+}
+
+fn continue_(_: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Err(Stop::Continue(act.msg.span))
+}
+
+fn return_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Err(Stop::Return(interp.arg(act, 0)?, act.locals.home()))
+}
+
+/// Evaluate the argument, returning the exception it raised or `nil`.
+fn try_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    match interp.arg(act, 0) {
+        Ok(_) => Ok(interp.nil()),
+        Err(Stop::Error(err)) => Ok(interp.exception(err)),
+        Err(stop) => Err(stop),
+    }
+}
+
+/// Evaluate the first argument, then the second one even if the first one stopped.
+fn ensure(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let res = interp.arg(act, 0);
+    interp.arg(act, 1)?;
+    res
+}
+
+fn self_(_: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(act.target.clone())
+}
+
+/// Evaluate the argument in the context of the target.
+fn do_(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    if let Some(arg) = act.msg.args.first() {
+        interp.eval_in(arg, &act.target)?;
+    }
+    Ok(act.target.clone())
+}
+
+fn list(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let items = (0..act.argc())
+        .map(|i| interp.arg(act, i))
+        .collect::<Result<_, _>>()?;
+    Ok(interp.list(items))
+}
+
+/// Write the strings to the output.
+fn output(interp: &mut Interpreter, act: &Activation<'_>, strings: &[Rc<str>]) -> Result<(), Stop> {
+    strings
+        .iter()
+        .try_for_each(|s| interp.output.write_all(s.as_bytes()))
+        .map_err(|err| interp.error(act.msg, format!("failed to write the output: {err}")))
+}
+
+fn print(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let s = interp.as_string(&act.target)?;
+    output(interp, act, &[s])?;
+    Ok(act.target.clone())
+}
+
+fn println(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let s = interp.as_string(&act.target)?;
+    output(interp, act, &[s, "\n".into()])?;
+    Ok(act.target.clone())
+}
+
+/// Evaluate the arguments and convert them to strings.
+fn strings(interp: &mut Interpreter, act: &Activation<'_>) -> Result<Vec<Rc<str>>, Stop> {
+    (0..act.argc())
+        .map(|i| {
+            let value = interp.arg(act, i)?;
+            interp.as_string(&value)
+        })
+        .collect()
+}
+
+fn write(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let strings = strings(interp, act)?;
+    output(interp, act, &strings)?;
+    Ok(interp.nil())
+}
+
+fn writeln(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let mut strings = strings(interp, act)?;
+    strings.push("\n".into());
+    output(interp, act, &strings)?;
+    Ok(interp.nil())
+}
+
+fn as_string(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let name = interp.type_name(&act.target);
+    Ok(interp.sequence(&format!("{name}_{:#x}", act.target.id())))
+}
+
+pub(super) fn nil_as_string(interp: &mut Interpreter, _: &Activation<'_>) -> EvalResult {
+    Ok(interp.sequence("nil"))
+}
+
+pub(super) fn true_as_string(interp: &mut Interpreter, _: &Activation<'_>) -> EvalResult {
+    Ok(interp.sequence("true"))
+}
+
+pub(super) fn false_as_string(interp: &mut Interpreter, _: &Activation<'_>) -> EvalResult {
+    Ok(interp.sequence("false"))
+}
+
+fn eq(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let other = interp.arg(act, 0)?;
+    Ok(interp.boolean(equals(&act.target, &other)))
+}
+
+fn ne(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let other = interp.arg(act, 0)?;
+    Ok(interp.boolean(!equals(&act.target, &other)))
+}
+
+fn not(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(interp.boolean(!interp.is_true(&act.target)))
+}
+
+fn is_nil(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(interp.boolean(act.target.ptr_eq(&interp.nil())))
+}
+
+fn and(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    if !interp.is_true(&act.target) {
+        return Ok(interp.boolean(false));
+    }
+    let other = interp.arg(act, 0)?;
+    Ok(interp.boolean(interp.is_true(&other)))
+}
+
+fn or(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    if interp.is_true(&act.target) {
+        return Ok(interp.boolean(true));
+    }
+    let other = interp.arg(act, 0)?;
+    Ok(interp.boolean(interp.is_true(&other)))
+}
+
+/// The prefix minus, `- x` is sent to the locals with `x` as the argument.
+fn negate(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let num = interp.number_arg(act, 0)?;
+    Ok(interp.number(-num))
+}
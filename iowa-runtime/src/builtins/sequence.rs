@@ -0,0 +1,56 @@
+//! The methods of `Sequence`, the strings.
+
+use std::rc::Rc;
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[
+    ("asString", as_string),
+    ("..", concat),
+    ("size", size),
+    ("asNumber", as_number),
+    ("<", |interp, act| compare(interp, act, |a, b| a < b)),
+    ("<=", |interp, act| compare(interp, act, |a, b| a <= b)),
+    (">", |interp, act| compare(interp, act, |a, b| a > b)),
+    (">=", |interp, act| compare(interp, act, |a, b| a >= b)),
+];
+
+fn target(interp: &Interpreter, act: &Activation<'_>) -> Result<Rc<str>, Stop> {
+    act.target
+        .as_str()
+        .ok_or_else(|| interp.error(act.msg, "the receiver is not a Sequence"))
+}
+
+fn as_string(_: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    Ok(act.target.clone())
+}
+
+/// Append the string representation of the argument.
+fn concat(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let a = target(interp, act)?;
+    let b = interp.arg(act, 0)?;
+    let b = interp.as_string(&b)?;
+    Ok(interp.sequence(&format!("{a}{b}")))
+}
+
+fn size(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let a = target(interp, act)?;
+    Ok(interp.number(a.chars().count() as f64))
+}
+
+fn as_number(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let a = target(interp, act)?;
+    Ok(a.trim()
+        .parse()
+        .map_or_else(|_| interp.nil(), |num| interp.number(num)))
+}
+
+fn compare(
+    interp: &mut Interpreter,
+    act: &Activation<'_>,
+    op: fn(&str, &str) -> bool,
+) -> EvalResult {
+    let a = target(interp, act)?;
+    let b = interp.sequence_arg(act, 0)?;
+    Ok(interp.boolean(op(&a, &b)))
+}
@@ -0,0 +1,14 @@
+//! The methods of `System`.
+
+use crate::interpreter::{Activation, EvalResult, Interpreter, NativeFn, Stop};
+
+pub(super) const METHODS: &[(&str, NativeFn)] = &[("exit", exit)];
+
+/// `exit` or `exit(status)`, which stops the program with the status, 0 by default.
+fn exit(interp: &mut Interpreter, act: &Activation<'_>) -> EvalResult {
+    let status = match act.argc() {
+        0 => 0.0,
+        _ => interp.number_arg(act, 0)?,
+    };
+    Err(Stop::Exit(status as i32))
+}
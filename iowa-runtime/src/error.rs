@@ -0,0 +1,185 @@
+//! Runtime errors.
+
+use std::fmt;
+
+use iowa_parser::{ParseError, Span};
+
+use crate::object::ObjectRef;
+
+/// An error raised while running the code.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    /// The description of the error.
+    pub message: String,
+    /// The location of the message which raised the error.
+    pub span: Span,
+    /// The message which raised the error, then the messages of the activations it left, the
+    /// innermost first.
+    pub trace: Vec<CallSite>,
+    /// The `Exception` raised by the code, or the one the error was caught as.
+    pub exception: Option<ObjectRef>,
+}
+
+impl RuntimeError {
+    pub(crate) fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            trace: Vec::new(),
+            exception: None,
+        }
+    }
+
+    /// Display the error with its stack trace, the locations in the file of the name.
+    pub fn report<'a>(&'a self, file: &'a str) -> Report<'a> {
+        Report { err: self, file }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.message)?;
+        if !self.span.is_dummy() {
+            write!(f, "\n --> {}:{}", self.span.line, self.span.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A message in the stack trace of an error.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CallSite {
+    /// The name of the message.
+    pub name: String,
+    /// The location of the message.
+    pub span: Span,
+}
+
+impl fmt::Display for CallSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.span.is_dummy() {
+            write!(f, " at {}:{}", self.span.line, self.span.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// A [`RuntimeError`] with its stack trace.
+///
+/// ```text
+/// error: Exception: out of range
+///     at raise (main.io:2:18)
+///     at check (main.io:5:1)
+/// ```
+///
+/// A message repeated by a recursion is shown once with the number of the other times.
+pub struct Report<'a> {
+    err: &'a RuntimeError,
+    file: &'a str,
+}
+
+impl Report<'_> {
+    fn site(&self, f: &mut fmt::Formatter<'_>, site: &CallSite) -> fmt::Result {
+        write!(f, "\n    at {}", site.name)?;
+        if !site.span.is_dummy() {
+            write!(
+                f,
+                " ({}:{}:{})",
+                self.file, site.span.line, site.span.column
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.err.message)?;
+        if self.err.trace.is_empty() && !self.err.span.is_dummy() {
+            let span = self.err.span;
+            write!(f, "\n --> {}:{}:{}", self.file, span.line, span.column)?;
+        }
+        let mut sites = self.err.trace.iter().peekable();
+        while let Some(site) = sites.next() {
+            self.site(f, site)?;
+            let mut repeated = 0;
+            while sites.next_if_eq(&site).is_some() {
+                repeated += 1;
+            }
+            match repeated {
+                0 => {}
+                1 => self.site(f, site)?,
+                _ => write!(f, "\n    ... repeated {repeated} more times")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error of parsing or running the code.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The code isn't valid.
+    Parse(ParseError),
+    /// The code raised an error.
+    Runtime(RuntimeError),
+    /// The code called `System exit` with the status.
+    Exit(i32),
+}
+
+impl Error {
+    /// Display the error with the locations in the file of the name.
+    pub fn report<'a>(&'a self, file: &'a str) -> ErrorReport<'a> {
+        ErrorReport { err: self, file }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(err: RuntimeError) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => err.fmt(f),
+            Self::Runtime(err) => err.fmt(f),
+            Self::Exit(status) => write!(f, "exited with status {status}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An [`Error`] with the name of the file, the [`ParseReport`](iowa_parser::ParseReport) or the
+/// [`Report`] of the error.
+pub struct ErrorReport<'a> {
+    err: &'a Error,
+    file: &'a str,
+}
+
+impl fmt::Display for ErrorReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.err {
+            Error::Parse(err) => err.report(self.file).fmt(f),
+            Error::Runtime(err) => err.report(self.file).fmt(f),
+            Error::Exit(_) => self.err.fmt(f),
+        }
+    }
+}
+
+/// The status of the process for the status of `System exit`, a failure for the ones out of
+/// `0..=255`.
+pub fn exit_status(status: i32) -> u8 {
+    u8::try_from(status).unwrap_or(1)
+}
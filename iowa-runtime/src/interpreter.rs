@@ -0,0 +1,911 @@
+//! The evaluator of the message chains.
+
+use std::cell::OnceCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use iowa_parser::{Identifier, Message, MessageChain, Number, Span, Symbol, SymbolId};
+
+use crate::builtins;
+use crate::error::{CallSite, Error, RuntimeError};
+use crate::object::{Block, Call, Data, Locals, MessageData, ObjectRef};
+
+/// The signature of the methods implemented by the runtime.
+pub(crate) type NativeFn = fn(&mut Interpreter, &Activation<'_>) -> EvalResult;
+
+/// The result of evaluating a message.
+pub type EvalResult = Result<ObjectRef, Stop>;
+
+/// The reason the evaluation stopped before the end of the code.
+#[derive(Debug)]
+pub enum Stop {
+    /// `return` from the activation with the locals, or from the top-level code for `None`.
+    Return(ObjectRef, Option<ObjectRef>),
+    /// `break` out of the loop, from the location of the message.
+    Break(ObjectRef, Span),
+    /// `continue` with the next iteration of the loop, from the location of the message.
+    Continue(Span),
+    /// An error.
+    Error(RuntimeError),
+    /// `System exit` with the status.
+    Exit(i32),
+}
+
+impl Stop {
+    /// Add the message of the activation an error leaves to its stack trace.
+    pub fn unwind(self, msg: &Message<'_>) -> Self {
+        match self {
+            Self::Error(mut err) => {
+                err.trace.push(CallSite {
+                    name: msg.symbol.to_string(),
+                    span: msg.span,
+                });
+                Self::Error(err)
+            }
+            stop => stop,
+        }
+    }
+
+    /// Turn a `break` or a `continue` which leaves the activation of a method, or the top-level
+    /// code, into an error.
+    pub fn outside_loop(self) -> Self {
+        let (name, span) = match self {
+            Self::Break(_, span) => ("break", span),
+            Self::Continue(span) => ("continue", span),
+            stop => return stop,
+        };
+        let mut err = RuntimeError::new(format!("'{name}' outside of a loop"), span);
+        err.trace.push(CallSite {
+            name: name.to_string(),
+            span,
+        });
+        Self::Error(err)
+    }
+}
+
+/// A message sent to a method implemented by the runtime.
+#[derive(Debug)]
+pub struct Activation<'m> {
+    /// The receiver of the message.
+    pub target: ObjectRef,
+    /// The context of the sender, where the arguments are evaluated.
+    pub locals: ObjectRef,
+    /// The message.
+    pub msg: &'m Message<'static>,
+    /// The messages following it in the chain.
+    pub next: &'m [Message<'static>],
+    /// The arguments if they're evaluated already, then the ones of the message are ignored.
+    pub args: Option<&'m [ObjectRef]>,
+}
+
+impl Activation<'_> {
+    /// The number of the arguments.
+    pub fn argc(&self) -> usize {
+        self.args.map_or(self.msg.args.len(), <[_]>::len)
+    }
+}
+
+/// The prototypes of the built-in objects.
+#[derive(Debug)]
+pub(crate) struct Protos {
+    pub(crate) object: ObjectRef,
+    pub(crate) number: ObjectRef,
+    pub(crate) sequence: ObjectRef,
+    pub(crate) list: ObjectRef,
+    pub(crate) block: ObjectRef,
+    pub(crate) nil: ObjectRef,
+    pub(crate) true_: ObjectRef,
+    pub(crate) false_: ObjectRef,
+    pub(crate) system: ObjectRef,
+    pub(crate) exception: ObjectRef,
+    pub(crate) message: ObjectRef,
+    pub(crate) call: ObjectRef,
+}
+
+/// Io interpreter.
+///
+/// Evaluates the parsed code directly, starting in the context of the `Lobby`.
+///
+/// Each activation of a method or a block runs on the stack of the thread, so at most
+/// [`max_depth`](Self::max_depth) of them can run at once, the next one raises a stack overflow.
+/// The thread needs a stack deep enough for them.
+pub struct Interpreter {
+    pub(crate) lobby: ObjectRef,
+    pub(crate) protos: Protos,
+    pub(crate) output: Box<dyn Write>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// The default number of the activations which can run at once.
+    pub const MAX_DEPTH: usize = 10_000;
+
+    /// Create an interpreter printing to the standard output.
+    pub fn new() -> Self {
+        Self::with_output(std::io::stdout())
+    }
+
+    /// Create an interpreter printing to the output.
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        let (lobby, protos) = builtins::boot();
+        Self {
+            lobby,
+            protos,
+            output: Box::new(output),
+            depth: 0,
+            max_depth: Self::MAX_DEPTH,
+        }
+    }
+
+    /// The number of the activations which can run at once.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Change the number of the activations which can run at once.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// The number of the activations running.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Count an activation by the message, which must be followed by [`leave`](Self::leave) if it
+    /// succeeds. Raises a stack overflow if too many are running.
+    pub fn enter(&mut self, msg: &Message<'_>) -> Result<(), Stop> {
+        if self.depth >= self.max_depth {
+            return Err(self.stack_overflow(msg));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Count the end of an activation.
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// The error of an activation by the message past the [`max_depth`](Self::max_depth).
+    pub fn stack_overflow(&self, msg: &Message<'_>) -> Stop {
+        self.error(msg, "stack overflow")
+    }
+
+    /// The `Lobby`, the context of the top-level code.
+    pub fn lobby(&self) -> &ObjectRef {
+        &self.lobby
+    }
+
+    /// Parse and evaluate the code, returning the value of the last chain.
+    pub fn eval(&mut self, source: &str) -> Result<ObjectRef, Error> {
+        let chains: Vec<_> = iowa_parser::parse(source)?
+            .into_iter()
+            .map(MessageChain::into_owned)
+            .collect();
+        self.eval_chains(&chains)
+    }
+
+    /// Evaluate the chains in the context of the `Lobby`.
+    pub fn eval_chains(&mut self, chains: &[MessageChain<'static>]) -> Result<ObjectRef, Error> {
+        let lobby = self.lobby.clone();
+        match self.eval_in(chains, &lobby).map_err(Stop::outside_loop) {
+            Ok(value) | Err(Stop::Return(value, _)) => Ok(value),
+            Err(Stop::Break(..) | Stop::Continue(_)) => unreachable!("they're errors now"),
+            Err(Stop::Error(err)) => Err(err.into()),
+            Err(Stop::Exit(status)) => Err(Error::Exit(status)),
+        }
+    }
+
+    /// Set `System args`, the arguments of the program.
+    pub fn set_args<S: AsRef<str>>(&self, args: impl IntoIterator<Item = S>) {
+        let args = args
+            .into_iter()
+            .map(|arg| self.sequence(arg.as_ref()))
+            .collect();
+        self.protos.system.set_slot("args", self.list(args));
+    }
+
+    /// Evaluate the chains in the context, returning the value of the last one.
+    pub(crate) fn eval_in(
+        &mut self,
+        chains: &[MessageChain<'static>],
+        locals: &ObjectRef,
+    ) -> EvalResult {
+        let mut value = self.nil();
+        for chain in chains {
+            value = self.eval_chain(chain, locals)?;
+        }
+        Ok(value)
+    }
+
+    /// Send the messages of the chain, each to the result of the previous one.
+    fn eval_chain(&mut self, chain: &MessageChain<'static>, locals: &ObjectRef) -> EvalResult {
+        let mut target = locals.clone();
+        for (i, msg) in chain.iter().enumerate() {
+            target = self.send_in_chain(&target, msg, &chain[i + 1..], locals)?;
+        }
+        Ok(target)
+    }
+
+    /// Send the message to the target, the arguments are evaluated in the locals.
+    pub fn send(
+        &mut self,
+        target: &ObjectRef,
+        msg: &Message<'static>,
+        locals: &ObjectRef,
+    ) -> EvalResult {
+        self.send_in_chain(target, msg, &[], locals)
+    }
+
+    /// Send the message followed by the others in its chain, which `call message` refers to.
+    pub fn send_in_chain(
+        &mut self,
+        target: &ObjectRef,
+        msg: &Message<'static>,
+        next: &[Message<'static>],
+        locals: &ObjectRef,
+    ) -> EvalResult {
+        let name = match &msg.symbol {
+            Symbol::Number(Number::Decimal(num)) => return Ok(self.number(*num)),
+            Symbol::Number(Number::Hex(num)) => return Ok(self.number(*num as f64)),
+            Symbol::Quote(quote) => return Ok(self.sequence(quote.as_str())),
+            Symbol::Identifier(ident) => ident.id(),
+            Symbol::Operator(op) => op.id(),
+            Symbol::Error => return Err(self.error(msg, "the code has a syntax error")),
+        };
+
+        let Some((slot, holder)) = target.lookup(name) else {
+            return Err(self.does_not_respond(target, msg));
+        };
+
+        let act = Activation {
+            target: self.receiver(target, &slot, &holder),
+            locals: locals.clone(),
+            msg,
+            next,
+            args: None,
+        };
+        self.activate(&slot, &act)
+    }
+
+    /// Run the slot value if it's a method, otherwise return it.
+    pub fn activate(&mut self, slot: &ObjectRef, act: &Activation<'_>) -> EvalResult {
+        let data = slot.data();
+        match &*data {
+            Data::Native(native) => {
+                let func = native.func;
+                drop(data);
+                func(self, act)
+            }
+            Data::Block(block) if block.scope.is_none() => {
+                let block = block.clone();
+                drop(data);
+                self.call_block(&block, act)
+            }
+            _ => Ok(slot.clone()),
+        }
+    }
+
+    /// Run the method or the block with the arguments of the message.
+    pub(crate) fn call_block(&mut self, block: &Rc<Block>, act: &Activation<'_>) -> EvalResult {
+        let locals = self.locals(block, &act.target, &act.locals, act.msg, act.next);
+        for (i, &param) in block.params.iter().enumerate() {
+            let value = self.arg(act, i)?;
+            locals.set_slot(param, value);
+        }
+
+        self.enter(act.msg)?;
+        let res = self.eval_in(&block.body, &locals);
+        self.leave();
+        locals.end_activation();
+        match res {
+            Err(Stop::Return(value, Some(home))) if home.ptr_eq(&locals) => Ok(value),
+            Err(stop) if block.scope.is_none() => Err(stop.outside_loop().unwind(act.msg)),
+            res => res.map_err(|stop| stop.unwind(act.msg)),
+        }
+    }
+
+    /// The receiver of a message sent to the target and answered by the slot found in the holder.
+    ///
+    /// The locals of an activation forward the messages to their `self`, like Io's
+    /// `localsForward`, so the callees don't see the caller's locals. The slots of the locals, of
+    /// the scopes of a block and the built-in methods of `Object`, like `setSlot` or `if`, are
+    /// still sent to the locals.
+    pub fn receiver(&self, target: &ObjectRef, slot: &ObjectRef, holder: &ObjectRef) -> ObjectRef {
+        let forward = matches!(&*target.data(), Data::Locals(_))
+            && !matches!(&*holder.data(), Data::Locals(_))
+            && !(holder.ptr_eq(&self.protos.object) && matches!(&*slot.data(), Data::Native(_)));
+        let receiver = forward
+            .then(|| target.local_slot(SymbolId::intern("self")))
+            .flatten();
+        receiver.unwrap_or_else(|| target.clone())
+    }
+
+    /// Create the locals of an activation of the method or the block by the message, without the
+    /// arguments.
+    ///
+    /// The locals of a method inherit from the target, which is also their `self`. The locals of
+    /// a block inherit from its scope and have the `self` of the scope, the target is the block
+    /// itself. If the body refers to `call`, it's set to the [`Call`] with the sender's locals and
+    /// the message followed by the next ones of its chain.
+    pub fn locals(
+        &self,
+        block: &Block,
+        target: &ObjectRef,
+        sender: &ObjectRef,
+        msg: &Message<'static>,
+        next: &[Message<'static>],
+    ) -> ObjectRef {
+        let (proto, target) = match &block.scope {
+            Some(scope) => {
+                let target = match &*scope.data() {
+                    Data::Locals(_) => scope.local_slot(SymbolId::intern("self")),
+                    _ => None,
+                };
+                (scope.clone(), target.unwrap_or_else(|| scope.clone()))
+            }
+            None => (target.clone(), target.clone()),
+        };
+        let state = Locals {
+            scope: block.scope.clone(),
+            running: true,
+        };
+        let locals = ObjectRef::new(vec![proto], Data::Locals(state));
+        locals.set_slot("self", target.clone());
+        if block.uses_call() {
+            let call = Call {
+                sender: sender.clone(),
+                target,
+                msg: msg.clone(),
+                next: next.to_vec(),
+                message: OnceCell::new(),
+            };
+            let call = ObjectRef::new(vec![self.protos.call.clone()], Data::Call(Rc::new(call)));
+            locals.set_slot("call", call);
+        }
+        locals
+    }
+
+    /// Send the message without arguments.
+    pub(crate) fn send_name(&mut self, target: &ObjectRef, name: &str) -> EvalResult {
+        let msg = Message::new(
+            Symbol::Identifier(Identifier::from(SymbolId::intern(name))),
+            Vec::new(),
+        );
+        self.send(target, &msg, target)
+    }
+
+    /// Evaluate the argument of the message, `nil` if it's missing.
+    pub(crate) fn arg(&mut self, act: &Activation<'_>, index: usize) -> EvalResult {
+        if let Some(args) = act.args {
+            return Ok(args.get(index).cloned().unwrap_or_else(|| self.nil()));
+        }
+        match act.msg.args.get(index) {
+            Some(arg) => self.eval_in(arg, &act.locals),
+            None => Ok(self.nil()),
+        }
+    }
+
+    /// Evaluate the argument which must be a number.
+    pub(crate) fn number_arg(&mut self, act: &Activation<'_>, index: usize) -> Result<f64, Stop> {
+        let value = self.arg(act, index)?;
+        value
+            .as_number()
+            .ok_or_else(|| self.arg_error(act, index, "Number", &value))
+    }
+
+    /// Evaluate the argument which must be a sequence.
+    pub(crate) fn sequence_arg(
+        &mut self,
+        act: &Activation<'_>,
+        index: usize,
+    ) -> Result<Rc<str>, Stop> {
+        let value = self.arg(act, index)?;
+        value
+            .as_str()
+            .ok_or_else(|| self.arg_error(act, index, "Sequence", &value))
+    }
+
+    /// The name of the argument, which must be a plain identifier.
+    pub(crate) fn name_arg(
+        &mut self,
+        act: &Activation<'_>,
+        index: usize,
+    ) -> Result<SymbolId, Stop> {
+        let name = act
+            .msg
+            .args
+            .get(index)
+            .filter(|_| act.args.is_none())
+            .and_then(|arg| match arg.as_slice() {
+                [chain] => match chain.as_slice() {
+                    [msg] if msg.args.is_empty() => msg.symbol.id(),
+                    _ => None,
+                },
+                _ => None,
+            });
+        name.ok_or_else(|| {
+            self.error(
+                act.msg,
+                format!(
+                    "argument {index} to method '{}' must be a name",
+                    act.msg.symbol
+                ),
+            )
+        })
+    }
+
+    fn arg_error(
+        &self,
+        act: &Activation<'_>,
+        index: usize,
+        expected: &str,
+        value: &ObjectRef,
+    ) -> Stop {
+        self.error(
+            act.msg,
+            format!(
+                "argument {index} to method '{}' must be a {expected}, not a '{}'",
+                act.msg.symbol,
+                self.type_name(value)
+            ),
+        )
+    }
+
+    /// The error of a message which isn't found in the target.
+    pub fn does_not_respond(&self, target: &ObjectRef, msg: &Message<'_>) -> Stop {
+        let name = msg.symbol.to_string();
+        let message = format!("{} does not respond to '{name}'", self.type_name(target));
+        self.error(msg, message)
+    }
+
+    /// An error at the message.
+    pub fn error(&self, msg: &Message<'_>, message: impl Into<String>) -> Stop {
+        Stop::Error(RuntimeError::new(message, msg.span)).unwind(msg)
+    }
+
+    /// The value of the `type` slot.
+    pub(crate) fn type_name(&self, obj: &ObjectRef) -> String {
+        obj.lookup(SymbolId::intern("type"))
+            .and_then(|(value, _)| value.as_str())
+            .map_or_else(|| "Object".to_string(), |name| name.to_string())
+    }
+
+    /// The string representation, from the `asString` slot.
+    pub fn as_string(&mut self, obj: &ObjectRef) -> Result<Rc<str>, Stop> {
+        let value = self.send_name(obj, "asString")?;
+        Ok(value
+            .as_str()
+            .unwrap_or_else(|| format!("{}_{:#x}", self.type_name(obj), obj.id()).into()))
+    }
+
+    /// Check if the slot is the built-in method of the name, whose behavior the compiled code can
+    /// assume.
+    pub fn is_builtin(&self, slot: &ObjectRef, name: SymbolId) -> bool {
+        matches!(&*slot.data(), Data::Native(native) if native.name == name.as_str())
+    }
+
+    /// Check if the object counts as true, everything except `false` and `nil` does.
+    pub fn is_true(&self, obj: &ObjectRef) -> bool {
+        !obj.ptr_eq(&self.protos.false_) && !obj.ptr_eq(&self.protos.nil)
+    }
+
+    /// The `nil` object.
+    pub fn nil(&self) -> ObjectRef {
+        self.protos.nil.clone()
+    }
+
+    /// The `true` or the `false` object.
+    pub fn boolean(&self, value: bool) -> ObjectRef {
+        if value {
+            self.protos.true_.clone()
+        } else {
+            self.protos.false_.clone()
+        }
+    }
+
+    /// Create a number.
+    pub fn number(&self, num: f64) -> ObjectRef {
+        ObjectRef::new(vec![self.protos.number.clone()], Data::Number(num))
+    }
+
+    /// Create a sequence.
+    pub fn sequence(&self, seq: &str) -> ObjectRef {
+        ObjectRef::new(
+            vec![self.protos.sequence.clone()],
+            Data::Sequence(seq.into()),
+        )
+    }
+
+    /// Create a list.
+    pub fn list(&self, items: Vec<ObjectRef>) -> ObjectRef {
+        ObjectRef::new(vec![self.protos.list.clone()], Data::List(items))
+    }
+
+    /// Create a method or a block.
+    pub fn block(&self, block: Block) -> ObjectRef {
+        ObjectRef::new(vec![self.protos.block.clone()], Data::Block(Rc::new(block)))
+    }
+
+    /// The `Exception` the error was raised with, or a new one with its message as the `error`.
+    /// The exception keeps the error to be passed on.
+    pub(crate) fn exception(&self, mut err: RuntimeError) -> ObjectRef {
+        let exception = err.exception.take().unwrap_or_else(|| {
+            let exception = ObjectRef::new(vec![self.protos.exception.clone()], Data::None);
+            exception.set_slot("error", self.sequence(&err.message));
+            exception
+        });
+        *exception.data_mut() = Data::Exception(Rc::new(err));
+        exception
+    }
+
+    /// Create the `Message` objects of the chains, returning the first one or `nil` if there are
+    /// none.
+    pub fn message(&self, chains: &[MessageChain<'static>]) -> ObjectRef {
+        let mut next = None;
+        for (i, chain) in chains.iter().enumerate().rev() {
+            for msg in chain.iter().rev() {
+                next = Some(self.message_object(msg, next));
+            }
+            if i > 0 {
+                let symbol = Symbol::Identifier(Identifier::from(";"));
+                let terminator = Message::new(symbol, Vec::new()).with_span(chain.span());
+                next = Some(self.message_object(&terminator, next));
+            }
+        }
+        next.unwrap_or_else(|| self.nil())
+    }
+
+    /// Create the `Message` object of the message followed by the next ones of its chain.
+    pub(crate) fn chain_object(
+        &self,
+        msg: &Message<'static>,
+        next: &[Message<'static>],
+    ) -> ObjectRef {
+        let next = next
+            .iter()
+            .rev()
+            .fold(None, |next, msg| Some(self.message_object(msg, next)));
+        self.message_object(msg, next)
+    }
+
+    /// Create the `Message` object of a single message followed by the next one.
+    pub(crate) fn message_object(
+        &self,
+        msg: &Message<'static>,
+        next: Option<ObjectRef>,
+    ) -> ObjectRef {
+        let data = MessageData {
+            symbol: msg.symbol.clone(),
+            args: msg.args.iter().map(|arg| self.message(arg)).collect(),
+            next,
+            span: msg.span,
+        };
+        ObjectRef::new(vec![self.protos.message.clone()], Data::Message(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> String {
+        let mut interp = Interpreter::with_output(std::io::sink());
+        let value = interp.eval(source).unwrap();
+        interp.as_string(&value).unwrap().to_string()
+    }
+
+    fn eval_err(source: &str) -> String {
+        let mut interp = Interpreter::with_output(std::io::sink());
+        match interp.eval(source) {
+            Err(Error::Runtime(err)) => err.message,
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("1 + 2 * 3"), "7");
+        assert_eq!(eval("2 ** 10 - 1"), "1023");
+        assert_eq!(eval("7 / 2"), "3.5");
+        assert_eq!(eval("- 3 abs"), "-3");
+        assert_eq!(eval("1 < 2 and 3 > 2"), "true");
+        assert_eq!(eval("false not or nil"), "true");
+        assert_eq!(eval(r#""foo" .. 1 + 2"#), "foo3");
+        assert_eq!(eval("list(1, 2) append(3)"), "list(1, 2, 3)");
+        assert_eq!(eval("nil isNil"), "true");
+    }
+
+    #[test]
+    fn test_slots() {
+        assert_eq!(eval("x := 1\nx = x + 1\nx"), "2");
+        assert_eq!(
+            eval("Foo := Object clone\nFoo bar := 42\nFoo clone bar"),
+            "42"
+        );
+        assert_eq!(eval("Foo := Object clone\nFoo hasSlot(\"clone\")"), "true");
+        assert_eq!(eval("Foo := Object clone\nFoo type"), "Foo");
+        assert_eq!(
+            eval("Foo := Object clone\nBar := Foo clone\nBar clone type"),
+            "Bar"
+        );
+        assert_eq!(
+            eval("Foo := Object clone\nfoo := Foo clone\nfoo type"),
+            "Foo"
+        );
+        assert_eq!(eval("Two := 2\nTwo type"), "Number");
+        assert_eq!(eval("Greeting := \"hi\"\nGreeting type"), "Sequence");
+        assert_eq!(eval("Items := list(1)\nItems type"), "List");
+        assert_eq!(eval("Run := method(1)\ngetSlot(\"Run\") type"), "Block");
+        assert_eq!(eval("Lobby Protos Core Number == Number"), "true");
+
+        let source = "hasSlot(\"slots_test_missing\") or getSlot(\"slots_test_missing\") != nil";
+        assert_eq!(eval(source), "false");
+        assert!(eval_err("updateSlot(\"slots_test_missing\", 1)").contains("not found"));
+        assert_eq!(SymbolId::get("slots_test_missing"), None);
+        assert_eq!(eval("a := Object clone\nb := Object clone\nb appendProto(a)\na appendProto(b)\na foo := 1\nb foo"), "1");
+    }
+
+    #[test]
+    fn test_methods() {
+        let source = r#"
+            Counter := Object clone
+            Counter count := 0
+            Counter increment := method(self count = count + 1; self)
+            c := Counter clone
+            c increment increment count
+        "#;
+        assert_eq!(eval(source), "2");
+        assert_eq!(
+            eval("f := method(n, if(n < 1, return 0); n + f(n - 1))\nf(4)"),
+            "10"
+        );
+        assert_eq!(
+            eval("x := 1\nb := block(y, x + y)\nx = 10\nb call(2)"),
+            "12"
+        );
+        assert_eq!(eval("i := 0\nwhile(i < 5, i = i + 1)\ni"), "5");
+        assert_eq!(
+            eval("i := 0\nloop(i = i + 1; if(i == 3, break(i * 2)))"),
+            "6"
+        );
+        assert_eq!(
+            eval("s := 0\nlist(1, 2, 3) foreach(n, if(n == 2, continue); s = s + n)\ns"),
+            "4"
+        );
+    }
+
+    #[test]
+    fn test_locals() {
+        // the callee doesn't see the locals of the caller
+        assert_eq!(
+            eval_err("f := method(secret := 42; g)\ng := method(secret)\nf"),
+            "Lobby does not respond to 'secret'"
+        );
+        assert_eq!(
+            eval("x := 1\nf := method(x := 2; g; x)\ng := method(x = 3)\nlist(f, x)"),
+            "list(2, 3)"
+        );
+        // a method found in the receiver runs with it as `self`
+        assert_eq!(
+            eval("Foo := Object clone\nFoo set := method(self val := 5)\nFoo run := method(set)\nFoo run\nFoo val"),
+            "5"
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            eval("counter := method(n := 0; block(n = n + 1))\nc := counter\nc call\nc call"),
+            "2"
+        );
+        assert_eq!(
+            eval("Foo := Object clone\nFoo n := 0\nFoo inc := method(n = n + 1)\nf := Foo clone\nf inc\nf inc\nlist(f n, Foo n)"),
+            "list(2, 0)"
+        );
+        let source = r#"
+            each := method(xs, b, xs foreach(x, b call(x)); "done")
+            find := method(xs, each(xs, block(x, if(x > 2, return x))); nil)
+            list(find(list(1, 3, 5)), find(list(1)))
+        "#;
+        assert_eq!(eval(source), "list(3, nil)");
+        assert_eq!(
+            eval("Foo := Object clone\nFoo make := method(block(self))\nFoo make call == Foo"),
+            "true"
+        );
+        assert_eq!(
+            eval("b := block(call sender)\nf := method(b call)\nf == Lobby"),
+            "false"
+        );
+        // the method of the block already returned, so it returns from the block
+        assert_eq!(
+            eval("mk := method(block(return 5))\nb := mk\ng := method(b call; 7)\nlist(g, b call)"),
+            "list(7, 5)"
+        );
+        assert_eq!(eval("block(return 3) call + 1"), "4");
+        assert_eq!(eval("block(a, b, a + b) argumentNames"), "list(a, b)");
+        assert_eq!(eval("method(a, a + 1; a) code"), "method(a, a + 1; a)");
+        assert_eq!(
+            eval("Foo := Object clone\nFoo x := 5\nb := block(x)\nb setScope(Foo) call"),
+            "5"
+        );
+        assert_eq!(eval("method(1) scope"), "nil");
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(
+            eval("message(foo bar(1, 2); baz) code"),
+            "foo bar(1, 2); baz"
+        );
+        assert_eq!(eval("message(foo bar(1, 2)) next argAt(1) code"), "2");
+        assert_eq!(eval("message(a + b) next arguments size"), "1");
+        assert_eq!(
+            eval("m := message(a b)\nm setNext(message(c d))\nm code"),
+            "a c d"
+        );
+        assert_eq!(
+            eval("m := message(a)\nm setName(\"b\") setArguments(list(message(1)))\nm code"),
+            "b(1)"
+        );
+        assert_eq!(eval("x := 3\nmessage(x * 2) doInContext"), "6");
+        assert_eq!(
+            eval_err("message(a) setNext(1)"),
+            "argument 0 to method 'setNext' must be a Message, not a 'Number'"
+        );
+    }
+
+    #[test]
+    fn test_call() {
+        let source = r#"
+            unless := method(c, if(c, nil, call evalArgAt(1)))
+            n := 0
+            unless(true, n = 1)
+            unless(false, n = n + 2)
+            n
+        "#;
+        assert_eq!(eval(source), "2");
+        assert_eq!(
+            eval("f := method(call message name .. call argCount)\nf(a, b)"),
+            "f2"
+        );
+        assert_eq!(eval("f := method(call argAt(0) code)\nf(x y)"), "x y");
+        assert_eq!(
+            eval("f := method(call message next name)\nf asString"),
+            "asString"
+        );
+        assert_eq!(eval("f := method(call message == call message)\nf"), "true");
+        assert_eq!(eval("f := method(call sender)\nf == Lobby"), "true");
+        assert_eq!(
+            eval("Foo := Object clone\nFoo f := method(call target)\nFoo f == Foo"),
+            "true"
+        );
+        assert_eq!(
+            eval("f := method(call evalArgs)\ny := 2\nf(y, y + 1)"),
+            "list(2, 3)"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval_err("foo"), "Lobby does not respond to 'foo'");
+        assert_eq!(
+            eval_err("1 + \"a\""),
+            "argument 0 to method '+' must be a Number, not a 'Sequence'"
+        );
+        assert_eq!(
+            eval_err("x = 1"),
+            "slot 'x' not found, assign with ':=' to create it"
+        );
+        assert_eq!(
+            eval_err("list(1, 2) foreach(x, f := method(break); f; x)"),
+            "'break' outside of a loop"
+        );
+        assert_eq!(
+            eval_err("f := method(continue)\nloop(f)"),
+            "'continue' outside of a loop"
+        );
+        assert_eq!(eval_err("break(1)"), "'break' outside of a loop");
+        assert_eq!(eval("loop(block(break(2)) call)"), "2");
+
+        let mut interp = Interpreter::with_output(std::io::sink());
+        let err = interp.eval("1\n  2 bar").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: Number does not respond to 'bar'\n --> 2:5"
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut interp = Interpreter::with_output(std::io::sink());
+        interp.set_max_depth(100);
+        let Err(Error::Runtime(err)) = interp.eval("f := method(n, f(n + 1))\nf(0)") else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "stack overflow");
+        assert_eq!(err.trace.len(), 101);
+
+        let value = interp
+            .eval("g := method(n, if(n > 0, g(n - 1), 0))\nlist(try(f(0)) error, g(99))")
+            .unwrap();
+        assert_eq!(
+            &*interp.as_string(&value).unwrap(),
+            "list(stack overflow, 0)"
+        );
+        assert_eq!(interp.depth(), 0);
+    }
+
+    #[test]
+    fn test_exceptions() {
+        assert_eq!(eval("try(Exception raise(\"boom\")) error"), "boom");
+        assert_eq!(eval("try(1 + 1)"), "nil");
+        assert_eq!(eval("try(foo) error"), "Lobby does not respond to 'foo'");
+        let source = r#"
+            Custom := Exception clone
+            e := try(Custom raise("bad"))
+            list(e catch(Number, 1) == e, e catch(Custom, 2), e type)
+        "#;
+        assert_eq!(eval(source), "list(true, nil, Custom)");
+        assert_eq!(
+            eval("e := try(Exception raise(\"a\"))\ne catch(Exception, x, x error .. \"!\")\nx error"),
+            "a"
+        );
+        assert_eq!(
+            eval("log := list()\ntry(ensure(Exception raise(1), log append(2)))\nlog"),
+            "list(2)"
+        );
+        assert_eq!(eval("ensure(1, 2)"), "1");
+        assert_eq!(eval("try(try(Exception raise(1)) pass) error"), "1");
+        assert_eq!(eval("try(nil) catch(Exception, 1) pass"), "nil");
+    }
+
+    #[test]
+    fn test_stack_traces() {
+        let mut interp = Interpreter::with_output(std::io::sink());
+        let source = "f := method(Exception raise(\"x\"))\ng := method(f)\ng";
+        let Err(Error::Runtime(err)) = interp.eval(source) else {
+            panic!("expected an exception");
+        };
+        assert_eq!(err.message, "Exception: x");
+        assert_eq!(
+            err.report("main.io").to_string(),
+            "error: Exception: x\n    at raise (main.io:1:23)\n    at f (main.io:2:13)\n    at g (main.io:3:1)"
+        );
+        assert!(err.exception.is_some());
+
+        // a passed exception keeps the trace of where it was raised
+        let source = "e := try(g)\nh := method(e pass)\nh";
+        let Err(Error::Runtime(passed)) = interp.eval(source) else {
+            panic!("expected an exception");
+        };
+        assert_eq!(passed.trace.len(), err.trace.len() + 1);
+        assert_eq!(passed.trace[..2], err.trace[..2]);
+    }
+
+    #[test]
+    fn test_system() {
+        let mut interp = Interpreter::with_output(std::io::sink());
+        assert_eq!(eval("System args size"), "0");
+        interp.set_args(["main.io", "foo"]);
+        let args = interp.eval("System args").unwrap();
+        assert_eq!(&*interp.as_string(&args).unwrap(), "list(main.io, foo)");
+
+        assert!(matches!(
+            interp.eval("System exit(3)\n1"),
+            Err(Error::Exit(3))
+        ));
+        assert!(matches!(
+            interp.eval("f := method(list(1) foreach(n, System exit))\nf"),
+            Err(Error::Exit(0))
+        ));
+    }
+}
@@ -0,0 +1,25 @@
+//! Runtime for Io programming language.
+//!
+//! Io is a prototype-based language: there are no classes, new objects are made by cloning the
+//! existing ones and inherit from them. All the computation is done by sending messages, see
+//! [`Interpreter`] for running the code.
+
+#![warn(
+    clippy::all,
+    deprecated_in_future,
+    missing_docs,
+    unused_import_braces,
+    unused_labels,
+    unused_lifetimes,
+    unused_qualifications,
+    unreachable_pub
+)]
+
+mod builtins;
+mod error;
+mod interpreter;
+mod object;
+
+pub use error::{exit_status, CallSite, Error, ErrorReport, Report, RuntimeError};
+pub use interpreter::{Activation, EvalResult, Interpreter, Stop};
+pub use object::{Block, Call, Data, Locals, MessageData, Native, ObjectRef};
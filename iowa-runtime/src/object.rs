@@ -0,0 +1,413 @@
+//! The object model.
+//!
+//! Every value is an object with a map of slots and a list of protos. A message is answered by the
+//! first slot with its name found in a depth-first search starting at the receiver and going
+//! through the protos in order. Objects are reference counted; cycles, like the one between
+//! `Object` and `Lobby`, are never freed.
+//!
+//! Changing the slots or the protos of an object advances its [`version`](ObjectRef::version), so
+//! the result of a lookup can be cached until one of the objects it looked into changes.
+
+use std::any::Any;
+use std::cell::{Cell, OnceCell, Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use iowa_parser::{Message, MessageChain, Span, Symbol, SymbolId};
+
+use crate::error::RuntimeError;
+use crate::interpreter::NativeFn;
+
+/// A reference to an object.
+///
+/// Comparing the references with [`ptr_eq`](Self::ptr_eq) compares the identities of the objects.
+#[derive(Clone)]
+pub struct ObjectRef(Rc<Object>);
+
+struct Object {
+    slots: RefCell<HashMap<SymbolId, ObjectRef>>,
+    protos: RefCell<Vec<ObjectRef>>,
+    data: RefCell<Data>,
+    /// The number of the changes of the slots and the protos.
+    version: Cell<u64>,
+}
+
+/// The primitive value of an object.
+#[derive(Default, Clone)]
+pub enum Data {
+    /// A plain object.
+    #[default]
+    None,
+    /// A number.
+    Number(f64),
+    /// A string.
+    Sequence(Rc<str>),
+    /// A list of objects.
+    List(Vec<ObjectRef>),
+    /// A method or a block.
+    Block(Rc<Block>),
+    /// A method implemented by the runtime.
+    Native(Native),
+    /// A message of the code.
+    Message(MessageData),
+    /// The activation of a method.
+    Call(Rc<Call>),
+    /// The locals of an activation.
+    Locals(Locals),
+    /// An `Exception` which was raised, with the error it carried.
+    Exception(Rc<RuntimeError>),
+}
+
+/// The code of a method or a block.
+pub struct Block {
+    /// The names of the arguments.
+    pub params: Vec<SymbolId>,
+    /// The body.
+    pub body: Vec<MessageChain<'static>>,
+    /// The context of a block, methods don't have one and run in the context of the receiver.
+    pub scope: Option<ObjectRef>,
+    /// The body compiled by an engine other than the interpreter, which keeps its own type here.
+    pub compiled: OnceCell<Rc<dyn Any>>,
+    /// The number of the calls an engine counted, to find the hot methods.
+    pub calls: Cell<u32>,
+    /// Whether the body refers to `call`, which is only made for the activations which can use it.
+    uses_call: bool,
+}
+
+impl Block {
+    /// Create a method, or a block if it has a scope.
+    pub fn new(
+        params: Vec<SymbolId>,
+        body: Vec<MessageChain<'static>>,
+        scope: Option<ObjectRef>,
+    ) -> Self {
+        let uses_call = mentions(&body, SymbolId::intern("call"));
+        Self {
+            params,
+            body,
+            scope,
+            compiled: OnceCell::new(),
+            calls: Cell::new(0),
+            uses_call,
+        }
+    }
+
+    /// Whether the body refers to `call`.
+    pub fn uses_call(&self) -> bool {
+        self.uses_call
+    }
+}
+
+/// Check if a message of the chains or of their arguments has the name.
+fn mentions(chains: &[MessageChain<'_>], name: SymbolId) -> bool {
+    chains
+        .iter()
+        .flat_map(|chain| chain.iter())
+        .any(|msg| msg.symbol.id() == Some(name) || msg.args.iter().any(|arg| mentions(arg, name)))
+}
+
+/// The state of the locals of an activation.
+#[derive(Debug, Clone)]
+pub struct Locals {
+    /// The scope of a block, `None` for a method.
+    pub scope: Option<ObjectRef>,
+    /// Whether the activation is still running, so `return` can leave it.
+    pub running: bool,
+}
+
+/// A message of the code as an object, linked to the next one in the chain.
+///
+/// The chains of an argument are separated by `;` messages.
+#[derive(Debug, Clone)]
+pub struct MessageData {
+    /// The name, or the literal.
+    pub symbol: Symbol<'static>,
+    /// The first messages of the arguments, `nil` for an empty one.
+    pub args: Vec<ObjectRef>,
+    /// The message sent to the result of this one.
+    pub next: Option<ObjectRef>,
+    /// The location in the source.
+    pub span: Span,
+}
+
+/// The activation of a method or a block, which its body gets as `call`.
+#[derive(Debug)]
+pub struct Call {
+    /// The locals of the sender, where the arguments are evaluated.
+    pub sender: ObjectRef,
+    /// The receiver of the message, or the `self` of the scope for a block.
+    pub target: ObjectRef,
+    /// The message which activated the method.
+    pub msg: Message<'static>,
+    /// The messages following it in the chain.
+    pub next: Vec<Message<'static>>,
+    /// The `Message` object of `call message`, made the first time it's asked for.
+    pub message: OnceCell<ObjectRef>,
+}
+
+/// A method implemented by the runtime.
+#[derive(Clone, Copy)]
+pub struct Native {
+    pub(crate) name: &'static str,
+    pub(crate) func: NativeFn,
+}
+
+impl ObjectRef {
+    /// Create an object with the protos.
+    pub fn new(protos: Vec<ObjectRef>, data: Data) -> Self {
+        Self(Rc::new(Object {
+            slots: RefCell::default(),
+            protos: RefCell::new(protos),
+            data: RefCell::new(data),
+            version: Cell::new(0),
+        }))
+    }
+
+    /// Create an object which inherits from this one and has the same primitive value.
+    pub fn clone_object(&self) -> Self {
+        Self::new(vec![self.clone()], self.data().clone())
+    }
+
+    /// Check if both references point to the same object.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// The address of the object, which identifies it.
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+
+    /// The value of the slot of this object, without looking into the protos.
+    pub fn local_slot(&self, name: SymbolId) -> Option<ObjectRef> {
+        self.0.slots.borrow().get(&name).cloned()
+    }
+
+    /// Set the slot of this object.
+    pub fn set_slot(&self, name: impl Into<SymbolId>, value: ObjectRef) {
+        self.0.slots.borrow_mut().insert(name.into(), value);
+        self.advance_version();
+    }
+
+    /// The version of the slots and the protos of this object, which changes with them.
+    pub fn version(&self) -> u64 {
+        self.0.version.get()
+    }
+
+    fn advance_version(&self) {
+        self.0.version.set(self.0.version.get() + 1);
+    }
+
+    /// The slots of this object.
+    pub fn slots(&self) -> Ref<'_, HashMap<SymbolId, ObjectRef>> {
+        self.0.slots.borrow()
+    }
+
+    /// Find the slot in this object or its protos.
+    ///
+    /// Returns the value along with the object which has the slot. Each object is visited once,
+    /// so cycles in the protos are fine.
+    pub fn lookup(&self, name: SymbolId) -> Option<(ObjectRef, ObjectRef)> {
+        self.lookup_visiting(name, |_| {})
+    }
+
+    /// Find the slot like [`lookup`](Self::lookup), calling the function with each object looked
+    /// into. The result is the same while none of them changes its [`version`](Self::version).
+    pub fn lookup_visiting(
+        &self,
+        name: SymbolId,
+        mut visit: impl FnMut(&ObjectRef),
+    ) -> Option<(ObjectRef, ObjectRef)> {
+        let mut visited = Vec::new();
+        let mut stack = vec![self.clone()];
+
+        while let Some(obj) = stack.pop() {
+            if visited.contains(&obj.id()) {
+                continue;
+            }
+            visited.push(obj.id());
+            visit(&obj);
+
+            if let Some(value) = obj.local_slot(name) {
+                return Some((value, obj));
+            }
+            stack.extend(obj.protos().iter().rev().cloned());
+        }
+
+        None
+    }
+
+    /// The protos of this object.
+    pub fn protos(&self) -> Ref<'_, Vec<ObjectRef>> {
+        self.0.protos.borrow()
+    }
+
+    /// Add the proto after the others.
+    pub fn append_proto(&self, proto: ObjectRef) {
+        self.0.protos.borrow_mut().push(proto);
+        self.advance_version();
+    }
+
+    /// Replace the protos.
+    pub fn set_protos(&self, protos: Vec<ObjectRef>) {
+        *self.0.protos.borrow_mut() = protos;
+        self.advance_version();
+    }
+
+    /// The primitive value.
+    pub fn data(&self) -> Ref<'_, Data> {
+        self.0.data.borrow()
+    }
+
+    /// The primitive value for changing.
+    pub fn data_mut(&self) -> RefMut<'_, Data> {
+        self.0.data.borrow_mut()
+    }
+
+    /// The number, if this object is one.
+    pub fn as_number(&self) -> Option<f64> {
+        match *self.data() {
+            Data::Number(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    /// The string, if this object is a sequence.
+    pub fn as_str(&self) -> Option<Rc<str>> {
+        match &*self.data() {
+            Data::Sequence(seq) => Some(seq.clone()),
+            _ => None,
+        }
+    }
+
+    /// The method or the block, if this object is one.
+    pub fn as_block(&self) -> Option<Rc<Block>> {
+        match &*self.data() {
+            Data::Block(block) => Some(block.clone()),
+            _ => None,
+        }
+    }
+
+    /// The locals of the activation which `return` leaves when it's run with these locals.
+    ///
+    /// It's the method the blocks were made in, following their scopes, if it's still running.
+    /// Otherwise it's the innermost block, and `None` outside of the activations.
+    pub(crate) fn home(&self) -> Option<ObjectRef> {
+        let mut locals = self.clone();
+        loop {
+            let scope = match &*locals.data() {
+                Data::Locals(Locals {
+                    scope: None,
+                    running: true,
+                }) => return Some(locals.clone()),
+                Data::Locals(Locals {
+                    scope: Some(scope), ..
+                }) => scope.clone(),
+                _ => break,
+            };
+            locals = scope;
+        }
+        matches!(&*self.data(), Data::Locals(_)).then(|| self.clone())
+    }
+
+    /// Mark the activation with these locals as ended, `return` can't leave it any more.
+    pub fn end_activation(&self) {
+        if let Data::Locals(locals) = &mut *self.data_mut() {
+            locals.running = false;
+        }
+    }
+
+    /// The message, if this object is one.
+    pub fn as_message(&self) -> Option<MessageData> {
+        match &*self.data() {
+            Data::Message(msg) => Some(msg.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.data() {
+            Data::None => write!(f, "Object_{:#x}", self.id()),
+            Data::Number(num) => write!(f, "{num}"),
+            Data::Sequence(seq) => write!(f, "{seq:?}"),
+            Data::List(items) => f.debug_list().entries(items).finish(),
+            Data::Block(block) => write!(f, "{block:?}"),
+            Data::Native(native) => write!(f, "{native:?}"),
+            Data::Message(msg) => write!(f, "Message({})", msg.symbol),
+            Data::Call(call) => write!(f, "Call({})", call.msg),
+            Data::Locals(_) => write!(f, "Locals_{:#x}", self.id()),
+            Data::Exception(err) => write!(f, "Exception({})", err.message),
+        }
+    }
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let name = SymbolId::intern("foo");
+        let base = ObjectRef::new(Vec::new(), Data::None);
+        let left = base.clone_object();
+        let right = base.clone_object();
+        let obj = ObjectRef::new(vec![left.clone(), right.clone()], Data::None);
+
+        assert!(obj.lookup(name).is_none());
+        base.set_slot(name, ObjectRef::new(Vec::new(), Data::Number(1.0)));
+        right.set_slot(name, ObjectRef::new(Vec::new(), Data::Number(2.0)));
+        // depth-first: the proto of `left` comes before `right`
+        let (value, context) = obj.lookup(name).unwrap();
+        assert_eq!(value.as_number(), Some(1.0));
+        assert!(context.ptr_eq(&base));
+
+        // a cycle in the protos
+        base.append_proto(obj.clone());
+        assert!(obj.lookup(SymbolId::intern("bar")).is_none());
+    }
+
+    #[test]
+    fn test_version() {
+        let base = ObjectRef::new(Vec::new(), Data::None);
+        let proto = base.clone_object();
+        let obj = proto.clone_object();
+        let name = SymbolId::intern("foo");
+        base.set_slot(name, obj.clone());
+
+        let mut visited = Vec::new();
+        let (_, holder) = obj
+            .lookup_visiting(name, |o| visited.push(o.clone()))
+            .unwrap();
+        assert!(holder.ptr_eq(&base));
+        assert_eq!(visited.len(), 3);
+        assert!(visited[1].ptr_eq(&proto));
+
+        let version = proto.version();
+        proto.set_slot("bar", obj.clone());
+        assert_eq!(proto.version(), version + 1);
+        proto.set_protos(Vec::new());
+        assert_eq!(proto.version(), version + 2);
+        proto.append_proto(base.clone());
+        assert_eq!(proto.version(), version + 3);
+        assert_eq!(base.version(), 1);
+    }
+}
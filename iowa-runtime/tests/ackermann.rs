@@ -0,0 +1,44 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use iowa_runtime::Interpreter;
+
+/// An output which can be read after it's given to the interpreter.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ackermann() {
+    let input = r#"
+    #!/usr/bin/env io
+
+    ack := method(m, n,
+      //writeln("ack(", m, ",", n, ")")
+      if (m < 1, return n + 1)
+      if (n < 1, return ack(m - 1, 1))
+      return ack(m - 1, ack(m, n - 1))
+    )
+
+    ack(3, 4) print
+    "\n" print
+    "#;
+
+    let output = Output::default();
+    let mut interp = Interpreter::with_output(output.clone());
+    interp.eval(input).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "125\n");
+
+    let res = interp.eval("ack(3, 4)").unwrap();
+    assert_eq!(res.as_number(), Some(125.0));
+}